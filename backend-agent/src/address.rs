@@ -0,0 +1,110 @@
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Metadata, Schema, SchemaObject, StringValidation};
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use starknet::{core::types::Felt, macros::felt};
+use std::fmt;
+use std::str::FromStr;
+
+/// Contract addresses are below 2^251 - 256, Starknet's `ADDR_BOUND`
+const ADDRESS_BOUND: Felt = felt!("0x7ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff00");
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AddressError {
+    #[error("Invalid contract address {0:?}")]
+    Parse(String),
+    #[error("Contract address {0} is out of range")]
+    OutOfRange(String),
+}
+
+/// Starknet contract address, range-checked. Written in canonical form, `0x` and 64 lowercase hex
+/// digits, so the same contract compares, hashes and prints the same whatever form it was typed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ContractAddress(Felt);
+
+impl ContractAddress {
+    pub fn new(felt: Felt) -> Result<Self, AddressError> {
+        if felt >= ADDRESS_BOUND {
+            return Err(AddressError::OutOfRange(felt.to_hex_string()));
+        }
+        Ok(Self(felt))
+    }
+
+    pub const fn felt(&self) -> Felt {
+        self.0
+    }
+}
+
+/// Hex, with or without `0x`, any case and any number of leading zeros
+impl FromStr for ContractAddress {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let digits = trimmed
+            .strip_prefix("0x")
+            .or_else(|| trimmed.strip_prefix("0X"))
+            .unwrap_or(trimmed);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(AddressError::Parse(s.to_string()));
+        }
+        // past 64 significant digits it can't be a felt
+        let felt = Felt::from_hex(&format!("0x0{}", digits.trim_start_matches('0')))
+            .map_err(|_| AddressError::OutOfRange(s.to_string()))?;
+        Self::new(felt)
+    }
+}
+
+impl TryFrom<Felt> for ContractAddress {
+    type Error = AddressError;
+
+    fn try_from(felt: Felt) -> Result<Self, Self::Error> {
+        Self::new(felt)
+    }
+}
+
+impl From<ContractAddress> for Felt {
+    fn from(address: ContractAddress) -> Self {
+        address.0
+    }
+}
+
+impl fmt::Display for ContractAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.to_fixed_hex_string())
+    }
+}
+
+impl Serialize for ContractAddress {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ContractAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+impl JsonSchema for ContractAddress {
+    fn schema_name() -> String {
+        "ContractAddress".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some("Starknet contract address in hex".to_string()),
+                ..Default::default()
+            })),
+            string: Some(Box::new(StringValidation {
+                pattern: Some("^(0x)?[0-9a-fA-F]{1,64}$".to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
@@ -1,2 +1,3 @@
+pub mod performance;
 pub mod portfolio;
 pub mod yield_analyzer;
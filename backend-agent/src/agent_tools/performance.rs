@@ -0,0 +1,101 @@
+use crate::agents::turn;
+use crate::backend::AppState;
+use crate::history::PortfolioChange;
+use crate::sources::{cite, Source};
+use crate::types::PortfolioError;
+use chrono::{Duration, Utc};
+use rig::{
+    completion::{CompletionModel, ToolDefinition},
+    tool::Tool,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Period looked back when the model doesn't give one
+pub const DEFAULT_DAYS: u32 = 7;
+
+/// How the user's portfolio changed since an earlier fetch
+#[derive(Clone)]
+pub struct PortfolioPerformance<M: CompletionModel> {
+    pub appstate: Arc<Mutex<AppState<M>>>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PerformanceArgs {
+    /// Days to look back
+    days: Option<u32>,
+}
+
+impl<M: CompletionModel + 'static> Tool for PortfolioPerformance<M> {
+    const NAME: &'static str = "portfolio_performance";
+    type Args = PerformanceArgs;
+    type Output = String;
+    type Error = PortfolioError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "How the user's portfolio did over a period: value change, profit and loss per token, and the yield actually earned versus the one you predicted. Uses the portfolio snapshots of the wallet the user shared.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "days": {
+                        "type": "integer",
+                        "description": "How many days to look back, 7 by default"
+                    }
+                },
+                "required": []
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let context = turn::tool_context().ok_or_else(|| PortfolioError("Portfolio performance outside of a turn".to_string()))?;
+        let wallet = context
+            .wallet
+            .ok_or_else(|| PortfolioError("No wallet address shared, ask the user for it".to_string()))?;
+        let days = args.days.unwrap_or(DEFAULT_DAYS);
+        let history = self.appstate.lock().await.history.clone();
+        let change = history
+            .change(wallet, Utc::now() - Duration::days(days.into()))
+            .ok_or_else(|| PortfolioError("Not enough portfolio snapshots yet, fetch the portfolio again later".to_string()))?;
+        let source = cite(Source::portfolio(wallet));
+        Ok(format!("[{source}] {}", format_change(&change)))
+    }
+}
+
+/// Value delta, then per-token PnL and predicted versus realized yields
+pub fn format_change(change: &PortfolioChange) -> String {
+    let usd = |value: Option<f64>| value.map_or("no price".to_string(), |value| format!("${value:+.2}"));
+    let mut content = format!(
+        "Portfolio of {} from {} to {}: ${:.2} -> ${:.2} ({}{})",
+        change.wallet,
+        change.from.format("%Y-%m-%d %H:%M UTC"),
+        change.to.format("%Y-%m-%d %H:%M UTC"),
+        change.value_from,
+        change.value_to,
+        usd(Some(change.delta)),
+        change.delta_percentage.map(|pct| format!(", {pct:+.2}%")).unwrap_or_default()
+    );
+    for token in &change.tokens {
+        content.push_str(&format!(
+            "\n{}: {} -> {} tokens, PnL {}",
+            token.symbol,
+            token.amount_from,
+            token.amount_to,
+            usd(token.pnl)
+        ));
+    }
+    for check in &change.yields {
+        content.push_str(&format!(
+            "\n{} on {} ({}): predicted {:.2}% APY, realized {}",
+            check.token,
+            check.protocol,
+            check.pool,
+            check.predicted_apy,
+            check.realized_apy.map_or("unknown".to_string(), |apy| format!("{apy:.2}% APY"))
+        ));
+    }
+    content
+}
@@ -3,37 +3,76 @@ use rig::{
     tool::Tool,
 };
 use serde_json::json;
+use serde::{Deserialize, Serialize};
 use starknet::{
-    core::types::{BlockId, BlockTag, Felt, FunctionCall},
+    core::types::{requests::CallRequest, BlockId, BlockTag, Felt, FunctionCall},
     macros::selector,
     providers::{
-        jsonrpc::{HttpTransport, JsonRpcClient},
-        Provider, Url,
+        jsonrpc::{HttpTransport, JsonRpcClient, JsonRpcResponse, JsonRpcTransport},
+        ProviderRequestData, Url,
     },
 };
+use crate::address::ContractAddress;
+use crate::agents::turn;
+use crate::amount::TokenAmount;
 use crate::backend::messaging::ChatHistoryCommand;
+use crate::positions::{PositionAdapters, PositionAmount, PositionFetch};
+use crate::prices::PriceFeed;
+use crate::sources::{cite, Source};
+use crate::starknet_id::StarknetId;
+use crate::token_registry::{TokenInfo, TokenRegistry};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::mpsc;
 use tokio::sync::Mutex;
-use tokio::task::spawn_blocking;
-use tracing::{error, info, warn};
+use tracing::{info, warn};
 
 use crate::{
     backend::AppState,
-    types::{PortfolioError, Price, Token},
-    utils::get_verified_tokens,
+    types::{display_wallet, AggregatedPortfolio, Portfolio, PortfolioError, Token},
 };
 
+/// Default mainnet RPC, override with `STARKNET_RPC_URL`
+pub const MAINNET_RPC_URL: &str = "https://starknet-mainnet.public.blastapi.io/rpc/v0_7";
+/// Node every Starknet call goes to, `STARKNET_RPC_URL` or the mainnet default
+pub fn rpc_url_from_env() -> Url {
+    let rpc_url = std::env::var("STARKNET_RPC_URL").unwrap_or_else(|_| MAINNET_RPC_URL.to_string());
+    Url::parse(&rpc_url).expect("Invalid STARKNET_RPC_URL")
+}
+
+/// `balanceOf` calls per JSON-RPC batch, public nodes cap batch sizes
+const BATCH_SIZE: usize = 50;
+
 #[derive(Clone)]
 pub struct PortfolioFetch<M: CompletionModel> {
     pub appstate: Arc<Mutex<AppState<M>>>,
+    pub rpc_url: Url,
+    pub prices: PriceFeed,
+    pub tokens: TokenRegistry,
+    pub positions: PositionAdapters,
+    pub starknet_id: StarknetId,
+}
+
+impl<M: CompletionModel> PortfolioFetch<M> {
+    pub fn new(appstate: Arc<Mutex<AppState<M>>>, prices: PriceFeed, tokens: TokenRegistry) -> Self {
+        let rpc_url = rpc_url_from_env();
+        Self {
+            appstate,
+            starknet_id: StarknetId::from_env(rpc_url.clone()),
+            rpc_url,
+            prices,
+            tokens,
+            positions: PositionAdapters::from_env().expect("Failed loading position adapters"),
+        }
+    }
 }
 
 #[derive(serde::Deserialize)]
 pub struct PortfolioArgs {
-    wallet_address: Felt,
-    session_id: String,
+    /// Address or Starknet ID domain, defaults to the wallet the user shared combined with the linked
+    /// ones, any other wallet is refused
+    wallet_address: Option<String>,
+    /// Tokens to check on top of the verified ones, resolved on-chain when unknown
+    token_addresses: Option<Vec<ContractAddress>>,
 }
 
 impl<M: CompletionModel + 'static> Tool for PortfolioFetch<M> {
@@ -45,109 +84,172 @@ impl<M: CompletionModel + 'static> Tool for PortfolioFetch<M> {
     async fn definition(&self, _prompt: String) -> ToolDefinition {
         ToolDefinition {
             name: Self::NAME.to_string(),
-            description: "Retrieve mainnet portfolio using the user input wallet address. REQUIRES user wallet address. <IMPORTANT>Only use if user has sent wallet address in its LAST message. If not, ask for it again or the world might fall apart... very important yes.<IMPORTANT/>".to_string(),
+            description: "Retrieve mainnet portfolio using the user input wallet address. REQUIRES user wallet address. Without wallet_address, the wallets the user linked are fetched too and combined. <IMPORTANT>Only use if user has sent wallet address in its LAST message. If not, ask for it again or the world might fall apart... very important yes.<IMPORTANT/>".to_string(),
             parameters: json!({
                 "type": "object",
                 "properties": {
                     "wallet_address": {
                         "type": "string",
-                        "description": "The Starknet wallet address to fetch the portfolio for"
+                        "description": "The Starknet wallet address or .stark domain the user shared, to look at only one of their wallets"
                     },
-                    "session_id": {
-                        "type": "string",
-                        "description": "User current session id. SAVED AS A 'SYSTEM' message in the beginning of the chat history THIS IS VERY IMPORTANT!"
+                    "token_addresses": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "Addresses of tokens the user asked about that may not be listed"
                     }
                 },
-                "required": ["wallet_address"]
+                "required": []
             })
         }
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        // session and wallets come from the server, the model only picks among what the user shared
+        let context = turn::tool_context().ok_or_else(|| PortfolioError("Portfolio fetch outside of a turn".to_string()))?;
+        let shared_wallet = context
+            .wallet
+            .ok_or_else(|| PortfolioError("No wallet address shared, ask the user for it".to_string()))?;
+        let linked: Vec<ContractAddress> = context
+            .linked_wallets
+            .into_iter()
+            .filter(|wallet| *wallet != shared_wallet)
+            .collect();
+        let requested = match args.wallet_address {
+            Some(input) => {
+                // spawned so the tool future stays Sync
+                let starknet_id = self.starknet_id.clone();
+                let wallet = tokio::task::spawn(async move { starknet_id.wallet(&input).await })
+                    .await
+                    .map_err(|e| PortfolioError(e.to_string()))?
+                    .map_err(|e| PortfolioError(e.to_string()))?;
+                Some(wallet)
+            }
+            None => None,
+        };
+        // one wallet when the model names it, else every wallet of the user combined
+        let wallets = match requested {
+            Some(wallet) if wallet == shared_wallet || linked.contains(&wallet) => vec![wallet],
+            Some(wallet) => {
+                return Err(PortfolioError(format!("Wallet {wallet} wasn't shared by the user")))
+            }
+            None => std::iter::once(shared_wallet).chain(linked).collect(),
+        };
         info!(
-            "Starting portfolio fetch for wallet: {}",
-            args.wallet_address.to_hex_string()
+            "Starting portfolio fetch for wallets: {} (request {})",
+            wallets.iter().map(|wallet| wallet.to_string()).collect::<Vec<_>>().join(", "),
+            context.request_id
         );
 
-        let mut token_balances: HashMap<Token, f64> = HashMap::new();
-        let (vec6, vec8, vec18) = get_verified_tokens();
-        let wallet_address = args.wallet_address;
-
-        // Create a single provider instance
-        info!("Creating provider...");
-
-        let provider = Arc::new(JsonRpcClient::new(HttpTransport::new(
-            Url::parse("https://starknet-mainnet.public.blastapi.io/rpc/v0_7").unwrap(),
-        )));
-
-        // Spawn each balance fetch in its own task to make it Sync
-        info!("Spawning balance fetch tasks...");
-
-        let balances6 = tokio::task::spawn({
-            let provider = provider.clone();
+        // spawned so the tool future stays Sync
+        let (fetches, mut errors, prices, queried) = tokio::task::spawn({
+            let rpc_url = self.rpc_url.clone();
+            let registry = self.tokens.clone();
+            let feed = self.prices.clone();
+            let adapters = self.positions.clone();
+            let starknet_id = self.starknet_id.clone();
+            let extra = args.token_addresses.unwrap_or_default();
             async move {
-                info!("Starting 6 decimals fetch");
-
-                let res = fetch_balances_with_provider(provider, 6.0, wallet_address, vec6).await;
-                info!("Completed 6 decimals fetch");
-                res
+                let mut tokens = registry.verified();
+                let mut unresolved = vec![];
+                let provider = JsonRpcClient::new(HttpTransport::new(rpc_url.clone()));
+                for address in extra {
+                    if tokens.iter().any(|token| token.address == address) {
+                        continue;
+                    }
+                    match registry.resolve(&provider, address).await {
+                        Ok(token) => tokens.push(token),
+                        Err(e) => unresolved.push(BalanceError {
+                            token: address.to_string(),
+                            error: e.to_string(),
+                        }),
+                    }
+                }
+                let mut fetches = vec![];
+                for wallet in wallets {
+                    let fetch = fetch_balances(&HttpTransport::new(rpc_url.clone()), wallet, &tokens).await;
+                    let positions = adapters.fetch(&provider, &registry, wallet).await;
+                    let domain = starknet_id.reverse(wallet).await.unwrap_or_else(|e| {
+                        warn!("Failed reverse resolving {}: {}", wallet, e);
+                        None
+                    });
+                    fetches.push((wallet, domain, fetch, positions));
+                }
+                let held = fetches
+                    .iter()
+                    .flat_map(|(_, _, fetch, positions)| {
+                        fetch.balances.keys().map(|token| token.address).chain(
+                            positions.positions.iter().flat_map(|position| {
+                                position.underlying.iter().chain(&position.rewards).map(|part| part.token)
+                            }),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let prices = feed.prices(&held).await;
+                (fetches, unresolved, prices, tokens.len())
             }
-        });
-
-        let balances8 = tokio::task::spawn({
-            let provider = provider.clone();
-            async move {
-                info!("Starting 8 decimals fetch");
+        })
+        .await
+        .map_err(|e| PortfolioError(e.to_string()))?;
 
-                let res = fetch_balances_with_provider(provider, 8.0, wallet_address, vec8).await;
-                info!("Completed 8 decimals fetch");
-                res
+        let mut portfolios = vec![];
+        let mut holdings = vec![];
+        let mut failed_wallets = vec![];
+        for (wallet, domain, BalanceFetch { balances, errors: balance_errors }, positions) in fetches {
+            // a partial portfolio is still useful, none at all isn't
+            if balances.is_empty() && balance_errors.len() >= queried {
+                let error = balance_errors
+                    .first()
+                    .map_or_else(|| "no tokens queried".to_string(), |error| error.error.clone());
+                failed_wallets.push((wallet.to_string(), error));
+                continue;
             }
-        });
-
-        let balances18 = tokio::task::spawn({
-            let provider = provider.clone();
-            async move {
-                info!("Starting 18 decimals fetch");
-                let res = fetch_balances_with_provider(provider, 18.0, wallet_address, vec18).await;
-                info!("Completed 18 decimals fetch");
-                res
+            for (token, amount) in &balances {
+                if !amount.is_zero() && !holdings.contains(&token.name) {
+                    holdings.push(token.name.clone());
+                }
             }
-        });
-
-        // Await all tasks and handle errors
-        info!("Awaiting balance results...");
+            let PositionFetch { positions, errors: position_errors } = positions;
+            errors.extend(balance_errors);
+            errors.extend(position_errors);
+            let mut portfolio = Portfolio::from_balances(wallet, &balances, &prices, &self.tokens)
+                .with_positions(positions, &prices, &self.tokens);
+            portfolio.domain = domain;
+            portfolios.push(portfolio);
+        }
+        let Some(aggregated) = AggregatedPortfolio::new(&portfolios) else {
+            return Err(PortfolioError(format!("Failed fetching balances: {}", failed_wallets[0].1)));
+        };
 
-        token_balances.extend(
-            balances6
-                .await
-                .map_err(|e| PortfolioError(e.to_string()))??,
-        );
-        token_balances.extend(
-            balances8
-                .await
-                .map_err(|e| PortfolioError(e.to_string()))??,
-        );
-        token_balances.extend(
-            balances18
-                .await
-                .map_err(|e| PortfolioError(e.to_string()))??,
-        );
-        info!(" balance result suxess");
+        turn::record(|metadata| {
+            metadata.holdings = holdings;
+            metadata.balance_errors = errors.clone();
+        });
+        let sources = portfolios
+            .iter()
+            .map(|portfolio| format!("[{}]", cite(Source::portfolio(portfolio.wallet_address))))
+            .collect::<Vec<_>>()
+            .join(" ");
 
         // Format content for chat history
         info!("Formatting content...");
 
-        let content = format!(
-            "User wallet {} portfolio balances:\n{}",
-            wallet_address.to_hex_string(),
-            token_balances
-                .iter()
-                .map(|(token, amount)| format!("{}: {:.6} tokens", token.name, amount))
-                .collect::<Vec<_>>()
-                .join("\n")
-        );
-        info!("Formatted content: {}", content); // Add this line to verify the content
+        let mut content = match portfolios.as_slice() {
+            [portfolio] => format!("{sources} {}", format_portfolio(portfolio)),
+            _ => format!("{sources} {}", format_aggregated(&aggregated)),
+        };
+        if !errors.is_empty() {
+            content.push_str(&format!(
+                "\nUnavailable balances, the portfolio may be incomplete: {}",
+                errors.iter().map(|error| error.token.as_str()).collect::<Vec<_>>().join(", ")
+            ));
+        }
+        if !failed_wallets.is_empty() {
+            content.push_str(&format!(
+                "\nUnavailable wallets, left out of the total: {}",
+                failed_wallets.iter().map(|(wallet, _)| wallet.as_str()).collect::<Vec<_>>().join(", ")
+            ));
+        }
+        info!("Formatted content: {}", content);
         info!("Updating state...");
     
         // Get the chat history sender from navigator
@@ -160,10 +262,10 @@ impl<M: CompletionModel + 'static> Tool for PortfolioFetch<M> {
         
     
         // Send message to update chat history
-        info!("Attempting to update chat history..."); //// deadlock here
+        info!("Attempting to update chat history...");
         chat_sender
             .send(ChatHistoryCommand::AddMessage(
-                args.session_id.clone(),
+                context.session_id.clone(),
                 Message {
                 role: "user".to_string(),
                 content: format!(
@@ -174,11 +276,9 @@ impl<M: CompletionModel + 'static> Tool for PortfolioFetch<M> {
             .map_err(|e| PortfolioError(e.to_string()))?;
         info!("Chat history update message sent");
         let content_2 = format!(
-            "I've recorded your portfolio data. Your largest holding is {} tokens. I'll use this information for any strategy advice.",
-            token_balances.iter()
-                .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
-                .map(|(token, amount)| format!("{} {}", amount, token.name))
-                .unwrap_or_default());
+            "{sources} I've recorded your portfolio data. Your largest holding is {}. I'll use this information for any strategy advice.",
+            largest_holding(&aggregated.combined)
+        );
         
 
   
@@ -186,25 +286,24 @@ impl<M: CompletionModel + 'static> Tool for PortfolioFetch<M> {
 
         chat_sender
     .send(ChatHistoryCommand::AddMessage(
-            args.session_id, ///// deadlock in here!!!!
+            context.session_id,
         Message {
         role: "assistant".to_string(),
         content: format!(
-            "I've recorded your portfolio data. Your largest holding is {} tokens. And your whole portfolio is \n{}\nI'll use this information for any strategy advice.",
-            token_balances.iter()
-                .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
-                .map(|(token, amount)| format!("{} {}", amount, token.name))
-                .unwrap_or_default()
+            "I've recorded your portfolio data. Your largest holding is {}. And your whole portfolio is \n{}\nI'll use this information for any strategy advice.",
+            largest_holding(&aggregated.combined)
             , content
         ),
     }))
     .await.map_err(|e| PortfolioError(e.to_string()))?;
     info!("sccessfully sent");
-        // Update portfolio
+        // Update portfolios, each wallet on its own
         {
             info!("updating portfolio");
             let state = self.appstate.lock().await;
-            state.update_portfolio(wallet_address.to_hex_string(), token_balances);
+            for portfolio in portfolios {
+                state.update_portfolio(portfolio.wallet_address, portfolio);
+            }
             info!("appstate lock dropped");
         }
         info!("Portfolio fetch completed successfully");
@@ -213,54 +312,166 @@ impl<M: CompletionModel + 'static> Tool for PortfolioFetch<M> {
     }
 }
 
-async fn fetch_balances_with_provider(
-    provider: Arc<JsonRpcClient<HttpTransport>>,
-    decimals: f64,
-    wallet_address: Felt,
-    tokens: Vec<(Felt, String)>,
-) -> Result<HashMap<Token, f64>, PortfolioError> {
-    let mut balances = HashMap::new();
-    info!(
-        "Starting fetch_balances_with_provider for {} decimals",
-        decimals
-    );
-    for (token_address, token_name) in tokens {
-        info!("Fetching balance for token: {}", token_name);
+/// `<amount> <symbol>` of the largest holding by USD value, worth included when priced
+fn largest_holding(portfolio: &Portfolio) -> String {
+    portfolio
+        .assets
+        .first()
+        .map(|asset| match asset.balance {
+            Some(value) => format!("{} {} (${:.2})", asset.amount, asset.symbol, value),
+            None => format!("{} {}", asset.amount, asset.symbol),
+        })
+        .unwrap_or_default()
+}
 
-        let call_result = provider
-            .call(
-                FunctionCall {
-                    contract_address: token_address,
-                    entry_point_selector: selector!("balanceOf"),
-                    calldata: vec![wallet_address],
-                },
-                BlockId::Tag(BlockTag::Latest),
+/// Holdings with their USD value and allocation, then the category split
+pub fn format_portfolio(portfolio: &Portfolio) -> String {
+    format!(
+        "User wallet {} portfolio balances, total value ${:.2}:\n{}",
+        portfolio.wallet_label(),
+        portfolio.total_value,
+        format_holdings(portfolio)
+    )
+}
+
+/// Value of each wallet, then the combined holdings as in [`format_portfolio`]
+pub fn format_aggregated(aggregated: &AggregatedPortfolio) -> String {
+    let wallets = aggregated
+        .wallets
+        .iter()
+        .map(|wallet| {
+            format!(
+                "{} ${:.2} ({:.1}%)",
+                display_wallet(wallet.wallet_address, wallet.domain.as_deref()),
+                wallet.value,
+                wallet.allocation
             )
-            .await
-            .map_err(|e| PortfolioError(e.to_string()))?;
+        });
+    format!(
+        "User wallets combined portfolio balances, total value ${:.2}:\nPer wallet: {}\n{}",
+        aggregated.combined.total_value,
+        wallets.collect::<Vec<_>>().join(", "),
+        format_holdings(&aggregated.combined)
+    )
+}
+
+fn format_holdings(portfolio: &Portfolio) -> String {
+    let assets = portfolio.assets.iter().map(|asset| match (asset.balance, asset.allocation) {
+        (Some(value), Some(allocation)) => format!(
+            "{}: {} tokens, ${:.2} ({:.1}%, {:?})",
+            asset.symbol, asset.amount, value, allocation, asset.category
+        ),
+        _ => format!("{}: {} tokens, no price ({:?})", asset.symbol, asset.amount, asset.category),
+    });
+    let amounts = |parts: &[PositionAmount]| {
+        parts
+            .iter()
+            .map(|part| format!("{} {}", part.amount, part.symbol))
+            .collect::<Vec<_>>()
+            .join(" + ")
+    };
+    let positions = portfolio.positions.iter().map(|holding| {
+        let mut line = format!("{}: {}", holding.position.label(), amounts(&holding.position.underlying));
+        if !holding.position.rewards.is_empty() {
+            line.push_str(&format!(", unclaimed {}", amounts(&holding.position.rewards)));
+        }
+        match (holding.value, holding.allocation) {
+            (Some(value), Some(allocation)) => line.push_str(&format!(", ${:.2} ({:.1}%)", value, allocation)),
+            _ => line.push_str(", no price"),
+        }
+        line
+    });
+    let categories = portfolio
+        .categories
+        .iter()
+        .map(|(category, split)| format!("{:?} {:.1}%", category, split.allocation));
+    let mut content = assets.collect::<Vec<_>>().join("\n");
+    if !portfolio.positions.is_empty() {
+        content.push_str(&format!("\nDeFi positions:\n{}", positions.collect::<Vec<_>>().join("\n")));
+    }
+    content.push_str(&format!("\nAllocation: {}", categories.collect::<Vec<_>>().join(", ")));
+    content
+}
 
-        if !call_result.is_empty() && call_result[0] > Felt::ZERO {
-            let balance: u128 = call_result[0]
-                .try_into()
-                .expect("Failed converting Felt to u128");
-            let adjusted_balance: f64 = balance as f64 / 10_f64.powf(decimals);
-            info!(
-                "Found non-zero balance for {}: {} tokens",
-                token_name, adjusted_balance
-            );
-            let token_info = Token {
-                name: token_name,
-                address: crate::types::StringContractAddress(token_address.to_hex_string()),
-                price: Price::default(),
-            };
-            balances.insert(token_info, adjusted_balance);
+/// A token whose balance couldn't be read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceError {
+    pub token: String,
+    pub error: String,
+}
+
+/// Non-zero balances, and the tokens that failed
+#[derive(Debug, Default)]
+pub struct BalanceFetch {
+    pub balances: HashMap<Token, TokenAmount>,
+    pub errors: Vec<BalanceError>,
+}
+
+/// `balanceOf` of every token in JSON-RPC batches of [`BATCH_SIZE`], one round-trip for the registry.
+/// A call failing only fails its token, a batch failing fails the tokens it holds.
+pub async fn fetch_balances<T: JsonRpcTransport>(
+    transport: &T,
+    wallet_address: ContractAddress,
+    tokens: &[TokenInfo],
+) -> BalanceFetch {
+    let mut fetch = BalanceFetch::default();
+    for batch in tokens.chunks(BATCH_SIZE) {
+        let requests: Vec<ProviderRequestData> = batch
+            .iter()
+            .map(|token| {
+                ProviderRequestData::Call(CallRequest {
+                    request: FunctionCall {
+                        contract_address: token.address.felt(),
+                        entry_point_selector: selector!("balanceOf"),
+                        calldata: vec![wallet_address.felt()],
+                    },
+                    block_id: BlockId::Tag(BlockTag::Latest),
+                })
+            })
+            .collect();
+        info!("Fetching {} balances in one batch", requests.len());
+
+        let mut results: HashMap<usize, Result<Vec<Felt>, String>> = match transport.send_requests(requests).await {
+            Ok(responses) => responses
+                .into_iter()
+                .map(|response| match response {
+                    JsonRpcResponse::Success { id, result } => {
+                        (id as usize, serde_json::from_value(result).map_err(|e| e.to_string()))
+                    }
+                    JsonRpcResponse::Error { id, error } => (id as usize, Err(error.message)),
+                })
+                .collect(),
+            Err(e) => {
+                warn!("Balance batch failed: {}", e);
+                (0..batch.len()).map(|i| (i, Err(e.to_string()))).collect()
+            }
+        };
+
+        for (i, token) in batch.iter().enumerate() {
+            let result = results.remove(&i).unwrap_or_else(|| Err("No response".to_string()));
+            let amount = result.and_then(|call_result| {
+                TokenAmount::from_u256_felts(&call_result, token.decimals).map_err(|e| e.to_string())
+            });
+            match amount {
+                Ok(amount) if !amount.is_zero() => {
+                    info!("Found non-zero balance for {}: {} tokens", token.symbol, amount);
+                    fetch.balances.insert(token.token(), amount);
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    warn!("Balance of {} unavailable: {}", token.symbol, error);
+                    fetch.errors.push(BalanceError {
+                        token: token.symbol.clone(),
+                        error,
+                    });
+                }
+            }
         }
     }
     info!(
-        "Completed fetch_balances_with_provider for {} decimals with {} tokens found",
-        decimals,
-        balances.len()
+        "Fetched balances: {} non-zero, {} failed",
+        fetch.balances.len(),
+        fetch.errors.len()
     );
-
-    Ok(balances)
+    fetch
 }
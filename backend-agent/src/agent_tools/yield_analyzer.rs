@@ -1,20 +1,27 @@
+use crate::prices::PriceFeed;
+use crate::sources::{cite, Source};
+use crate::token_registry::TokenRegistry;
 use crate::tokens::fetch_all_tokens;
 use crate::types::{ProtocolYield, YieldAnalyzer};
 use anyhow::Error;
+use chrono::{DateTime, Utc};
 use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use serde::Serialize;
 use serde_json::json;
+use starknet::providers::Url;
 
 #[derive(Serialize, Clone)]
 pub struct AnalyzerTool {
     pub yields_data: Vec<ProtocolYield>,
+    /// When `yields_data` was fetched
+    pub as_of: DateTime<Utc>,
 }
 
 impl YieldAnalyzer {
-    /// supported for now: "STRK", "BROTHER", "ETH"; {token}/USDC pairs
-    pub async fn get_yields_data() -> Result<Vec<ProtocolYield>, Error> {
-        let (tokens, market_data) = fetch_all_tokens().await;
+    /// supported for now: "STRK", "BROTHER", "ETH"; {token}/USDC pairs, reserves read on the node at `rpc_url`
+    pub async fn get_yields_data(registry: &TokenRegistry, prices: &PriceFeed, rpc_url: &Url) -> Result<Vec<ProtocolYield>, Error> {
+        let (tokens, market_data) = fetch_all_tokens(registry, prices, rpc_url).await?;
 
         let mut res: Vec<ProtocolYield> = Vec::with_capacity(tokens.len());
         for (token, market) in tokens.iter().zip(market_data.iter()) {
@@ -47,7 +54,7 @@ impl Tool for AnalyzerTool {
     async fn definition(&self, _prompt: String) -> ToolDefinition {
         ToolDefinition {
             name: Self::NAME.to_string(),
-            description: "Fetch the estimated yield returns for supported tokens. ETH, STRK, BROTHER all with USDC pair".to_string(),
+            description: "Fetch the estimated yield returns for supported tokens. ETH, STRK, BROTHER all with USDC pair, APYs in percent".to_string(),
             parameters: json!({
                 "type": "object",
                 "properties": {
@@ -66,6 +73,7 @@ impl Tool for AnalyzerTool {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let source = cite(Source::yields(self.as_of));
         let filtered_data = if let Some(risk_score) = args.risk_score {
             self.yields_data
                 .iter()
@@ -83,7 +91,7 @@ impl Tool for AnalyzerTool {
                 .collect()
         };
 
-        Ok(format_yields_data(filtered_data))
+        Ok(format!("[{source}] {}", format_yields_data(filtered_data)))
     }
 }
 
@@ -97,23 +105,18 @@ pub struct AnalyzArgs {
     risk_score: Option<String>,
 }
 
+/// One line per pool, `apy` is already a percentage
 pub fn format_yields_data(yields_data: Vec<ProtocolYield>) -> String {
     let formatted_data = yields_data
         .iter()
         .map(|yield_data| {
             format!(
-                "Token: {}, APY: {:.2}%, TVL: ${:.2}, Risk Score: {:.2}",
-                yield_data.token.name,
-                yield_data.apy * 100.0,
-                yield_data.tvl,
-                yield_data.risk_score
+                "{}/USDC {:?} pool: APY {:.2}%, TVL ${:.2}, Risk Score: {:.2}",
+                yield_data.token.name, yield_data.pool_type, yield_data.apy, yield_data.tvl, yield_data.risk_score
             )
         })
         .collect::<Vec<String>>()
-        .join(", ");
+        .join("\n");
 
-    format!(
-        "Here is the latest yields data of {{token}}/USDC pair: {}.",
-        formatted_data
-    )
+    format!("Here is the latest yields data:\n{}", formatted_data)
 }
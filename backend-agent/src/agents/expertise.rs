@@ -0,0 +1,46 @@
+use crate::address::ContractAddress;
+use crate::types::Expertise;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Default)]
+struct Settings {
+    /// Set through the API, per session id
+    sessions: HashMap<String, Expertise>,
+    /// Set through the API, per wallet, kept across sessions
+    wallets: HashMap<ContractAddress, Expertise>,
+    /// Inferred by the navigator from the conversation, per session id
+    inferred: HashMap<String, Expertise>,
+}
+
+/// Expertise of each session and wallet, shared with the backend. Clones share the settings.
+#[derive(Clone, Default)]
+pub struct ExpertiseSettings(Arc<RwLock<Settings>>);
+
+impl ExpertiseSettings {
+    pub fn set_session(&self, session_id: &str, expertise: Expertise) {
+        self.0.write().sessions.insert(session_id.to_string(), expertise);
+    }
+
+    pub fn set_wallet(&self, wallet: ContractAddress, expertise: Expertise) {
+        self.0.write().wallets.insert(wallet, expertise);
+    }
+
+    /// Default for the session until the user sets one
+    pub fn infer(&self, session_id: &str, expertise: Expertise) {
+        self.0.write().inferred.insert(session_id.to_string(), expertise);
+    }
+
+    /// What the session set, else what its wallet set, else what was inferred, else `standard`
+    pub fn resolve(&self, session_id: &str, wallet: Option<ContractAddress>) -> Expertise {
+        let settings = self.0.read();
+        settings
+            .sessions
+            .get(session_id)
+            .or_else(|| wallet.and_then(|wallet| settings.wallets.get(&wallet)))
+            .or_else(|| settings.inferred.get(session_id))
+            .copied()
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,386 @@
+use super::turn;
+use crate::types::{Expertise, PoolType, ProtocolYield};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// Well known protocols that don't live on Starknet, mentioning them is most likely a hallucination
+const FOREIGN_PROTOCOLS: &[&str] = &[
+    "uniswap", "aave", "pancakeswap", "sushiswap", "raydium", "lido", "gmx", "makerdao", "yearn", "trader joe",
+];
+/// Starknet protocols, often written in caps
+const STARKNET_PROTOCOLS: &[&str] = &[
+    "NOSTRA", "EKUBO", "AVNU", "ZKLEND", "JEDISWAP", "MYSWAP", "ENDUR", "VESU", "NIMBORA", "HAIKO", "CARMINE", "FIBROUS",
+];
+/// Uppercase words that look like tickers but aren't
+const ACRONYMS: &[&str] = &[
+    "APY", "APR", "TVL", "LP", "LPS", "DEFI", "USD", "IL", "AMM", "DEX", "CEX", "NFT", "DAO", "ROI",
+    "DYOR", "NFA", "GM", "OK", "UI", "API", "ID", "IMO", "TL", "DR", "FAQ", "TX", "CL", "ATH", "ATL",
+    "HODL", "AI", "BTC", "NOT", "DO", "NO", "YES", "AND", "OR", "THE", "ALL", "NOW", "NEW", "MUST",
+    "RISK", "DEGEN", "STABLE", "LST", "LSTS", "HIGH", "LOW",
+];
+/// Words before a caps word that make it a token, e.g. "swap for PEPE"
+const TOKEN_VERBS: &[&str] = &["buy", "sell", "swap", "hold", "ape", "long", "short", "into", "for", "stake", "bridge"];
+/// Words after a caps word that make it a token, e.g. "PEPE token"
+const TOKEN_NOUNS: &[&str] = &["token", "tokens", "coin", "coins"];
+const DISCLAIMER_MARKERS: &[&str] = &["not financial advice", "dyor", "nfa", "do your own research"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuardrailConfig {
+    pub max_lines: usize,
+    /// How many times the model is asked again before repairs are applied
+    pub max_reasks: u32,
+    /// Relative difference tolerated between a quoted APY and the data
+    pub apy_tolerance: f64,
+    pub disclaimer: String,
+    pub degen_warning: String,
+}
+
+impl Default for GuardrailConfig {
+    fn default() -> Self {
+        Self {
+            max_lines: 3,
+            max_reasks: 1,
+            apy_tolerance: 0.1,
+            disclaimer: "Not financial advice, DYOR.".to_string(),
+            degen_warning: "Degen pools can lose most of their value quickly, only risk what you can afford to lose."
+                .to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    Length,
+    UnknownToken,
+    UnsupportedProtocol,
+    ApyMismatch,
+    Disclaimer,
+}
+
+/// A failed check and what was done about it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuardrailFinding {
+    pub check: Check,
+    pub detail: String,
+    /// Fixed in place rather than needing the model to answer again
+    pub repaired: bool,
+}
+
+/// Outcome of running the checks on a response
+#[derive(Debug, Clone)]
+pub struct Review {
+    pub findings: Vec<GuardrailFinding>,
+    /// Response with repairs applied
+    pub response: String,
+}
+
+impl Review {
+    /// Whether asking the model again could fix what repairs can't
+    pub fn needs_reask(&self) -> bool {
+        self.findings.iter().any(|finding| !finding.repaired)
+    }
+
+    /// Correction sent back to the model when re-asking
+    pub fn feedback(&self) -> String {
+        let issues = self
+            .findings
+            .iter()
+            .filter(|finding| !finding.repaired)
+            .map(|finding| format!("- {}", finding.detail))
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!("Your previous answer had these issues:\n{issues}\nAnswer again, only using supported tokens, Starknet protocols and the current yields data.")
+    }
+}
+
+/// Post-processing checks on defiproman's answers
+#[derive(Debug, Clone)]
+pub struct Guardrails {
+    pub config: GuardrailConfig,
+    supported_tokens: HashSet<String>,
+    /// Protocols with position adapters, on top of [`STARKNET_PROTOCOLS`]
+    protocols: HashSet<String>,
+    yields: Vec<ProtocolYield>,
+}
+
+/// A quoted APY that differs from the data
+struct ApyMismatch {
+    symbol: String,
+    quoted: String,
+    /// Bytes of the number, without the `%`
+    span: Range<usize>,
+    /// `None` when the quote can't be pinned to one pool
+    actual: Option<f64>,
+}
+
+impl Guardrails {
+    pub fn new(config: GuardrailConfig, supported_tokens: impl IntoIterator<Item = String>, yields: Vec<ProtocolYield>) -> Self {
+        let supported_tokens = supported_tokens
+            .into_iter()
+            .chain(yields.iter().map(|y| y.token.name.clone()))
+            .map(|symbol| symbol.to_uppercase())
+            .collect();
+        Self {
+            config,
+            supported_tokens,
+            protocols: HashSet::new(),
+            yields,
+        }
+    }
+
+    /// Protocol names that aren't tickers even in caps
+    pub fn with_protocols(mut self, protocols: impl IntoIterator<Item = String>) -> Self {
+        self.protocols
+            .extend(protocols.into_iter().map(|protocol| protocol.to_uppercase()));
+        self
+    }
+
+    pub fn review(&self, response: &str) -> Review {
+        let mut findings = vec![];
+        let mut response = response.trim().to_string();
+
+        for symbol in self.unknown_tokens(&response) {
+            findings.push(GuardrailFinding {
+                check: Check::UnknownToken,
+                detail: format!("{symbol} is not a supported token"),
+                repaired: false,
+            });
+        }
+        for protocol in self.foreign_protocols(&response) {
+            findings.push(GuardrailFinding {
+                check: Check::UnsupportedProtocol,
+                detail: format!("{protocol} is not a Starknet protocol we support"),
+                repaired: false,
+            });
+        }
+        let mismatches = self.apy_mismatches(&response);
+        for ApyMismatch { symbol, quoted, actual, .. } in &mismatches {
+            findings.push(GuardrailFinding {
+                check: Check::ApyMismatch,
+                detail: match actual {
+                    Some(actual) => format!("{symbol} APY is {actual:.2}%, not {quoted}%"),
+                    None => format!("{quoted}% doesn't match any {symbol} pool in the yields data"),
+                },
+                repaired: actual.is_some(),
+            });
+        }
+        // back to front so earlier spans stay valid
+        for mismatch in mismatches.into_iter().rev() {
+            if let Some(actual) = mismatch.actual {
+                response.replace_range(mismatch.span, &format!("{actual:.2}"));
+            }
+        }
+
+        let expertise = turn::current(|metadata| metadata.expertise).unwrap_or_default();
+        if let Some(disclaimer) = self.missing_disclaimer(&response, expertise) {
+            findings.push(GuardrailFinding {
+                check: Check::Disclaimer,
+                detail: "risk disclaimer added".to_string(),
+                repaired: true,
+            });
+            response = format!("{response}\n{disclaimer}");
+        }
+
+        // the disclaimer counts towards the limit but is never cut
+        let max_lines = self.max_lines(expertise);
+        let lines: Vec<&str> = response.lines().filter(|line| !line.trim().is_empty()).collect();
+        if lines.len() > max_lines {
+            findings.push(GuardrailFinding {
+                check: Check::Length,
+                detail: format!("{} lines, limit is {}", lines.len(), max_lines),
+                repaired: true,
+            });
+            let notices = lines.iter().filter(|line| self.is_notice(line)).count();
+            let mut body = max_lines.saturating_sub(notices);
+            response = lines
+                .into_iter()
+                .filter(|line| {
+                    if self.is_notice(line) {
+                        return true;
+                    }
+                    let keep = body > 0;
+                    body = body.saturating_sub(1);
+                    keep
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+
+        Review { findings, response }
+    }
+
+    /// Lines allowed at `expertise`: beginners get room for explanations, pros for breakdowns
+    pub fn max_lines(&self, expertise: Expertise) -> usize {
+        match expertise {
+            Expertise::Beginner => self.config.max_lines * 2,
+            Expertise::Standard => self.config.max_lines,
+            Expertise::Pro => self.config.max_lines * 3,
+        }
+    }
+
+    /// Ticker-looking words absent from the registry: `$XYZ`, or all caps next to a token context
+    /// such as an amount, "swap for XYZ" or "XYZ token"
+    fn unknown_tokens(&self, response: &str) -> Vec<String> {
+        let words: Vec<&str> = words(response).collect();
+        let mut unknown: Vec<String> = words
+            .iter()
+            .enumerate()
+            .filter_map(|(i, word)| {
+                let ticker = word.strip_prefix('$');
+                let symbol = ticker.unwrap_or(word);
+                let looks_like_ticker = (2..=6).contains(&symbol.len())
+                    && symbol.chars().all(|c| c.is_ascii_alphanumeric())
+                    && symbol.chars().any(|c| c.is_ascii_alphabetic());
+                let in_token_context = || {
+                    let before = i.checked_sub(1).map(|j| words[j].to_lowercase());
+                    let after = words.get(i + 1).map(|w| w.to_lowercase());
+                    symbol.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+                        && (before.as_deref().is_some_and(|w| TOKEN_VERBS.contains(&w) || w.parse::<f64>().is_ok())
+                            || after.as_deref().is_some_and(|w| TOKEN_NOUNS.contains(&w)))
+                };
+                let upper = symbol.to_uppercase();
+                let known = self.supported_tokens.contains(&upper)
+                    || self.protocols.contains(&upper)
+                    || STARKNET_PROTOCOLS.contains(&upper.as_str())
+                    || ACRONYMS.contains(&upper.as_str());
+                (looks_like_ticker && !known && (ticker.is_some() || in_token_context())).then_some(upper)
+            })
+            .collect();
+        unknown.sort();
+        unknown.dedup();
+        unknown
+    }
+
+    fn foreign_protocols(&self, response: &str) -> Vec<String> {
+        let normalized = response.to_lowercase();
+        FOREIGN_PROTOCOLS
+            .iter()
+            .filter(|protocol| {
+                normalized
+                    .match_indices(*protocol)
+                    .any(|(i, _)| is_boundary(&normalized, i, protocol.len()))
+            })
+            .map(|protocol| protocol.to_string())
+            .collect()
+    }
+
+    /// Quoted APYs that differ from the data, in order. A percentage is a quote only next to
+    /// "APY" or "yield", allocations like "put 20% in ETH" aren't.
+    fn apy_mismatches(&self, response: &str) -> Vec<ApyMismatch> {
+        let words: Vec<(usize, &str)> = word_spans(response).collect();
+        let mut mismatches = vec![];
+        for (i, &(start, word)) in words.iter().enumerate() {
+            let Some(quoted) = word.strip_suffix('%') else {
+                continue;
+            };
+            let Ok(value) = quoted.parse::<f64>() else {
+                continue;
+            };
+            // closest rate keyword, "12% APY" or "APY of 12%". APRs are quoted next to APYs
+            // in breakdowns, they're lower by design.
+            let keyword = words
+                .get(i + 1)
+                .into_iter()
+                .chain(words[i.saturating_sub(3)..i].iter().rev())
+                .map(|(_, w)| w.to_lowercase())
+                .find(|w| matches!(w.as_str(), "apy" | "apr" | "yield" | "yields" | "yielding"));
+            if keyword.is_none_or(|w| w == "apr") {
+                continue;
+            }
+            // token symbols within the few words before the number
+            let mut symbols: Vec<&str> = words[i.saturating_sub(8)..i]
+                .iter()
+                .rev()
+                .filter_map(|(_, w)| self.yields.iter().find(|y| is_symbol(w, &y.token.name)))
+                .map(|y| y.token.name.as_str())
+                .collect();
+            let Some(&symbol) = symbols.first() else {
+                continue;
+            };
+            symbols.sort_unstable();
+            symbols.dedup();
+            let apys: Vec<f64> = self
+                .yields
+                .iter()
+                .filter(|y| y.token.name == symbol)
+                .map(|y| y.apy)
+                .collect();
+            let matches = apys
+                .iter()
+                .any(|apy| (value - apy).abs() <= (apy.abs() * self.config.apy_tolerance).max(0.01));
+            if !matches {
+                // several tokens or pools it could be about, only the model can tell which
+                let unambiguous = symbols.len() == 1 && apys.len() == 1;
+                mismatches.push(ApyMismatch {
+                    symbol: symbol.to_string(),
+                    quoted: quoted.to_string(),
+                    span: start..start + quoted.len(),
+                    actual: unambiguous.then_some(apys[0]),
+                });
+            }
+        }
+        mismatches
+    }
+
+    /// A line carrying the disclaimer or the Degen warning
+    fn is_notice(&self, line: &str) -> bool {
+        let normalized = line.to_lowercase();
+        DISCLAIMER_MARKERS.iter().any(|marker| normalized.contains(marker))
+            || normalized.contains(&self.config.degen_warning.to_lowercase())
+    }
+
+    /// Disclaimer to append when the response suggests a position without one.
+    /// Beginners always get the Degen warning when Degen pools come up.
+    fn missing_disclaimer(&self, response: &str, expertise: Expertise) -> Option<String> {
+        let normalized = response.to_lowercase();
+        let mentions_degen = normalized.contains("degen")
+            || self.yields.iter().any(|y| {
+                matches!(y.pool_type, PoolType::Degen)
+                    && words(response).any(|w| is_symbol(w, &y.token.name))
+            });
+        if DISCLAIMER_MARKERS.iter().any(|marker| normalized.contains(marker)) {
+            let warned = normalized.contains(&self.config.degen_warning.to_lowercase());
+            return (expertise == Expertise::Beginner && mentions_degen && !warned)
+                .then(|| self.config.degen_warning.clone());
+        }
+        let gives_advice = response.contains('%')
+            || ["stake", "provide liquidity", "lend", "borrow", "deposit", "farm", "swap", "allocate", "put "]
+                .iter()
+                .any(|word| normalized.contains(word));
+        match (mentions_degen, gives_advice) {
+            (true, _) => Some(format!("{} {}", self.config.degen_warning, self.config.disclaimer)),
+            (false, true) => Some(self.config.disclaimer.clone()),
+            (false, false) => None,
+        }
+    }
+}
+
+/// Words stripped of surrounding punctuation, keeping `$`, `%` and decimal points
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    word_spans(text).map(|(_, word)| word)
+}
+
+/// [`words`] with their byte offset in `text`
+fn word_spans(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | '(' | ')' | '"' | '!' | '?' | ':' | '/'))
+        .map(|word| word.trim_matches(|c: char| matches!(c, '.' | '\'' | '*' | '~' | '-')))
+        .filter(|word| !word.is_empty())
+        .map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+}
+
+/// `word` names the token `symbol`: as written, in caps or as a `$` ticker.
+/// Lowercase words don't count, "Starknet brother" isn't about BROTHER.
+fn is_symbol(word: &str, symbol: &str) -> bool {
+    match word.strip_prefix('$') {
+        Some(ticker) => ticker.eq_ignore_ascii_case(symbol),
+        None => word == symbol || word == symbol.to_uppercase(),
+    }
+}
+
+fn is_boundary(text: &str, start: usize, len: usize) -> bool {
+    let before = text[..start].chars().next_back();
+    let after = text[start + len..].chars().next();
+    !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+}
@@ -1,12 +1,26 @@
-use navigator::Navigator;
+use expertise::ExpertiseSettings;
+use navigator::{Navigator, RoutingLog};
+use risk_officer::RiskLog;
 use rig::completion::CompletionModel;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use crate::starknet_id::StarknetId;
+use crate::wallets::WalletLinks;
 
-pub mod lp_pro_man;
+pub mod expertise;
+pub mod guardrails;
 pub mod navigator;
+pub mod planner;
+pub mod registry;
+pub mod risk_officer;
+pub mod strategy;
+pub mod turn;
 
 #[derive(Clone)]
 pub struct AgentState<M: CompletionModel> {
-    pub navigator: Arc<Mutex<Navigator<M>>>,
+    pub navigator: Arc<Navigator<M>>,
+    pub routing_log: RoutingLog,
+    pub risk_log: RiskLog,
+    pub expertise: ExpertiseSettings,
+    pub wallet_links: WalletLinks,
+    pub starknet_id: StarknetId,
 }
@@ -1,115 +1,740 @@
 use crate::{
-    agent_tools::{portfolio::PortfolioFetch, yield_analyzer::AnalyzerTool},
+    agent_tools::{
+        performance::PortfolioPerformance,
+        portfolio::PortfolioFetch,
+        yield_analyzer::{format_yields_data, AnalyzerTool},
+    },
     backend::{AppState, Backend},
-    types::ProtocolYield,
-    backend::messaging::spawn_chat_history_manager
+    token_registry::TokenRegistry,
+    prices::PriceFeed,
+    prompts::{PromptLibrary, PromptVars},
+    sources::{cite, Source},
+    types::{Expertise, ProtocolYield, RiskProfile},
 };
 
-use anyhow::Ok;
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
 use rig::{
     agent::{Agent, AgentBuilder},
-    completion::{Chat, CompletionModel, Message, Prompt, PromptError, CompletionError},
-    loaders::FileLoader,
+    completion::{Chat, Completion, CompletionModel, Message, ModelChoice, PromptError, CompletionError},
+    tool::Tool,
 };
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use tokio::sync::{mpsc::{self, Sender}, oneshot, Mutex};
-use tracing::info;
-use crate::backend::messaging::{ChatHistoryCommand, ChatHistoryManager};
+use tracing::{info, warn};
+use crate::address::ContractAddress;
+use crate::backend::messaging::ChatHistoryCommand;
+use crate::starknet_id::{domain_in_prompt, StarknetId};
+use crate::wallets::WalletLinks;
+use super::expertise::ExpertiseSettings;
+use super::guardrails::{GuardrailConfig, Guardrails};
+use super::planner::{Planner, PlannerConfig};
+use super::registry::{AgentRegistry, AgentSpec, RegistryError, NAVIGATOR};
+use super::risk_officer::{RiskLog, RiskOfficer, Verdict};
+use super::strategy::{self, Strategy, StrategyPlan, STRATEGY_PROMPT, SUBMIT_STRATEGY};
+use super::turn::{self, TurnMetadata, TurnOutcome};
+
+/// How many routing decisions are kept in memory for evaluation
+const ROUTING_LOG_CAPACITY: usize = 1000;
+
 #[derive(Clone)]
 pub struct Tools<M: CompletionModel> {
-    pub _analyzer_tool: AnalyzerTool,
+    pub analyzer_tool: AnalyzerTool,
     pub portfolio_tool: PortfolioFetch<M>,
+    pub performance_tool: PortfolioPerformance<M>,
+    /// Shared with the portfolio tool, grows as it discovers tokens
+    pub tokens: TokenRegistry,
 }
 
-impl<M: CompletionModel> Tools<M> {
-    pub fn new(yields_data: Vec<ProtocolYield>, appstate: Arc<Mutex<AppState<M>>>) -> Self {
+impl<M: CompletionModel + 'static> Tools<M> {
+    /// Adds the tools `spec` lists to `builder`
+    pub fn attach(&self, spec: &AgentSpec, builder: AgentBuilder<M>) -> Result<AgentBuilder<M>, RegistryError> {
+        spec.tools.iter().try_fold(builder, |builder, name| match name.as_str() {
+            PortfolioFetch::<M>::NAME => Ok(builder.tool(self.portfolio_tool.clone())),
+            PortfolioPerformance::<M>::NAME => Ok(builder.tool(self.performance_tool.clone())),
+            AnalyzerTool::NAME => Ok(builder.tool(self.analyzer_tool.clone())),
+            _ => Err(RegistryError::UnknownTool(spec.name.clone(), name.clone())),
+        })
+    }
+
+    pub fn new(yields_data: Vec<ProtocolYield>, appstate: Arc<Mutex<AppState<M>>>, tokens: TokenRegistry) -> Self {
+        let prices = PriceFeed::from_env(&yields_data);
         Self {
-            _analyzer_tool: AnalyzerTool { yields_data },
-            portfolio_tool: PortfolioFetch { appstate },
+            analyzer_tool: AnalyzerTool {
+                yields_data,
+                as_of: Utc::now(),
+            },
+            portfolio_tool: PortfolioFetch::new(appstate.clone(), prices, tokens.clone()),
+            performance_tool: PortfolioPerformance { appstate },
+            tokens,
+        }
+    }
+}
+
+/// What the user is asking for, decided before any LLM is called
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Intent {
+    Greeting,
+    PortfolioRequest,
+    YieldQuestion,
+    ProtocolEducation,
+    StrategyRequest,
+    OffTopic,
+    /// Nothing matched, the navigator LLM decides
+    Unclassified,
+}
+
+/// Which handler answered the prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Route {
+    /// Canned answer, no LLM call
+    Deterministic,
+    /// Straight to the intent's specialist, defiproman unless the registry says otherwise; navigator skipped
+    Defiproman,
+    /// Navigator refines the prompt then forwards it to the specialist
+    RefineAndForward,
+    /// Defiproman chains tool calls in a planning loop
+    Plan,
+}
+
+/// Sessions are left out, their id is the only credential of a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingDecision {
+    pub prompt: String,
+    pub intent: Intent,
+    pub route: Route,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Bounded in-memory log of routing decisions, shared with the backend
+#[derive(Clone, Default)]
+pub struct RoutingLog(Arc<RwLock<VecDeque<RoutingDecision>>>);
+
+impl RoutingLog {
+    pub fn record(&self, decision: RoutingDecision) {
+        let mut log = self.0.write();
+        if log.len() >= ROUTING_LOG_CAPACITY {
+            log.pop_front();
+        }
+        log.push_back(decision);
+    }
+
+    pub fn decisions(&self) -> Vec<RoutingDecision> {
+        self.0.read().iter().cloned().collect()
+    }
+}
+
+const GREETINGS: &[&str] = &[
+    "hi", "hello", "hey", "gm", "yo", "sup", "hola", "howdy", "greetings", "good morning",
+    "good evening", "good afternoon", "whats up", "what's up",
+];
+const PORTFOLIO_WORDS: &[&str] = &["portfolio", "wallet", "my balance", "my balances", "holdings", "my tokens", "my bag"];
+const STRATEGY_WORDS: &[&str] = &[
+    "strategy", "strategies", "should i", "where should", "best way", "best place", "allocate",
+    "allocation", "invest", "put my", "farm", "rebalance", "diversify", "maximize", "recommend",
+];
+const YIELD_WORDS: &[&str] = &["apy", "apr", "yield", "yields", "returns", "tvl", "interest rate", "rewards"];
+const EDUCATION_WORDS: &[&str] = &[
+    "what is", "what's a", "what are", "how does", "how do", "explain", "difference between",
+    "impermanent loss", "liquidity pool", "lending", "borrowing", "staking", "amm",
+];
+pub const PROTOCOLS: &[&str] = &["nostra", "ekubo", "jediswap", "zklend", "myswap", "10kswap", "sithswap", "avnu", "haiko", "vesu", "endur"];
+/// Anything DeFi-ish, used to avoid flagging real questions as off-topic
+const DEFI_WORDS: &[&str] = &[
+    "defi", "starknet", "token", "pool", "swap", "liquidity", "stake", "lp", "strk", "eth",
+    "usdc", "usdt", "btc", "brother", "crypto", "price", "market", "chain", "bridge", "gas",
+];
+const OFF_TOPIC_WORDS: &[&str] = &[
+    "weather", "recipe", "poem", "joke", "movie", "song", "football", "soccer", "basketball",
+    "politics", "election", "homework", "essay", "write code", "translate", "dating",
+];
+
+const CONSERVATIVE_WORDS: &[&str] = &["safe", "safest", "low risk", "conservative", "risk averse", "can't afford to lose", "beginner"];
+const AGGRESSIVE_WORDS: &[&str] = &["degen", "high risk", "aggressive", "ape", "moon", "100x", "leverage"];
+const BEGINNER_WORDS: &[&str] = &[
+    "beginner", "newbie", "noob", "eli5", "first time", "new to defi", "new to crypto", "new to starknet",
+    "just started", "never used", "simple terms", "explain like",
+];
+const PRO_WORDS: &[&str] = &[
+    "concentrated liquidity", "tick range", "delta neutral", "market maker", "market making", "formula",
+    "contract address", "basis points", "bps", "hedge", "hedging", "funding rate", "ltv", "utilization rate",
+    "apr breakdown", "compounding frequency",
+];
+
+/// Symbols of the verified tokens
+fn supported_tokens(tokens: &TokenRegistry) -> Vec<String> {
+    tokens.verified().into_iter().map(|token| token.symbol).collect()
+}
+
+/// Lowercased prompt and its words
+fn normalize(prompt: &str) -> (String, Vec<String>) {
+    let normalized = prompt.trim().to_lowercase();
+    let words = normalized
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect();
+    (normalized, words)
+}
+
+/// Multi word phrases match on the normalized text, single words on word boundaries
+fn mentions(normalized: &str, words: &[String], list: &[&str]) -> bool {
+    list.iter().any(|kw| {
+        if kw.contains(' ') {
+            normalized.contains(kw)
+        } else {
+            words.iter().any(|w| w == kw)
         }
+    })
+}
+
+/// Risk appetite stated in a prompt, if any
+pub fn infer_risk_profile(prompt: &str) -> Option<RiskProfile> {
+    let (normalized, words) = normalize(prompt);
+    match (
+        mentions(&normalized, &words, CONSERVATIVE_WORDS),
+        mentions(&normalized, &words, AGGRESSIVE_WORDS),
+    ) {
+        (true, false) => Some(RiskProfile::Conservative),
+        (false, true) => Some(RiskProfile::Aggressive),
+        _ => None,
     }
 }
 
+/// DeFi experience shown by a prompt, if any
+pub fn infer_expertise(prompt: &str) -> Option<Expertise> {
+    let (normalized, words) = normalize(prompt);
+    match (
+        mentions(&normalized, &words, BEGINNER_WORDS),
+        mentions(&normalized, &words, PRO_WORDS),
+    ) {
+        (true, false) => Some(Expertise::Beginner),
+        (false, true) => Some(Expertise::Pro),
+        _ => None,
+    }
+}
+
+/// First Starknet address typed in the prompt
+pub fn wallet_in_prompt(prompt: &str) -> Option<ContractAddress> {
+    prompt
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| w.len() > 10 && (w.starts_with("0x") || w.starts_with("0X")))
+        .find_map(|w| w.parse().ok())
+}
+
+/// Strategy questions about the user's own holdings, which need the portfolio before any advice
+pub fn needs_planning(prompt: &str) -> bool {
+    let (normalized, words) = normalize(prompt);
+    let mentions = |list: &[&str]| mentions(&normalized, &words, list);
+    mentions(STRATEGY_WORDS) && (wallet_in_prompt(prompt).is_some() || domain_in_prompt(prompt).is_some() || mentions(PORTFOLIO_WORDS))
+}
+
+/// Label a prompt with keyword rules; cheap, deterministic and good enough to skip the navigator LLM.
+pub fn classify_intent(prompt: &str) -> Intent {
+    let (normalized, words) = normalize(prompt);
+    let mentions = |list: &[&str]| mentions(&normalized, &words, list);
+
+    if wallet_in_prompt(prompt).is_some() || domain_in_prompt(prompt).is_some() || mentions(PORTFOLIO_WORDS) {
+        return Intent::PortfolioRequest;
+    }
+    if mentions(STRATEGY_WORDS) {
+        return Intent::StrategyRequest;
+    }
+    if mentions(YIELD_WORDS) {
+        return Intent::YieldQuestion;
+    }
+    if mentions(EDUCATION_WORDS) || mentions(PROTOCOLS) {
+        return Intent::ProtocolEducation;
+    }
+    if words.len() <= 4 && mentions(GREETINGS) {
+        return Intent::Greeting;
+    }
+    if mentions(OFF_TOPIC_WORDS) && !mentions(DEFI_WORDS) {
+        return Intent::OffTopic;
+    }
+    Intent::Unclassified
+}
+
+/// A registry agent the navigator forwards prompts to
+struct Specialist<M: CompletionModel> {
+    spec: AgentSpec,
+    agent: Agent<M>,
+}
+
 pub struct Navigator<M: CompletionModel> {
     navigator: Agent<M>,
-    defiproman: Agent<M>,
+    registry: AgentRegistry,
+    specialists: Vec<Specialist<M>>,
+    /// Reviews drafts when the registry has a reviewer
+    risk_officer: Option<RiskOfficer<M>>,
+    pub risk_log: RiskLog,
     pub chat_history_sender: mpsc::Sender<ChatHistoryCommand>,
+    pub routing_log: RoutingLog,
     tools: Tools<M>,
+    prompts: PromptLibrary,
+    guardrails: Guardrails,
+    pub planner: PlannerConfig,
+    /// Risk appetite stated by each session, `balanced` until it says otherwise
+    risk_profiles: Arc<RwLock<HashMap<String, RiskProfile>>>,
+    /// Last wallet each session shared
+    session_wallets: Arc<RwLock<HashMap<String, ContractAddress>>>,
+    pub expertise: ExpertiseSettings,
+    pub wallet_links: WalletLinks,
+    pub starknet_id: StarknetId,
 }
 
 impl<M: CompletionModel + 'static> Navigator<M> {
-    pub fn new(nav_model: M, defaigent_model: AgentBuilder<M>, tools: Tools<M>, chat_sender: Sender<ChatHistoryCommand>) -> Self {
+    /// Builds the agents of `registry` from `models`, keyed by agent name
+    pub fn new(
+        registry: AgentRegistry,
+        mut models: HashMap<String, AgentBuilder<M>>,
+        tools: Tools<M>,
+        chat_sender: Sender<ChatHistoryCommand>,
+        prompts: PromptLibrary,
+        risk_log: RiskLog,
+    ) -> Result<Self, RegistryError> {
+        let mut model = |name: &str| models.remove(name).ok_or_else(|| RegistryError::MissingModel(name.to_string()));
+        let navigator = agent_build(model(NAVIGATOR)?, &registry);
+        let specialists = registry
+            .specialists()
+            .map(|spec| {
+                let agent = tools.attach(spec, model(&spec.name)?)?.build();
+                Ok(Specialist {
+                    spec: spec.clone(),
+                    agent,
+                })
+            })
+            .collect::<Result<Vec<_>, RegistryError>>()?;
+        let risk_officer = match registry.reviewer() {
+            Some(spec) => Some(RiskOfficer::new(
+                tools.attach(spec, model(&spec.name)?)?.build(),
+                &spec.preamble,
+                risk_log.clone(),
+            )),
+            None => None,
+        };
 
-        Self {
-            navigator: agent_build(nav_model.clone()).expect("Failed building navigator"),
-            defiproman: super::lp_pro_man::proman_agent_build(defaigent_model, tools.clone())
-                .expect("Failed building defiproman"),
+        // signatures are checked on the node portfolios are fetched from
+        let wallet_links = WalletLinks::new(tools.portfolio_tool.rpc_url.clone());
+        let starknet_id = tools.portfolio_tool.starknet_id.clone();
+
+        Ok(Self {
+            navigator,
+            registry,
+            specialists,
+            risk_officer,
+            risk_log,
             chat_history_sender: chat_sender,
+            routing_log: RoutingLog::default(),
+            guardrails: Guardrails::new(
+                GuardrailConfig::default(),
+                supported_tokens(&tools.tokens),
+                tools.analyzer_tool.yields_data.clone(),
+            )
+            .with_protocols(tools.portfolio_tool.positions.adapters.iter().map(|adapter| adapter.protocol().to_string())),
             tools,
+            prompts,
+            planner: PlannerConfig::from_env(),
+            risk_profiles: Arc::default(),
+            session_wallets: Arc::default(),
+            expertise: ExpertiseSettings::default(),
+            wallet_links,
+            starknet_id,
+        })
+    }
+
+    /// Specialist the registry routes `intent` to
+    fn specialist(&self, intent: Intent) -> Result<&Specialist<M>, PromptError> {
+        let name = &self
+            .registry
+            .specialist_for(intent)
+            .map_err(|e| PromptError::CompletionError(CompletionError::RequestError(Box::new(e))))?
+            .name;
+        Ok(self
+            .specialists
+            .iter()
+            .find(|specialist| &specialist.spec.name == name)
+            .expect("every registry specialist is built"))
+    }
+
+    /// Preamble template of the navigator agent
+    fn navigator_preamble(&self, session_id: &str) -> Result<String, PromptError> {
+        let template = self
+            .registry
+            .get(NAVIGATOR)
+            .map_err(|e| PromptError::CompletionError(CompletionError::RequestError(Box::new(e))))?
+            .preamble
+            .clone();
+        self.preamble(&template, session_id)
+    }
+
+    /// Renders the preamble template `name` for this session and records its version in the turn.
+    /// `<name>_beginner` and `<name>_pro` replace it at those expertise levels when they exist.
+    fn preamble(&self, name: &str, session_id: &str) -> Result<String, PromptError> {
+        let expertise = turn::current(|metadata| metadata.expertise).unwrap_or_default();
+        let variant = format!("{name}_{expertise}");
+        let name = match expertise != Expertise::Standard && self.prompts.contains(&variant) {
+            true => &variant,
+            false => name,
+        };
+        let template = self
+            .prompts
+            .select(name, session_id)
+            .map_err(|e| PromptError::CompletionError(CompletionError::RequestError(Box::new(e))))?;
+        let vars = PromptVars {
+            date: Utc::now(),
+            supported_tokens: supported_tokens(&self.tools.tokens),
+            risk_profile: self.risk_profiles.read().get(session_id).copied().unwrap_or_default(),
+            yields_as_of: Some(self.tools.analyzer_tool.as_of),
+            expertise,
+            max_lines: self.guardrails.max_lines(expertise),
+            pools: self.tools.analyzer_tool.yields_data.clone(),
+        };
+        turn::record(|metadata| metadata.prompt_versions.push(template.id()));
+        std::result::Result::Ok(template.render(&vars))
+    }
+
+    pub async fn process_prompt(&self, prompt: &str, current_session: String) -> Result<TurnOutcome, PromptError> {
+        self.process_prompt_with(prompt, current_session, TurnOptions::default()).await
+    }
+
+    pub async fn process_prompt_with(
+        &self,
+        prompt: &str,
+        current_session: String,
+        options: TurnOptions,
+    ) -> Result<TurnOutcome, PromptError> {
+        let mut metadata = TurnMetadata::new(&current_session);
+        // tools only get the wallet the user typed, or the one already known for the session,
+        // and the wallets linked to the session by signature
+        metadata.linked_wallets = self.wallet_links.wallets(&current_session);
+        let typed = match (wallet_in_prompt(prompt), domain_in_prompt(prompt)) {
+            (Some(wallet), _) => Some(wallet),
+            (None, Some(domain)) => self
+                .starknet_id
+                .resolve(&domain)
+                .await
+                .inspect_err(|e| warn!("Session {}: {}", current_session, e))
+                .ok(),
+            (None, None) => None,
+        };
+        metadata.wallet = typed
+            .or_else(|| self.session_wallets.read().get(&current_session).copied())
+            .or_else(|| metadata.linked_wallets.first().copied());
+        if let Some(expertise) = infer_expertise(prompt) {
+            info!("Session {} expertise: {}", current_session, expertise);
+            self.expertise.infer(&current_session, expertise);
+        }
+        metadata.expertise = self.expertise.resolve(&current_session, metadata.wallet);
+        let (response, metadata) = turn::scope(metadata, self.answer(prompt, current_session.clone(), options)).await;
+        if let Some(wallet) = metadata.wallet {
+            self.session_wallets.write().insert(current_session, wallet);
         }
+
+        response.map(|(response, strategy)| TurnOutcome {
+            response,
+            metadata,
+            strategy,
+        })
     }
 
-    pub async fn process_prompt(&self, prompt: &str, current_session: String) -> Result<String, PromptError> {
+    async fn answer(
+        &self,
+        prompt: &str,
+        current_session: String,
+        options: TurnOptions,
+    ) -> Result<(String, Option<Strategy>), PromptError> {
+        let intent = classify_intent(prompt);
+        if let Some(risk_profile) = infer_risk_profile(prompt) {
+            info!("Session {} risk profile: {}", current_session, risk_profile);
+            self.risk_profiles.write().insert(current_session.clone(), risk_profile);
+        }
+
         // Add user message
-        self.chat_history_sender
-            .send(ChatHistoryCommand::AddMessage(
-                current_session.clone(),
-                Message {
-                role: "user".to_string(),
-                content: format!("<session_id>{}<session_id/> <prompt>{}<prompt/>",current_session.clone(), prompt.to_string()),
-            }))
-            .await
-            .map_err(|e| PromptError::CompletionError(
-                CompletionError::ResponseError(e.to_string())
-            ))?;
+        self.add_to_history(&current_session, "user", prompt.to_string()).await?;
 
         info!("Processing prompt from session {}", current_session.clone());
-    
-        // Get current history for AI
-        let (tx, rx) = oneshot::channel();
-        self.chat_history_sender
-            .send(ChatHistoryCommand::GetHistory(current_session.clone(), tx))
-            .await
-            .map_err(|e| PromptError::CompletionError(
-                CompletionError::ResponseError(e.to_string())
-            ))?;
-        
-        let history = rx.await.map_err(|e| PromptError::CompletionError(
-            CompletionError::ResponseError(e.to_string())
-        ))?;
-    
-        let refined_prompt = self.navigator.prompt(prompt).await?;
-        println!("{refined_prompt}");
-    
-        let response = self
-            .defiproman
-            .chat(&refined_prompt, history) // Use the history we got from the channel
-            .await
-            .map_err(|e| {
-                PromptError::CompletionError(
+
+        let (route, response, strategy) = match self.deterministic_answer(intent, prompt) {
+            Some(answer) => (Route::Deterministic, answer, None),
+            None => {
+                // Get current history for AI
+                let (tx, rx) = oneshot::channel();
+                self.chat_history_sender
+                    .send(ChatHistoryCommand::GetHistory(current_session.clone(), tx))
+                    .await
+                    .map_err(|e| PromptError::CompletionError(
+                        CompletionError::ResponseError(e.to_string())
+                    ))?;
+
+                let history = rx.await.map_err(|e| PromptError::CompletionError(
                     CompletionError::ResponseError(e.to_string())
-                )
-            })?;
-    
+                ))?;
+
+                let specialist = self.specialist(intent)?;
+                turn::record(|metadata| metadata.agent = Some(specialist.spec.name.clone()));
+                let (route, expert_prompt) = if needs_planning(prompt) {
+                    (Route::Plan, prompt.to_string())
+                } else if intent == Intent::Unclassified {
+                    let preamble = self.navigator_preamble(&current_session)?;
+                    let refined_prompt = complete(&self.navigator, preamble, prompt, vec![]).await?.into_inner();
+                    info!("Navigator refined prompt: {refined_prompt}");
+                    (Route::RefineAndForward, refined_prompt)
+                } else {
+                    (Route::Defiproman, prompt.to_string())
+                };
+
+                let preamble = self.preamble(&specialist.spec.preamble, &current_session)?;
+                let draft = match route {
+                    Route::Plan => Planner::new(&specialist.agent, self.planner)
+                        .with_guardrails(&self.guardrails)
+                        .run(&preamble, &expert_prompt, history.clone())
+                        .await
+                        .map(Reply::Text),
+                    _ => self.ask_specialist(&specialist.agent, preamble.clone(), &expert_prompt, history.clone()).await, // Use the history we got from the channel
+                }
+                .map_err(|e| {
+                    PromptError::CompletionError(
+                        CompletionError::ResponseError(e.to_string())
+                    )
+                })?;
+                let response = self
+                    .review_risk(specialist, &preamble, &expert_prompt, draft, &history, &current_session)
+                    .await?;
+                let strategy = match options.structured {
+                    true => self.structure_strategy(&specialist.agent, preamble, &expert_prompt, &response, history).await,
+                    false => None,
+                };
+                (route, response, strategy)
+            }
+        };
+
+        turn::record(|metadata| {
+            metadata.intent = Some(intent);
+            metadata.route = Some(route);
+        });
+        info!("Routing session {}: intent={:?} route={:?}", current_session, intent, route);
+        self.routing_log.record(RoutingDecision {
+            prompt: prompt.to_string(),
+            intent,
+            route,
+            timestamp: Utc::now(),
+        });
+
         // Add assistant's response to history
+        self.add_to_history(&current_session, "assistant", response.clone()).await?;
+
+        std::result::Result::Ok((response, strategy))
+    }
+
+    /// Runs the draft past the risk officer: approved drafts are sent as is, annotated ones with
+    /// the warnings appended, and revised ones are answered again once by the specialist.
+    /// Tool outputs aren't advice and skip the review.
+    async fn review_risk(
+        &self,
+        specialist: &Specialist<M>,
+        preamble: &str,
+        prompt: &str,
+        draft: Reply,
+        history: &[Message],
+        session_id: &str,
+    ) -> Result<String, PromptError> {
+        let (risk_officer, mut draft) = match (&self.risk_officer, draft) {
+            (Some(risk_officer), Reply::Text(text)) => (risk_officer, text),
+            (_, reply) => return std::result::Result::Ok(reply.into_inner()),
+        };
+        let risk_profile = self.risk_profiles.read().get(session_id).copied().unwrap_or_default();
+        let yields = &self.tools.analyzer_tool.yields_data;
+        let reviewer_preamble = self.preamble(&risk_officer.preamble, session_id)?;
+
+        let mut review = risk_officer.review(reviewer_preamble.clone(), &draft, risk_profile, yields).await;
+        if review.verdict == Some(Verdict::Revise) {
+            let reason = review.reason.clone().unwrap_or_else(|| "it doesn't fit the user's risk profile".to_string());
+            let mut revision_history = history.to_vec();
+            revision_history.push(Message {
+                role: "user".to_string(),
+                content: prompt.to_string(),
+            });
+            revision_history.push(Message {
+                role: "assistant".to_string(),
+                content: draft.clone(),
+            });
+            let revision_prompt = format!(
+                "The risk officer rejected your answer: {reason}. Answer again for a {risk_profile} user."
+            );
+            draft = self
+                .ask_specialist(&specialist.agent, preamble.to_string(), &revision_prompt, revision_history)
+                .await?
+                .into_inner();
+            review = risk_officer.review(reviewer_preamble, &draft, risk_profile, yields).await;
+        }
+
+        let warnings = match review.verdict {
+            Some(Verdict::Annotate) => review.warnings,
+            // a second rejection still gets through, with the reason as a warning
+            Some(Verdict::Revise) => review.reason.into_iter().chain(review.warnings).collect(),
+            _ => vec![],
+        };
+        std::result::Result::Ok(match warnings.is_empty() {
+            true => draft.to_string(),
+            false => format!("{draft}\nRisk warning: {}", warnings.join(" ")),
+        })
+    }
+
+    /// Asks the specialist to call `submit_strategy` with the actions of `answer`, checked against
+    /// the current yields. Best effort: the prose answer stands on its own if this fails.
+    async fn structure_strategy(
+        &self,
+        agent: &Agent<M>,
+        preamble: String,
+        prompt: &str,
+        answer: &str,
+        mut history: Vec<Message>,
+    ) -> Option<Strategy> {
+        history.push(Message {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        });
+        history.push(Message {
+            role: "assistant".to_string(),
+            content: answer.to_string(),
+        });
+        let response = async {
+            agent
+                .completion(STRATEGY_PROMPT, history)
+                .await?
+                .preamble(preamble)
+                .tool(strategy::submit_strategy_definition())
+                .send()
+                .await
+        }
+        .await;
+
+        let plan = match response.map(|response| response.choice) {
+            std::result::Result::Ok(ModelChoice::ToolCall(name, args)) if name == SUBMIT_STRATEGY => {
+                serde_json::from_value::<StrategyPlan>(args)
+            }
+            std::result::Result::Ok(choice) => {
+                warn!("Defiproman didn't submit a strategy: {:?}", choice);
+                return None;
+            }
+            Err(e) => {
+                warn!("Strategy request failed: {}", e);
+                return None;
+            }
+        };
+        match plan {
+            std::result::Result::Ok(plan) => {
+                cite(Source::yields(self.tools.analyzer_tool.as_of));
+                Some(strategy::validate(plan, &self.tools.analyzer_tool.yields_data))
+            }
+            Err(e) => {
+                warn!("Invalid strategy from defiproman: {}", e);
+                None
+            }
+        }
+    }
+
+    /// The specialist's answer once it passed the guardrails: repaired in place when possible,
+    /// asked again with the issues otherwise. Tool outputs are returned as is.
+    async fn ask_specialist(
+        &self,
+        agent: &Agent<M>,
+        preamble: String,
+        prompt: &str,
+        history: Vec<Message>,
+    ) -> Result<Reply, PromptError> {
+        let mut reply = complete(agent, preamble.clone(), prompt, history.clone()).await?;
+        let mut reasks = 0;
+        loop {
+            let Reply::Text(text) = reply else {
+                return std::result::Result::Ok(reply);
+            };
+            let review = self.guardrails.review(&text);
+            turn::record(|metadata| metadata.guardrails.extend(review.findings.clone()));
+            if !review.needs_reask() || reasks >= self.guardrails.config.max_reasks {
+                return std::result::Result::Ok(Reply::Text(review.response));
+            }
+
+            reasks += 1;
+            warn!("Guardrails rejected defiproman answer, asking again: {:?}", review.findings);
+            let mut retry_history = history.clone();
+            retry_history.push(Message {
+                role: "user".to_string(),
+                content: prompt.to_string(),
+            });
+            retry_history.push(Message {
+                role: "assistant".to_string(),
+                content: text,
+            });
+            reply = complete(agent, preamble.clone(), &review.feedback(), retry_history).await?;
+        }
+    }
+
+    /// Canned answers for intents that don't need an LLM
+    fn deterministic_answer(&self, intent: Intent, prompt: &str) -> Option<String> {
+        match intent {
+            Intent::Greeting => Some(
+                "GM Starknet brother! Ask me about yields, Starknet DeFi protocols or share your wallet address and I'll review your portfolio.".to_string(),
+            ),
+            Intent::OffTopic => Some(
+                "Sorry Starknet brother, I only talk about DeFi strategies on Starknet. Ask me about yields, protocols or your portfolio!".to_string(),
+            ),
+            Intent::YieldQuestion => {
+                // only answer directly when the question targets exactly one token we have data for
+                let normalized = prompt.to_lowercase();
+                let yields = &self.tools.analyzer_tool.yields_data;
+                let mut symbols: Vec<&str> = yields
+                    .iter()
+                    .map(|y| y.token.name.as_str())
+                    .filter(|symbol| {
+                        normalized
+                            .split(|c: char| !c.is_alphanumeric())
+                            .any(|w| w == symbol.to_lowercase())
+                    })
+                    .collect();
+                symbols.sort_unstable();
+                symbols.dedup();
+                match symbols.as_slice() {
+                    [symbol] => {
+                        cite(Source::yields(self.tools.analyzer_tool.as_of));
+                        Some(format_yields_data(
+                            yields.iter().filter(|y| y.token.name == *symbol).cloned().collect(),
+                        ))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    async fn add_to_history(&self, session_id: &str, role: &str, content: String) -> Result<(), PromptError> {
         self.chat_history_sender
-            .send(ChatHistoryCommand::AddMessage(current_session.clone(),
+            .send(ChatHistoryCommand::AddMessage(
+                session_id.to_string(),
                 Message {
-                role: "assistant".to_string(),
-                content: response.clone(),
-            }))
+                    role: role.to_string(),
+                    content,
+                },
+            ))
             .await
             .map_err(|e| PromptError::CompletionError(
                 CompletionError::ResponseError(e.to_string())
-            ))?;
-    
-        Ok(response).map_err(|e|PromptError::CompletionError(CompletionError::ResponseError(e.to_string())))
+            ))
+    }
+
+    pub fn guardrails(&self) -> &Guardrails {
+        &self.guardrails
     }
-    
 
     pub async fn debug_print_history(&self, current_session: String) {
         let (tx, rx) = oneshot::channel();
-        if let std::result::Result::Ok(_) = self.chat_history_sender.send(ChatHistoryCommand::GetHistory(current_session.clone(), tx)).await {
+        if self.chat_history_sender.send(ChatHistoryCommand::GetHistory(current_session.clone(), tx)).await.is_ok() {
             if let std::result::Result::Ok(history) = rx.await {
                 info!("Current chat history:");
                 for msg in history {
@@ -118,76 +743,74 @@ impl<M: CompletionModel + 'static> Navigator<M> {
             }
         }
     }
-//    pub async fn update_chat_history(&mut self, content: &str) -> Result<(), anyhow::Error> {
-//        info!("Starting chat history update...");
-//        // Add debug info about the current state
-//        info!(
-//            "Current chat history length: {}",
-//            self.chat_history.lock().await.len()
-//        );
-//
-//        self.chat_history.lock().await.push(Message {
-//            role: "system".to_string(),
-//            content: content.to_string(),
-//        });
-//
-//        info!(
-//            "Message pushed to chat history. New length: {}",
-//            self.chat_history.lock().await.len()
-//        );
-//        Ok(())
-//    }
-}
-
-impl<M: CompletionModel> Chat for Navigator<M> {
+}
+
+impl<M: CompletionModel + 'static> Chat for Navigator<M> {
     async fn chat(
         &self,
         prompt: &str,
         chat_history: Vec<rig::completion::Message>,
     ) -> Result<String, rig::completion::PromptError> {
-        let refined_prompt = self.navigator.prompt(prompt).await?;
+        let refined_prompt = complete(&self.navigator, self.navigator_preamble("")?, prompt, vec![])
+            .await?
+            .into_inner();
 
-        self.defiproman
-            .chat(&refined_prompt, chat_history.clone())
+        let specialist = self.specialist(Intent::Unclassified)?;
+        self.ask_specialist(&specialist.agent, self.preamble(&specialist.spec.preamble, "")?, &refined_prompt, chat_history)
             .await
+            .map(Reply::into_inner)
+    }
+}
+
+/// Per-request switches of a turn
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TurnOptions {
+    /// Also return the recommended actions as a [`Strategy`]
+    pub structured: bool,
+}
+
+/// What an agent answered with
+enum Reply {
+    Text(String),
+    /// Output of the tool the model called, returned verbatim
+    ToolOutput(String),
+}
+
+impl Reply {
+    fn into_inner(self) -> String {
+        match self {
+            Reply::Text(text) | Reply::ToolOutput(text) => text,
+        }
+    }
+}
+
+/// Same as `Agent::chat` but with `preamble` replacing the one the agent was built with
+async fn complete<M: CompletionModel>(
+    agent: &Agent<M>,
+    preamble: String,
+    prompt: &str,
+    chat_history: Vec<Message>,
+) -> Result<Reply, PromptError> {
+    let response = agent.completion(prompt, chat_history).await?.preamble(preamble).send().await?;
+    match response.choice {
+        ModelChoice::Message(message) => std::result::Result::Ok(Reply::Text(message)),
+        ModelChoice::ToolCall(name, args) => {
+            turn::record(|metadata| metadata.tool_calls.push(name.clone()));
+            std::result::Result::Ok(Reply::ToolOutput(agent.tools.call(&name, args.to_string()).await?))
+        }
     }
 }
 
 pub async fn launch<M: CompletionModel + 'static>(
-    backend: &Backend<M>,
+    _backend: &Backend<M>,
 ) -> Result<(), anyhow::Error> {
-//    let nav_agent = backend
-//        .app_state
-//        .lock()
-//        .await
-//        .agent_state
-//        .clone()
-//        .expect("No agent available")
-//        .navigator;
-//    let result = nav_agent
-//        .lock()
-//        .await
-//        .process_prompt("Hi from space.")
-//        .await
-//        .expect("Failed processing prompt");
-    println!("GOOD," );
+    info!("Agent launched");
 
     Ok(())
 }
 
-pub fn agent_build<M: CompletionModel>(model: M) -> Result<Agent<M>, anyhow::Error> {
-    // Load in all the rust examples
-    let examples = FileLoader::with_glob("agents/*.rs")?
-        .read_with_path()
-        .ignore_errors()
-        .into_iter();
-    // Create an agent with multiple context documents
-    let agent = examples
-        .fold(AgentBuilder::new(model), |builder, (path, content)| {
-            builder.context(format!("Your agents knowledge {:?}:\n{}", path, content).as_str())
-        })
-        .preamble("You are a navigator in the Brother Yield project, made for assisting the user with DeFi strategy optimization on Starknet. You have your own AI defi expert, called LiquidityProMan(LPM). So when user asks you a question you will be the middleman: refine the user prompt and use your refined version to prompt LPM. Keep your prompts shorter than 2 lines, start by 'brother defiproman {your_refined_prompt}'. ex: 'user:' 'hello' 'navigator': 'brother defiproman hello'. Be sure to rely the greetings properly.")
-        .build();
-
-    Ok(agent)
+/// The navigator agent, its context is the capabilities manifest of the registry and its
+/// preamble the `navigator` prompt template
+pub fn agent_build<M: CompletionModel>(model: AgentBuilder<M>, registry: &AgentRegistry) -> Agent<M> {
+    model.context(&registry.manifest()).build()
 }
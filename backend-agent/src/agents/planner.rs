@@ -0,0 +1,195 @@
+use super::guardrails::Guardrails;
+use super::turn;
+use crate::usage::estimate_tokens;
+use rig::agent::Agent;
+use rig::completion::{Completion, CompletionError, CompletionModel, Message, ModelChoice, PromptError};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+/// Appended to the preamble while planning
+const PLANNER_INSTRUCTIONS: &str = "Answer step by step: call one tool at a time, read its result, then call the next tool you need \
+(portfolio, yields, risk comparison) until you can give the final allocation. When you have enough information, answer the user directly.";
+const CONTINUE_PROMPT: &str = "Continue: call the next tool you need, or give your final answer.";
+const FINAL_PROMPT: &str = "You are out of steps, give your final answer now with what you have. Don't call any tool.";
+
+/// Bounds of a planning run
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PlannerConfig {
+    /// Model calls per run, the last one has to answer
+    pub max_steps: usize,
+    /// Estimated tokens sent and received over the run
+    pub max_tokens: u64,
+}
+
+impl Default for PlannerConfig {
+    fn default() -> Self {
+        Self {
+            max_steps: 6,
+            max_tokens: 24_000,
+        }
+    }
+}
+
+impl PlannerConfig {
+    /// Defaults overridden by `PLANNER_MAX_STEPS` and `PLANNER_MAX_TOKENS`
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let parse = |name: &str| std::env::var(name).ok().and_then(|value| value.parse().ok());
+        Self {
+            max_steps: parse("PLANNER_MAX_STEPS").unwrap_or(default.max_steps),
+            max_tokens: parse("PLANNER_MAX_TOKENS").unwrap_or(default.max_tokens as usize) as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepKind {
+    ToolCall,
+    /// Final answer rejected by the guardrails, the model is asked to fix it
+    Revision,
+    Answer,
+}
+
+/// One iteration of the planning loop as exposed in the turn metadata.
+/// Tool outputs stay in the scratchpad, only their size is kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub kind: StepKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub args: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub output_chars: usize,
+    /// Estimated tokens spent by the run so far
+    pub tokens: u64,
+}
+
+/// Bounded ReAct loop: the agent calls tools one at a time, seeing each result, until it answers
+pub struct Planner<'a, M: CompletionModel> {
+    agent: &'a Agent<M>,
+    config: PlannerConfig,
+    guardrails: Option<&'a Guardrails>,
+}
+
+impl<'a, M: CompletionModel> Planner<'a, M> {
+    pub fn new(agent: &'a Agent<M>, config: PlannerConfig) -> Self {
+        Self {
+            agent,
+            config,
+            guardrails: None,
+        }
+    }
+
+    /// Final answers are reviewed, failing ones cost a step to be revised
+    pub fn with_guardrails(mut self, guardrails: &'a Guardrails) -> Self {
+        self.guardrails = Some(guardrails);
+        self
+    }
+
+    pub async fn run(&self, preamble: &str, prompt: &str, history: Vec<Message>) -> Result<String, PromptError> {
+        let preamble = format!("{preamble}\n\n{PLANNER_INSTRUCTIONS}");
+        // never shown to the user nor added to the chat history
+        let mut scratchpad = history;
+        let mut next_prompt = prompt.to_string();
+        let mut tokens = 0;
+        let mut reasks = 0;
+
+        for step in 1..=self.config.max_steps.max(1) {
+            let last = step >= self.config.max_steps || tokens >= self.config.max_tokens;
+            if last && step > 1 {
+                next_prompt = FINAL_PROMPT.to_string();
+            }
+            tokens += estimate_tokens(
+                preamble.len() + next_prompt.len() + scratchpad.iter().map(|m| m.content.len()).sum::<usize>(),
+            );
+            let response = self
+                .agent
+                .completion(&next_prompt, scratchpad.clone())
+                .await?
+                .preamble(preamble.clone())
+                .send()
+                .await?;
+            scratchpad.push(message("user", &next_prompt));
+
+            match response.choice {
+                ModelChoice::Message(text) => {
+                    tokens += estimate_tokens(text.len());
+                    let review = self.guardrails.map(|guardrails| guardrails.review(&text));
+                    if let Some(review) = &review {
+                        turn::record(|metadata| metadata.guardrails.extend(review.findings.clone()));
+                    }
+                    let max_reasks = self.guardrails.map_or(0, |guardrails| guardrails.config.max_reasks);
+                    match review {
+                        Some(review) if review.needs_reask() && reasks < max_reasks && !last => {
+                            reasks += 1;
+                            record(step, PlanStep::new(StepKind::Revision, text.len(), tokens));
+                            scratchpad.push(message("assistant", &text));
+                            next_prompt = review.feedback();
+                        }
+                        review => {
+                            record(step, PlanStep::new(StepKind::Answer, text.len(), tokens));
+                            return Ok(review.map_or(text, |review| review.response));
+                        }
+                    }
+                }
+                ModelChoice::ToolCall(name, args) if !last => {
+                    turn::record(|metadata| metadata.tool_calls.push(name.clone()));
+                    let mut plan_step = PlanStep::new(StepKind::ToolCall, 0, tokens);
+                    let observation = match self.agent.tools.call(&name, args.to_string()).await {
+                        Ok(output) => output,
+                        // the model gets a chance to recover, e.g. by calling another tool
+                        Err(e) => {
+                            warn!("Planner tool {} failed: {}", name, e);
+                            plan_step.error = Some(e.to_string());
+                            format!("Error: {e}")
+                        }
+                    };
+                    tokens += estimate_tokens(observation.len());
+                    plan_step.tokens = tokens;
+                    plan_step.output_chars = observation.len();
+                    plan_step.tool = Some(name.clone());
+                    plan_step.args = Some(args.clone());
+                    record(step, plan_step);
+
+                    scratchpad.push(message("assistant", &format!("Calling {name} with {args}")));
+                    scratchpad.push(message("user", &format!("Result of {name}:\n{observation}")));
+                    next_prompt = CONTINUE_PROMPT.to_string();
+                }
+                ModelChoice::ToolCall(name, _) => {
+                    return Err(PromptError::CompletionError(CompletionError::ResponseError(format!(
+                        "Planner out of steps, the model still called {name}"
+                    ))));
+                }
+            }
+        }
+        unreachable!("the last step either answers or fails")
+    }
+}
+
+impl PlanStep {
+    fn new(kind: StepKind, output_chars: usize, tokens: u64) -> Self {
+        Self {
+            kind,
+            tool: None,
+            args: None,
+            error: None,
+            output_chars,
+            tokens,
+        }
+    }
+}
+
+fn record(step: usize, plan_step: PlanStep) {
+    info!("Planner step {}: {:?} {:?}", step, plan_step.kind, plan_step.tool);
+    turn::record(|metadata| metadata.plan.push(plan_step));
+}
+
+fn message(role: &str, content: &str) -> Message {
+    Message {
+        role: role.to_string(),
+        content: content.to_string(),
+    }
+}
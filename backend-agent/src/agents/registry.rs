@@ -0,0 +1,118 @@
+use super::navigator::Intent;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Default registry file, relative to the working directory like `prompts/`
+pub const DEFAULT_AGENTS_FILE: &str = "agents.json";
+/// The router, every other agent is a specialist it can hand prompts to
+pub const NAVIGATOR: &str = "navigator";
+
+#[derive(Debug, thiserror::Error)]
+pub enum RegistryError {
+    #[error("Failed reading agents registry: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid agents registry: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Agent {0} is not in the registry")]
+    MissingAgent(String),
+    #[error("No model given for agent {0}")]
+    MissingModel(String),
+    #[error("Agent {0} uses unknown tool {1}")]
+    UnknownTool(String, String),
+    #[error("No specialist handles {0:?}")]
+    NoSpecialist(Intent),
+}
+
+/// An agent as declared in the registry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentSpec {
+    pub name: String,
+    /// What the agent is for, shown to the navigator
+    pub description: String,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Tool names, see `navigator::Tools`
+    #[serde(default)]
+    pub tools: Vec<String>,
+    /// Model profile, `<MODEL>_LLM_*` variables
+    pub model: String,
+    /// Prompt template of the preamble
+    pub preamble: String,
+    /// Intents routed to this agent
+    #[serde(default)]
+    pub intents: Vec<Intent>,
+    /// Gets the insights and protocols knowledge as dynamic context
+    #[serde(default)]
+    pub retrieval: bool,
+    /// Reviews the specialists' drafts instead of answering prompts, see `risk_officer`
+    #[serde(default)]
+    pub reviewer: bool,
+}
+
+/// Agents the navigator works with, `agents.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentRegistry {
+    pub agents: Vec<AgentSpec>,
+}
+
+impl AgentRegistry {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RegistryError> {
+        let registry: Self = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        registry.get(NAVIGATOR)?;
+        Ok(registry)
+    }
+
+    /// Registry from `AGENTS_FILE`, `agents.json` by default. Its reviewer only reviews when
+    /// `RISK_REVIEWS_ENABLED` is `true`, each review is another model call per answer.
+    pub fn from_env() -> Result<Self, RegistryError> {
+        let registry = Self::load(std::env::var("AGENTS_FILE").unwrap_or_else(|_| DEFAULT_AGENTS_FILE.to_string()))?;
+        match std::env::var("RISK_REVIEWS_ENABLED").is_ok_and(|enabled| enabled == "true") {
+            true => Ok(registry),
+            false => Ok(registry.without_reviewer()),
+        }
+    }
+
+    /// Same agents, drafts sent without review
+    pub fn without_reviewer(mut self) -> Self {
+        self.agents.retain(|spec| !spec.reviewer);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Result<&AgentSpec, RegistryError> {
+        self.agents
+            .iter()
+            .find(|spec| spec.name == name)
+            .ok_or_else(|| RegistryError::MissingAgent(name.to_string()))
+    }
+
+    pub fn specialists(&self) -> impl Iterator<Item = &AgentSpec> {
+        self.agents.iter().filter(|spec| spec.name != NAVIGATOR && !spec.reviewer)
+    }
+
+    /// The agent reviewing drafts before they're sent, if any
+    pub fn reviewer(&self) -> Option<&AgentSpec> {
+        self.agents.iter().find(|spec| spec.reviewer)
+    }
+
+    /// First specialist declaring `intent`
+    pub fn specialist_for(&self, intent: Intent) -> Result<&AgentSpec, RegistryError> {
+        self.specialists()
+            .find(|spec| spec.intents.contains(&intent))
+            .ok_or(RegistryError::NoSpecialist(intent))
+    }
+
+    /// What each specialist can do, given to the navigator as context
+    pub fn manifest(&self) -> String {
+        let mut manifest = "Agents you can forward prompts to:\n".to_string();
+        for spec in self.specialists() {
+            manifest.push_str(&format!("\n## {}\n{}\n", spec.name, spec.description));
+            if !spec.capabilities.is_empty() {
+                manifest.push_str(&format!("Capabilities: {}\n", spec.capabilities.join(", ")));
+            }
+            if !spec.tools.is_empty() {
+                manifest.push_str(&format!("Tools: {}\n", spec.tools.join(", ")));
+            }
+        }
+        manifest
+    }
+}
@@ -0,0 +1,222 @@
+use super::turn;
+use crate::types::{ProtocolYield, RiskProfile};
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use rig::agent::Agent;
+use rig::completion::{Completion, CompletionModel, ModelChoice, ToolDefinition};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Function the reviewer calls to give its verdict
+pub const SUBMIT_VERDICT: &str = "submit_verdict";
+/// How many reviews are kept in memory for audit
+const RISK_LOG_CAPACITY: usize = 1000;
+/// Default risk log file, relative to the working directory like the portfolio history
+pub const DEFAULT_RISK_LOG_FILE: &str = "risk_reviews.jsonl";
+
+#[derive(Debug, thiserror::Error)]
+pub enum RiskLogError {
+    #[error("Failed accessing risk log: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid risk log line {0}: {1}")]
+    Json(usize, serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// The answer fits the user's risk profile as is
+    Approve,
+    /// The answer can be sent with the warnings appended
+    Annotate,
+    /// The answer is unsuitable, the specialist has to answer again
+    Revise,
+}
+
+/// Arguments of [`SUBMIT_VERDICT`]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct VerdictArgs {
+    pub verdict: Verdict,
+    /// Short warnings for the user, required to annotate
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// What the specialist must change, required to revise
+    pub reason: Option<String>,
+}
+
+/// A review of a draft answer, kept in the turn metadata and the risk log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiskReview {
+    pub session_id: String,
+    pub request_id: String,
+    pub risk_profile: RiskProfile,
+    pub draft: String,
+    /// `None` when the reviewer failed, the draft is then sent as is
+    pub verdict: Option<Verdict>,
+    pub warnings: Vec<String>,
+    pub reason: Option<String>,
+    pub error: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Log of risk reviews: the latest ones in memory, every one appended to a JSONL file when one
+/// is set. Clones share the log.
+#[derive(Clone, Default)]
+pub struct RiskLog {
+    reviews: Arc<RwLock<VecDeque<RiskReview>>>,
+    file: Option<PathBuf>,
+}
+
+impl RiskLog {
+    /// Log kept in `path`, loading the latest reviews it already holds
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RiskLogError> {
+        let path = path.as_ref().to_path_buf();
+        let mut reviews = VecDeque::new();
+        if path.exists() {
+            for (i, line) in BufReader::new(std::fs::File::open(&path)?).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if reviews.len() >= RISK_LOG_CAPACITY {
+                    reviews.pop_front();
+                }
+                reviews.push_back(serde_json::from_str(&line).map_err(|e| RiskLogError::Json(i + 1, e))?);
+            }
+        }
+        Ok(Self {
+            reviews: Arc::new(RwLock::new(reviews)),
+            file: Some(path),
+        })
+    }
+
+    /// Log from `RISK_LOG_FILE`, `risk_reviews.jsonl` by default
+    pub fn from_env() -> Result<Self, RiskLogError> {
+        Self::open(std::env::var("RISK_LOG_FILE").unwrap_or_else(|_| DEFAULT_RISK_LOG_FILE.to_string()))
+    }
+
+    pub fn record(&self, review: RiskReview) {
+        self.append(&review);
+        let mut log = self.reviews.write();
+        if log.len() >= RISK_LOG_CAPACITY {
+            log.pop_front();
+        }
+        log.push_back(review);
+    }
+
+    pub fn reviews(&self) -> Vec<RiskReview> {
+        self.reviews.read().iter().cloned().collect()
+    }
+
+    fn append(&self, review: &RiskReview) {
+        let Some(path) = &self.file else { return };
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(review).unwrap_or_default()));
+        if let Err(e) = result {
+            warn!("Failed persisting risk review: {}", e);
+        }
+    }
+}
+
+/// Second-pass reviewer checking drafts against the user's risk profile and the pools' risk scores
+pub struct RiskOfficer<M: CompletionModel> {
+    pub agent: Agent<M>,
+    /// Prompt template of the reviewer preamble
+    pub preamble: String,
+    pub log: RiskLog,
+}
+
+impl<M: CompletionModel> RiskOfficer<M> {
+    pub fn new(agent: Agent<M>, preamble: &str, log: RiskLog) -> Self {
+        Self {
+            agent,
+            preamble: preamble.to_string(),
+            log,
+        }
+    }
+
+    /// Reviews `draft`, recording the review in the turn and the log
+    pub async fn review(
+        &self,
+        preamble: String,
+        draft: &str,
+        risk_profile: RiskProfile,
+        yields: &[ProtocolYield],
+    ) -> RiskReview {
+        let prompt = review_prompt(draft, risk_profile, yields);
+        let response = async {
+            self.agent
+                .completion(&prompt, vec![])
+                .await?
+                .preamble(preamble)
+                .tool(submit_verdict_definition())
+                .send()
+                .await
+        }
+        .await;
+
+        let verdict = match response.map(|response| response.choice) {
+            Ok(ModelChoice::ToolCall(name, args)) if name == SUBMIT_VERDICT => {
+                serde_json::from_value::<VerdictArgs>(args).map_err(|e| format!("Invalid verdict: {e}"))
+            }
+            Ok(choice) => Err(format!("No verdict submitted: {choice:?}")),
+            Err(e) => Err(e.to_string()),
+        };
+        let (session_id, request_id) = turn::current(|metadata| (metadata.session_id.clone(), metadata.request_id.clone()))
+            .unwrap_or_default();
+        let mut review = RiskReview {
+            session_id,
+            request_id,
+            risk_profile,
+            draft: draft.to_string(),
+            verdict: None,
+            warnings: vec![],
+            reason: None,
+            error: None,
+            timestamp: Utc::now(),
+        };
+        match verdict {
+            Ok(args) => {
+                info!("Risk officer verdict: {:?}", args.verdict);
+                review.verdict = Some(args.verdict);
+                review.warnings = args.warnings;
+                review.reason = args.reason;
+            }
+            Err(e) => {
+                warn!("Risk review failed, sending the draft as is: {}", e);
+                review.error = Some(e);
+            }
+        }
+
+        turn::record(|metadata| metadata.risk_reviews.push(review.clone()));
+        self.log.record(review.clone());
+        review
+    }
+}
+
+pub fn submit_verdict_definition() -> ToolDefinition {
+    let schema = schemars::schema_for!(VerdictArgs);
+    ToolDefinition {
+        name: SUBMIT_VERDICT.to_string(),
+        description: "Approve the draft answer, annotate it with warnings for the user, or ask for a revision".to_string(),
+        parameters: serde_json::to_value(schema).unwrap_or_default(),
+    }
+}
+
+fn review_prompt(draft: &str, risk_profile: RiskProfile, yields: &[ProtocolYield]) -> String {
+    let pools = yields
+        .iter()
+        .map(|y| format!("- {} {:?}: risk score {:.0}/100, APY {:.2}%", y.token.name, y.pool_type, y.risk_score, y.apy))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("User risk profile: {risk_profile}\n\nCurrent pools:\n{pools}\n\nDraft answer:\n{draft}")
+}
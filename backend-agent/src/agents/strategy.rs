@@ -0,0 +1,137 @@
+use super::navigator::PROTOCOLS;
+use crate::types::{PoolType, ProtocolYield};
+use rig::completion::ToolDefinition;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Function the model calls to hand over its recommendations as structured data
+pub const SUBMIT_STRATEGY: &str = "submit_strategy";
+/// Follow-up prompt asking the model to structure the answer it just gave
+pub const STRATEGY_PROMPT: &str = "Now submit the strategy from your last answer by calling submit_strategy. Only include actions you actually recommended.";
+
+/// One recommended action, as produced by the model
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StrategyAction {
+    /// Starknet protocol, e.g. Ekubo or Nostra
+    pub protocol: String,
+    /// Pool or pair, e.g. STRK/USDC
+    pub pool: String,
+    pub pool_type: PoolType,
+    /// Token symbol to deposit
+    pub token: String,
+    /// Amount of `token` to deposit
+    pub amount: Option<f64>,
+    /// Share of the user's portfolio, 0 to 100
+    pub portfolio_percentage: Option<f64>,
+    /// Expected APY in percent
+    pub expected_apy: f64,
+    /// Risk score, 0 (safe) to 100 (degen)
+    pub risk_score: f64,
+    pub rationale: String,
+}
+
+/// Arguments of [`SUBMIT_STRATEGY`]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StrategyPlan {
+    pub actions: Vec<StrategyAction>,
+}
+
+/// Live numbers of the pool an action targets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveYield {
+    pub apy: f64,
+    pub tvl: f64,
+    pub volume_24h: f64,
+    pub risk_score: f64,
+    pub pool_type: PoolType,
+}
+
+/// An action checked against the current yields
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyCard {
+    #[serde(flatten)]
+    pub action: StrategyAction,
+    pub live: LiveYield,
+    pub warnings: Vec<String>,
+}
+
+/// Structured recommendations returned next to the prose answer
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Strategy {
+    pub cards: Vec<StrategyCard>,
+    /// Actions dropped during validation and why
+    pub rejected: Vec<String>,
+}
+
+pub fn submit_strategy_definition() -> ToolDefinition {
+    let schema = schemars::schema_for!(StrategyPlan);
+    ToolDefinition {
+        name: SUBMIT_STRATEGY.to_string(),
+        description: "Submit the recommended strategy as a list of actions the user can take".to_string(),
+        parameters: serde_json::to_value(schema).unwrap_or_default(),
+    }
+}
+
+/// Checks `plan` against `yields`: unknown tokens and protocols are rejected,
+/// APY, risk score and pool type are replaced by the live numbers
+pub fn validate(plan: StrategyPlan, yields: &[ProtocolYield]) -> Strategy {
+    let mut strategy = Strategy::default();
+    for mut action in plan.actions {
+        let protocol = action.protocol.to_lowercase();
+        if !PROTOCOLS.iter().any(|known| protocol.contains(known)) {
+            strategy.rejected.push(format!("{}: unsupported protocol {}", action.pool, action.protocol));
+            continue;
+        }
+        let pools: Vec<&ProtocolYield> = yields
+            .iter()
+            .filter(|y| y.token.name.eq_ignore_ascii_case(&action.token))
+            .collect();
+        if pools.is_empty() {
+            strategy.rejected.push(format!("{}: no yields data for {}", action.pool, action.token));
+            continue;
+        }
+
+        let mut warnings = vec![];
+        let pool = pools
+            .iter()
+            .find(|y| y.pool_type == action.pool_type)
+            .copied()
+            .unwrap_or_else(|| {
+                warnings.push(format!("No {:?} pool for {}, showing {:?}", action.pool_type, action.token, pools[0].pool_type));
+                pools[0]
+            });
+        if (action.expected_apy - pool.apy).abs() > pool.apy.abs() * 0.1 + 0.01 {
+            warnings.push(format!("Quoted {:.2}% APY, live is {:.2}%", action.expected_apy, pool.apy));
+        }
+        if action.portfolio_percentage.is_some_and(|share| !(0.0..=100.0).contains(&share)) {
+            warnings.push("Portfolio share out of range".to_string());
+            action.portfolio_percentage = None;
+        }
+        action.expected_apy = pool.apy;
+        action.risk_score = pool.risk_score;
+        action.pool_type = pool.pool_type.clone();
+        if action.pool_type == PoolType::Degen {
+            warnings.push("Degen pool, high risk of loss".to_string());
+        }
+
+        strategy.cards.push(StrategyCard {
+            live: LiveYield {
+                apy: pool.apy,
+                tvl: pool.tvl,
+                volume_24h: pool.volume_24h,
+                risk_score: pool.risk_score,
+                pool_type: pool.pool_type.clone(),
+            },
+            action,
+            warnings,
+        });
+    }
+
+    let total_share: f64 = strategy.cards.iter().filter_map(|card| card.action.portfolio_percentage).sum();
+    if total_share > 100.0 {
+        if let Some(card) = strategy.cards.first_mut() {
+            card.warnings.push(format!("Actions allocate {total_share:.0}% of the portfolio"));
+        }
+    }
+    strategy
+}
@@ -0,0 +1,125 @@
+use super::guardrails::GuardrailFinding;
+use super::navigator::{Intent, Route};
+use super::planner::PlanStep;
+use super::risk_officer::RiskReview;
+use super::strategy::Strategy;
+use crate::address::ContractAddress;
+use crate::agent_tools::portfolio::BalanceError;
+use crate::sources::Source;
+use crate::types::Expertise;
+use crate::usage::UsageTotals;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+
+tokio::task_local! {
+    static CURRENT_TURN: Arc<Mutex<TurnMetadata>>;
+}
+
+/// One completion served for an agent during a turn
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelCall {
+    pub agent: String,
+    /// Model that actually answered, `provider/model`
+    pub model: String,
+    pub attempts: u32,
+    /// True when the primary model failed and a fallback answered
+    pub fallback: bool,
+}
+
+/// Everything worth knowing about how a turn was answered, returned next to the response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TurnMetadata {
+    pub request_id: String,
+    pub session_id: String,
+    /// Wallet the session is working with, once known
+    pub wallet: Option<ContractAddress>,
+    /// Other wallets the session proved it owns, their holdings are combined with the wallet's
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub linked_wallets: Vec<ContractAddress>,
+    /// Expertise the answer was tailored to
+    pub expertise: Expertise,
+    pub intent: Option<Intent>,
+    pub route: Option<Route>,
+    /// Registry agent that answered, when a specialist was asked
+    pub agent: Option<String>,
+    pub model_calls: Vec<ModelCall>,
+    /// Tools the agents called, in order
+    pub tool_calls: Vec<String>,
+    /// Steps of the planning loop, when the turn was planned
+    pub plan: Vec<PlanStep>,
+    /// Context the answer was built from, see [`Source`]
+    pub sources: Vec<Source>,
+    /// Tokens held by the wallet fetched during the turn
+    pub holdings: Vec<String>,
+    /// Tokens whose balance couldn't be fetched, the holdings may be incomplete
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub balance_errors: Vec<BalanceError>,
+    /// Prompt templates used, `name/version`
+    pub prompt_versions: Vec<String>,
+    /// Risk officer verdicts on the drafts of this turn
+    pub risk_reviews: Vec<RiskReview>,
+    /// Guardrail checks that failed on defiproman's answers
+    pub guardrails: Vec<GuardrailFinding>,
+    /// Tokens and cost of every model call made during the turn
+    pub usage: UsageTotals,
+}
+
+impl TurnMetadata {
+    pub fn new(session_id: &str) -> Self {
+        Self {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            session_id: session_id.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// What tools can rely on about the turn they run in. Set by the server, never by the model.
+#[derive(Debug, Clone)]
+pub struct ToolContext {
+    pub request_id: String,
+    pub session_id: String,
+    /// Wallet the user shared in this turn or earlier in the session
+    pub wallet: Option<ContractAddress>,
+    /// Wallets linked to the session by signature
+    pub linked_wallets: Vec<ContractAddress>,
+}
+
+/// Response of a turn along with its metadata
+#[derive(Debug, Clone, Serialize)]
+pub struct TurnOutcome {
+    pub response: String,
+    pub metadata: TurnMetadata,
+    /// Structured recommendations, when asked for and the model gave some
+    pub strategy: Option<Strategy>,
+}
+
+/// Runs `fut` as a turn; models and tools called inside can record into the metadata through [`record`]
+pub async fn scope<F: Future>(metadata: TurnMetadata, fut: F) -> (F::Output, TurnMetadata) {
+    let turn = Arc::new(Mutex::new(metadata));
+    let output = CURRENT_TURN.scope(turn.clone(), fut).await;
+    let metadata = turn.lock().clone();
+    (output, metadata)
+}
+
+/// Updates the metadata of the current turn, no-op outside of [`scope`]
+pub fn record(f: impl FnOnce(&mut TurnMetadata)) {
+    let _ = CURRENT_TURN.try_with(|turn| f(&mut turn.lock()));
+}
+
+/// Reads from the current turn metadata, `None` outside of [`scope`]
+pub fn current<T>(f: impl FnOnce(&TurnMetadata) -> T) -> Option<T> {
+    CURRENT_TURN.try_with(|turn| f(&turn.lock())).ok()
+}
+
+/// Context of the current turn for tool execution, `None` outside of [`scope`]
+pub fn tool_context() -> Option<ToolContext> {
+    current(|metadata| ToolContext {
+        request_id: metadata.request_id.clone(),
+        session_id: metadata.session_id.clone(),
+        wallet: metadata.wallet,
+        linked_wallets: metadata.linked_wallets.clone(),
+    })
+}
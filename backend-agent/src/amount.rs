@@ -0,0 +1,217 @@
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject};
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use starknet::core::types::{Felt, U256};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Largest number of decimals an amount can have, 10^77 is the largest power of ten in a u256
+pub const MAX_DECIMALS: u8 = 77;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AmountError {
+    #[error("Empty u256 result")]
+    Empty,
+    #[error("u256 limb {0} doesn't fit 128 bits")]
+    InvalidLimb(String),
+    #[error("Amount overflows a u256")]
+    Overflow,
+    #[error("Amount is negative")]
+    Underflow,
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Amounts have {0} and {1} decimals")]
+    DecimalsMismatch(u8, u8),
+    #[error("{0} decimals, at most {MAX_DECIMALS} are supported")]
+    TooManyDecimals(u8),
+    #[error("Invalid amount {0:?}")]
+    Parse(String),
+}
+
+/// Exact token amount: the raw ERC-20 integer and the token's decimals.
+/// Serialized as a decimal string keeping every decimal, e.g. `"2.500000"` for 2.5 USDC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TokenAmount {
+    raw: U256,
+    decimals: u8,
+}
+
+impl TokenAmount {
+    pub fn new(raw: U256, decimals: u8) -> Result<Self, AmountError> {
+        if decimals > MAX_DECIMALS {
+            return Err(AmountError::TooManyDecimals(decimals));
+        }
+        Ok(Self { raw, decimals })
+    }
+
+    pub fn zero(decimals: u8) -> Self {
+        Self {
+            raw: U256::from(0u8),
+            decimals: decimals.min(MAX_DECIMALS),
+        }
+    }
+
+    /// Decodes a Cairo u256 returned as `[low, high]` felts, e.g. by `balanceOf`
+    pub fn from_u256_felts(felts: &[Felt], decimals: u8) -> Result<Self, AmountError> {
+        let limb = |i: usize| -> Result<u128, AmountError> {
+            felts.get(i).map_or(Ok(0), |felt| {
+                u128::try_from(*felt).map_err(|_| AmountError::InvalidLimb(format!("{felt:#x}")))
+            })
+        };
+        if felts.is_empty() {
+            return Err(AmountError::Empty);
+        }
+        Self::new(U256::from_words(limb(0)?, limb(1)?), decimals)
+    }
+
+    /// Parses a decimal string, rejecting more fractional digits than `decimals`
+    pub fn parse(value: &str, decimals: u8) -> Result<Self, AmountError> {
+        let invalid = || AmountError::Parse(value.to_string());
+        let (integral, fractional) = value.trim().split_once('.').unwrap_or((value.trim(), ""));
+        if integral.is_empty() && fractional.is_empty()
+            || fractional.len() > decimals as usize
+            || !integral.chars().chain(fractional.chars()).all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let digits = format!("{integral}{fractional:0<width$}", width = decimals as usize);
+        let mut raw = U256::from(0u8);
+        for digit in digits.bytes() {
+            raw = checked_mul(raw, U256::from(10u8)).ok_or(AmountError::Overflow)?;
+            raw = checked_add(raw, U256::from(digit - b'0')).ok_or(AmountError::Overflow)?;
+        }
+        Self::new(raw, decimals)
+    }
+
+    pub fn raw(&self) -> U256 {
+        self.raw
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.raw == U256::from(0u8)
+    }
+
+    pub fn checked_add(self, other: Self) -> Result<Self, AmountError> {
+        self.same_decimals(&other)?;
+        let raw = checked_add(self.raw, other.raw).ok_or(AmountError::Overflow)?;
+        Ok(Self { raw, ..self })
+    }
+
+    pub fn checked_sub(self, other: Self) -> Result<Self, AmountError> {
+        self.same_decimals(&other)?;
+        if other.raw > self.raw {
+            return Err(AmountError::Underflow);
+        }
+        Ok(Self {
+            raw: self.raw - other.raw,
+            ..self
+        })
+    }
+
+    /// `self * numerator / denominator` rounded down, e.g. a share of pool reserves
+    pub fn mul_div(self, numerator: U256, denominator: U256) -> Result<Self, AmountError> {
+        let zero = U256::from(0u8);
+        if denominator == zero {
+            return Err(AmountError::DivisionByZero);
+        }
+        let raw = match checked_mul(self.raw, numerator) {
+            Some(product) => product / denominator,
+            // precision loss only for amounts past 2^128
+            None => checked_mul(self.raw / denominator, numerator).ok_or(AmountError::Overflow)?,
+        };
+        Ok(Self { raw, ..self })
+    }
+
+    /// Closest `f64`, for valuations and ratios where exactness doesn't matter
+    pub fn to_f64(&self) -> f64 {
+        let raw = self.raw.low() as f64 + self.raw.high() as f64 * 2_f64.powi(128);
+        raw / 10_f64.powi(self.decimals as i32)
+    }
+
+    /// Every decimal, `2.500000` for 2.5 with 6 decimals
+    pub fn to_full_string(&self) -> String {
+        let digits = self.raw.to_string();
+        let decimals = self.decimals as usize;
+        if decimals == 0 {
+            return digits;
+        }
+        let digits = format!("{digits:0>width$}", width = decimals + 1);
+        let (integral, fractional) = digits.split_at(digits.len() - decimals);
+        format!("{integral}.{fractional}")
+    }
+
+    fn same_decimals(&self, other: &Self) -> Result<(), AmountError> {
+        match self.decimals == other.decimals {
+            true => Ok(()),
+            false => Err(AmountError::DecimalsMismatch(self.decimals, other.decimals)),
+        }
+    }
+}
+
+/// Orders amounts of the same token, amounts with different decimals aren't comparable
+impl PartialOrd for TokenAmount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (self.decimals == other.decimals).then(|| self.raw.cmp(&other.raw))
+    }
+}
+
+/// Shortest exact form, `2.5` for 2.5 with 6 decimals
+impl fmt::Display for TokenAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let full = self.to_full_string();
+        let trimmed = match full.contains('.') {
+            true => full.trim_end_matches('0').trim_end_matches('.'),
+            false => &full,
+        };
+        f.write_str(trimmed)
+    }
+}
+
+impl Serialize for TokenAmount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_full_string())
+    }
+}
+
+/// Decimals are the number of fractional digits, as serialized
+impl<'de> Deserialize<'de> for TokenAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        let decimals = value.split_once('.').map_or(0, |(_, fractional)| fractional.len());
+        let decimals = u8::try_from(decimals).map_err(|_| de::Error::custom(AmountError::Parse(value.clone())))?;
+        Self::parse(&value, decimals).map_err(de::Error::custom)
+    }
+}
+
+impl JsonSchema for TokenAmount {
+    fn schema_name() -> String {
+        "TokenAmount".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            format: Some("decimal".to_string()),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+fn u256_max() -> U256 {
+    U256::from_words(u128::MAX, u128::MAX)
+}
+
+fn checked_add(a: U256, b: U256) -> Option<U256> {
+    (b <= u256_max() - a).then(|| a + b)
+}
+
+fn checked_mul(a: U256, b: U256) -> Option<U256> {
+    let zero = U256::from(0u8);
+    (a == zero || b <= u256_max() / a).then(|| a * b)
+}
@@ -1,20 +1,29 @@
-use crate::agents::navigator::{launch, Navigator, Tools};
-use crate::types::{ProtocolYield, Token};
-use axum::extract::State;
+use crate::address::{AddressError, ContractAddress};
+use crate::agents::navigator::{launch, Navigator, RoutingDecision, Tools, TurnOptions};
+use crate::agent_tools::performance;
+use crate::agents::risk_officer::{RiskLog, RiskReview};
+use crate::history::{PortfolioChange, PortfolioHistory, Snapshot};
+use crate::agents::registry::{AgentRegistry, RegistryError};
+use crate::prompts::PromptLibrary;
+use crate::sources::Source;
+use crate::starknet_id::{self, StarknetIdError};
+use crate::types::{AggregatedPortfolio, Expertise, Portfolio, ProtocolYield};
+use crate::usage::{UsageLedger, UsageReport};
+use crate::wallets::WalletLinkError;
+use axum::extract::{Path, Query, State};
 use axum::{
-    http::{header, Method, StatusCode},
+    http::{header, HeaderMap, Method, StatusCode},
     routing::{get, post},
     Json, Router,
 };
 use parking_lot::RwLock;
 use rig::agent::AgentBuilder;
 use rig::completion::{CompletionModel, Message};
-use tokio::sync::mpsc::Sender;
+use starknet::core::types::Felt;
 use tower_http::cors::CorsLayer;
-use crate::agents::AgentState;
+use crate::agents::{strategy::Strategy, turn::TurnMetadata, AgentState};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::mpsc::Receiver;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
@@ -32,12 +41,21 @@ pub struct Backend<M: CompletionModel> {
     pub listener_addr: Arc<RwLock<Option<Url>>>,
     pub app_state: Arc<Mutex<AppState<M>>>,
     pub yields_data: Vec<ProtocolYield>,
+    pub usage: UsageLedger,
+    pub prompts: PromptLibrary,
+    pub agents: AgentRegistry,
+    /// Bearer token of the admin endpoints, `ADMIN_TOKEN`; they're disabled when unset
+    pub admin_token: Option<String>,
+    /// Reviews of the registry's reviewer, see [`RiskLog`]
+    pub risk_log: RiskLog,
 }
 
 #[derive(Clone)]
 pub struct AppState<M: CompletionModel> {
     pub agent_state: Option<AgentState<M>>,
-    pub portfolio_data: Arc<RwLock<HashMap<String, HashMap<Token, f64>>>>,
+    pub portfolio_data: Arc<RwLock<HashMap<ContractAddress, Portfolio>>>,
+    /// Every portfolio fetched, see [`PortfolioHistory`]
+    pub history: PortfolioHistory,
     pub chat_sender: mpsc::Sender<ChatHistoryCommand>,
 }
 
@@ -47,10 +65,26 @@ pub struct ApiResponse {
     message: String,
 }
 
+#[derive(Serialize, Debug)]
+pub struct PromptResponse {
+    status: String,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<TurnMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    strategy: Option<Strategy>,
+    /// Context the answer relied on, for citations
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    sources: Vec<Source>,
+}
+
 #[derive(Deserialize)]
 pub struct PromptRequest {
     prompt: String,
-    session_id: String
+    session_id: String,
+    /// Also return the recommendations as strategy cards
+    #[serde(default)]
+    structured: bool,
 }
 
 #[derive(Deserialize)]
@@ -58,43 +92,122 @@ pub struct ValidateSessionRequest {
     session_id: String,
 }
 
+/// Expertise for a session, or for a wallet linked to it
+#[derive(Deserialize)]
+pub struct ExpertiseRequest {
+    session_id: String,
+    wallet: Option<String>,
+    expertise: Expertise,
+}
+
 #[derive(Serialize)]
 pub struct YieldsResponse {
     yields: Vec<ProtocolYield>,
 }
 
+#[derive(Serialize)]
+pub struct RoutingDecisionsResponse {
+    decisions: Vec<RoutingDecision>,
+}
+
+#[derive(Serialize)]
+pub struct RiskReviewsResponse {
+    reviews: Vec<RiskReview>,
+}
+
 impl<M: CompletionModel + 'static> Backend<M> {
-    pub fn new(yields_data: Vec<ProtocolYield>, manager: ChatHistoryManager) -> Self {
-        
+    pub fn new(
+        yields_data: Vec<ProtocolYield>,
+        manager: ChatHistoryManager,
+        usage: UsageLedger,
+        prompts: PromptLibrary,
+        agents: AgentRegistry,
+    ) -> Self {
         Self {
             is_active: Arc::new(AtomicBool::new(false)),
             listener_addr: Arc::new(RwLock::new(None)),
             app_state: Arc::new(Mutex::new(AppState::new(manager.get_sender()))),
             yields_data,
+            usage,
+            prompts,
+            // no file to write without a reviewer
+            risk_log: match agents.reviewer() {
+                Some(_) => RiskLog::from_env().expect("Failed loading risk log"),
+                None => RiskLog::default(),
+            },
+            agents,
+            admin_token: std::env::var("ADMIN_TOKEN").ok().filter(|token| !token.is_empty()),
         }
     }
 
     pub async fn start(
         self,
-        nav_model: M,
-        defaigent_model: AgentBuilder<M>,
+        models: HashMap<String, AgentBuilder<M>>,
         tools: Tools<M>,
         receiver: mpsc::Receiver<ChatHistoryCommand>,
     ) -> Result<(), anyhow::Error> {
+        self.init_agents(models, tools, receiver).await?;
+        let app = self.router();
+
+        let listener = tokio::net::TcpListener::bind("0.0.0.0:8000")
+            .await
+            .expect("Listener failure");
+        info!("Listener started on 0.0.0.0:8000");
+
+        self.is_active.store(true, Ordering::SeqCst);
+        *self.listener_addr.write() = Some(Url::parse("http://0.0.0.0:8000/").unwrap());
+
+        info!(
+            "Backend is active: {} {}",
+            self.is_active.load(Ordering::SeqCst),
+            self.clone().listener_addr.read().as_ref().unwrap()
+        );
+        axum::serve(listener, app)
+            .await
+            .expect("axum serving failure");
+        Ok(())
+    }
+}
+
+impl<M: CompletionModel + 'static> Backend<M> {
+    /// Starts the chat history manager and builds the registry agents from `models`, keyed by
+    /// agent name, without binding any port
+    pub async fn init_agents(
+        &self,
+        models: HashMap<String, AgentBuilder<M>>,
+        tools: Tools<M>,
+        receiver: mpsc::Receiver<ChatHistoryCommand>,
+    ) -> Result<(), RegistryError> {
         let sessions = Arc::new(Mutex::new(HashMap::<String, Vec<Message>>::new()));
         spawn_chat_history_manager(receiver, sessions.clone());
         info!("getting sender...");
         let chat_sender = {self.app_state.lock().await.chat_sender.clone()};
         info!("got sender");
+        let navigator = Navigator::new(
+            self.agents.clone(),
+            models,
+            tools,
+            chat_sender,
+            self.prompts.clone(),
+            self.risk_log.clone(),
+        )?;
+        let routing_log = navigator.routing_log.clone();
+        let risk_log = navigator.risk_log.clone();
+        let expertise = navigator.expertise.clone();
+        let wallet_links = navigator.wallet_links.clone();
+        let starknet_id = navigator.starknet_id.clone();
         self.app_state.lock().await.agent_state = Some(AgentState {
-            navigator: Arc::new(Mutex::new(Navigator::new(
-                nav_model,
-                defaigent_model,
-                tools,
-                chat_sender,
-            ))),
+            navigator: Arc::new(navigator),
+            routing_log,
+            risk_log,
+            expertise,
+            wallet_links,
+            starknet_id,
         });
+        Ok(())
+    }
 
+    pub fn router(&self) -> Router {
         let cors = CorsLayer::new()
             .allow_origin(
                 "https://brother-yields.vercel.app"
@@ -105,32 +218,27 @@ impl<M: CompletionModel + 'static> Backend<M> {
             .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
             .allow_credentials(true);
 
-        let app = Router::new()
+        Router::new()
             .route("/init-session", get(init_session_handler))
             .route("/validate-session", post(validate_session_handler)) // Add this line
             .route("/launch", post(launch_handler))
             .route("/prompt", post(prompt_handler))
             .route("/yields", get(yields_handler))
+            .route("/portfolio/{wallet}", get(portfolio_handler))
+            .route("/portfolio/{wallet}/history", get(portfolio_history_handler))
+            .route("/portfolio/{wallet}/pnl", get(portfolio_pnl_handler))
+            .route("/starknet-id/{wallet}", get(starknet_id_handler))
+            .route("/wallets/challenge", post(wallet_challenge_handler))
+            .route("/wallets/link", post(link_wallet_handler))
+            .route("/wallets/unlink", post(unlink_wallet_handler))
+            .route("/wallets", post(wallets_handler))
+            .route("/wallets/portfolio", post(aggregated_portfolio_handler))
+            .route("/expertise", post(expertise_handler))
+            .route("/admin/usage", get(usage_handler))
+            .route("/admin/routing-decisions", get(routing_decisions_handler))
+            .route("/admin/risk-reviews", get(risk_reviews_handler))
             .layer(cors)
-            .with_state(self.clone());
-
-        let listener = tokio::net::TcpListener::bind("0.0.0.0:8000")
-            .await
-            .expect("Listener failure");
-        info!("Listener started on 0.0.0.0:8000");
-
-        self.is_active.store(true, Ordering::SeqCst);
-        *self.listener_addr.write() = Some(Url::parse("http://0.0.0.0:8000/").unwrap());
-
-        info!(
-            "Backend is active: {} {}",
-            self.is_active.load(Ordering::SeqCst),
-            self.clone().listener_addr.read().as_ref().unwrap()
-        );
-        axum::serve(listener, app)
-            .await
-            .expect("axum serving failure");
-        Ok(())
+            .with_state(self.clone())
     }
 }
 
@@ -139,12 +247,19 @@ impl<M: CompletionModel> AppState<M> {
         Self {
             agent_state: None,
             portfolio_data: Arc::new(RwLock::new(HashMap::new())),
+            history: PortfolioHistory::from_env().expect("Failed loading portfolio history"),
             chat_sender,
         }
     }
-    pub fn update_portfolio(&self, address: String, balances: HashMap<Token, f64>) {
+    /// Latest portfolio of `address`, also kept as a snapshot
+    pub fn update_portfolio(&self, address: ContractAddress, portfolio: Portfolio) {
+        self.history.record_snapshot(Snapshot {
+            wallet: address,
+            taken_at: chrono::Utc::now(),
+            portfolio: portfolio.clone(),
+        });
         let mut data = self.portfolio_data.write();
-        data.insert(address.clone(), balances);
+        data.insert(address, portfolio);
         info!("User {} portfolio updated", address);
     }
 }
@@ -174,32 +289,63 @@ pub async fn launch_handler<M: CompletionModel +'static>(
 pub async fn prompt_handler<M: CompletionModel + 'static>(
     State(backend): State<Backend<M>>,
     Json(request): Json<PromptRequest>,
-) -> (StatusCode, Json<ApiResponse>) {
+) -> (StatusCode, Json<PromptResponse>) {
     info!("received call");
-    
-    let nav_agent = backend
-        .app_state
-        .lock()
-        .await
-        .agent_state
-        .clone()
-        .expect("No agent available")
-        .navigator;
 
-    info!("appstate locked nav locked");
-    let byebye = match nav_agent.lock().await.process_prompt(&request.prompt, request.session_id).await {
-        Ok(response) => (
-            StatusCode::OK,
-            Json(ApiResponse {
-                status: "success".to_string(),
-                message: response,
+    let (nav_agent, history, linked_wallets) = {
+        let app_state = backend.app_state.lock().await;
+        let agent_state = app_state.agent_state.clone().expect("No agent available");
+        (
+            agent_state.navigator,
+            app_state.history.clone(),
+            agent_state.wallet_links.wallets(&request.session_id),
+        )
+    };
+
+    if let Err(e) = backend.usage.check_quota(&request.session_id, &linked_wallets) {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(PromptResponse {
+                status: "error".to_string(),
+                message: e.to_string(),
+                metadata: None,
+                strategy: None,
+                sources: vec![],
             }),
-        ),
+        );
+    }
+
+    let byebye = match nav_agent.process_prompt_with(
+        &request.prompt,
+        request.session_id,
+        TurnOptions {
+            structured: request.structured,
+        },
+    ).await {
+        Ok(outcome) => {
+            // kept to compare with the yield the wallet actually earns
+            if let (Some(strategy), Some(wallet)) = (&outcome.strategy, outcome.metadata.wallet) {
+                history.record_predictions(wallet, strategy);
+            }
+            (
+                StatusCode::OK,
+                Json(PromptResponse {
+                    status: "success".to_string(),
+                    message: outcome.response,
+                    sources: outcome.metadata.sources.clone(),
+                    metadata: Some(outcome.metadata),
+                    strategy: outcome.strategy,
+                }),
+            )
+        }
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ApiResponse {
+            Json(PromptResponse {
                 status: "error".to_string(),
                 message: e.to_string(),
+                metadata: None,
+                strategy: None,
+                sources: vec![],
             }),
         ),
     };
@@ -217,6 +363,327 @@ pub async fn yields_handler<M: CompletionModel>(
     )
 }
 
+fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<ApiResponse>) {
+    (
+        status,
+        Json(ApiResponse {
+            status: "error".to_string(),
+            message: message.to_string(),
+        }),
+    )
+}
+
+/// Address typed as hex or as a Starknet ID domain
+async fn wallet_address<M: CompletionModel>(
+    backend: &Backend<M>,
+    wallet: &str,
+) -> Result<ContractAddress, (StatusCode, Json<ApiResponse>)> {
+    if !starknet_id::is_domain(wallet) {
+        return wallet
+            .parse()
+            .map_err(|e: AddressError| error_response(StatusCode::BAD_REQUEST, &e.to_string()));
+    }
+    agent_state(backend)
+        .await?
+        .starknet_id
+        .resolve(wallet)
+        .await
+        .map_err(|e| match e {
+            StarknetIdError::InvalidDomain(_) | StarknetIdError::InvalidAddress(_) => {
+                error_response(StatusCode::BAD_REQUEST, &e.to_string())
+            }
+            StarknetIdError::NotFound(_) => error_response(StatusCode::NOT_FOUND, &e.to_string()),
+            StarknetIdError::Call(_) => error_response(StatusCode::BAD_GATEWAY, &e.to_string()),
+        })
+}
+
+/// Last portfolio fetched for `wallet`, valued in USD
+pub async fn portfolio_handler<M: CompletionModel + 'static>(
+    State(backend): State<Backend<M>>,
+    Path(wallet): Path<String>,
+) -> Result<Json<Portfolio>, (StatusCode, Json<ApiResponse>)> {
+    let wallet = wallet_address(&backend, &wallet).await?;
+    let portfolio = backend.app_state.lock().await.portfolio_data.read().get(&wallet).cloned();
+    portfolio
+        .map(Json)
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "No portfolio fetched for this wallet"))
+}
+
+#[derive(Serialize)]
+pub struct HistoryResponse {
+    snapshots: Vec<Snapshot>,
+}
+
+/// Every snapshot of `wallet`, oldest first
+pub async fn portfolio_history_handler<M: CompletionModel + 'static>(
+    State(backend): State<Backend<M>>,
+    Path(wallet): Path<String>,
+) -> Result<Json<HistoryResponse>, (StatusCode, Json<ApiResponse>)> {
+    let wallet = wallet_address(&backend, &wallet).await?;
+    let snapshots = backend.app_state.lock().await.history.snapshots(wallet);
+    Ok(Json(HistoryResponse { snapshots }))
+}
+
+#[derive(Deserialize)]
+pub struct PnlQuery {
+    /// Start of the period, RFC 3339
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    /// Or how many days back, 7 by default
+    days: Option<u32>,
+}
+
+/// Value change, per-token PnL and realized versus predicted yields of `wallet` over a period
+pub async fn portfolio_pnl_handler<M: CompletionModel + 'static>(
+    State(backend): State<Backend<M>>,
+    Path(wallet): Path<String>,
+    Query(query): Query<PnlQuery>,
+) -> Result<Json<PortfolioChange>, (StatusCode, Json<ApiResponse>)> {
+    let wallet = wallet_address(&backend, &wallet).await?;
+    let since = query.since.unwrap_or_else(|| {
+        chrono::Utc::now() - chrono::Duration::days(query.days.unwrap_or(performance::DEFAULT_DAYS).into())
+    });
+    let history = backend.app_state.lock().await.history.clone();
+    history
+        .change(wallet, since)
+        .map(Json)
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "Not enough snapshots for this wallet"))
+}
+
+#[derive(Serialize)]
+pub struct StarknetIdResponse {
+    address: ContractAddress,
+    /// Main Starknet ID domain of the address
+    domain: Option<String>,
+}
+
+/// Address and domain of a wallet typed as either
+pub async fn starknet_id_handler<M: CompletionModel + 'static>(
+    State(backend): State<Backend<M>>,
+    Path(wallet): Path<String>,
+) -> Result<Json<StarknetIdResponse>, (StatusCode, Json<ApiResponse>)> {
+    let address = wallet_address(&backend, &wallet).await?;
+    let domain = agent_state(&backend)
+        .await?
+        .starknet_id
+        .reverse(address)
+        .await
+        .map_err(|e| error_response(StatusCode::BAD_GATEWAY, &e.to_string()))?;
+    Ok(Json(StarknetIdResponse {
+        address,
+        domain,
+    }))
+}
+
+/// A wallet of a session, to link or unlink
+#[derive(Deserialize)]
+pub struct WalletRequest {
+    session_id: String,
+    wallet: String,
+}
+
+/// Session a request is about, in the body since the id is all it takes to act as the session
+#[derive(Deserialize)]
+pub struct SessionRequest {
+    session_id: String,
+}
+
+/// Signature of the challenge of `wallet`, as the account returns it
+#[derive(Deserialize)]
+pub struct LinkWalletRequest {
+    session_id: String,
+    wallet: String,
+    signature: Vec<Felt>,
+}
+
+#[derive(Serialize)]
+pub struct ChallengeResponse {
+    /// SNIP-12 message to sign with the wallet
+    typed_data: serde_json::Value,
+}
+
+#[derive(Serialize)]
+pub struct WalletsResponse {
+    wallets: Vec<ContractAddress>,
+}
+
+async fn agent_state<M: CompletionModel>(backend: &Backend<M>) -> Result<AgentState<M>, (StatusCode, Json<ApiResponse>)> {
+    backend
+        .app_state
+        .lock()
+        .await
+        .agent_state
+        .clone()
+        .ok_or_else(|| error_response(StatusCode::SERVICE_UNAVAILABLE, "No agent available"))
+}
+
+/// Message the wallet signs to prove it belongs to the session's user
+pub async fn wallet_challenge_handler<M: CompletionModel + 'static>(
+    State(backend): State<Backend<M>>,
+    Json(request): Json<WalletRequest>,
+) -> Result<Json<ChallengeResponse>, (StatusCode, Json<ApiResponse>)> {
+    let wallet = wallet_address(&backend, &request.wallet).await?;
+    let typed_data = agent_state(&backend).await?.wallet_links.challenge(&request.session_id, wallet);
+    Ok(Json(ChallengeResponse { typed_data }))
+}
+
+/// Links the wallet to the session once its account accepts the signature of the challenge
+pub async fn link_wallet_handler<M: CompletionModel + 'static>(
+    State(backend): State<Backend<M>>,
+    Json(request): Json<LinkWalletRequest>,
+) -> Result<Json<WalletsResponse>, (StatusCode, Json<ApiResponse>)> {
+    let wallet = wallet_address(&backend, &request.wallet).await?;
+    let links = agent_state(&backend).await?.wallet_links;
+    match links.link(&request.session_id, wallet, request.signature).await {
+        Ok(wallets) => Ok(Json(WalletsResponse { wallets })),
+        Err(e @ (WalletLinkError::InvalidSignature(_) | WalletLinkError::Expired(_))) => {
+            Err(error_response(StatusCode::UNAUTHORIZED, &e.to_string()))
+        }
+        Err(e @ WalletLinkError::Call(..)) => Err(error_response(StatusCode::BAD_GATEWAY, &e.to_string())),
+        Err(e) => Err(error_response(StatusCode::BAD_REQUEST, &e.to_string())),
+    }
+}
+
+pub async fn unlink_wallet_handler<M: CompletionModel + 'static>(
+    State(backend): State<Backend<M>>,
+    Json(request): Json<WalletRequest>,
+) -> Result<Json<WalletsResponse>, (StatusCode, Json<ApiResponse>)> {
+    let wallet = wallet_address(&backend, &request.wallet).await?;
+    let links = agent_state(&backend).await?.wallet_links;
+    if !links.unlink(&request.session_id, wallet) {
+        return Err(error_response(StatusCode::NOT_FOUND, "Wallet isn't linked to this session"));
+    }
+    Ok(Json(WalletsResponse {
+        wallets: links.wallets(&request.session_id),
+    }))
+}
+
+/// Wallets linked to the session
+pub async fn wallets_handler<M: CompletionModel + 'static>(
+    State(backend): State<Backend<M>>,
+    Json(request): Json<SessionRequest>,
+) -> Result<Json<WalletsResponse>, (StatusCode, Json<ApiResponse>)> {
+    let wallets = agent_state(&backend).await?.wallet_links.wallets(&request.session_id);
+    Ok(Json(WalletsResponse { wallets }))
+}
+
+/// Last portfolios fetched for the session's linked wallets, per wallet and combined
+pub async fn aggregated_portfolio_handler<M: CompletionModel + 'static>(
+    State(backend): State<Backend<M>>,
+    Json(request): Json<SessionRequest>,
+) -> Result<Json<AggregatedPortfolio>, (StatusCode, Json<ApiResponse>)> {
+    let wallets = agent_state(&backend).await?.wallet_links.wallets(&request.session_id);
+    let portfolios: Vec<Portfolio> = {
+        let state = backend.app_state.lock().await;
+        let data = state.portfolio_data.read();
+        wallets.iter().filter_map(|wallet| data.get(wallet).cloned()).collect()
+    };
+    AggregatedPortfolio::new(&portfolios)
+        .map(Json)
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "No portfolio fetched for the linked wallets"))
+}
+
+/// Sets the expertise answers are tailored to, overriding the one inferred from the conversation.
+/// With `wallet`, sets the wallet's expertise instead, which applies to every session using it
+/// unless the session sets its own; only a session the wallet is linked to can.
+pub async fn expertise_handler<M: CompletionModel + 'static>(
+    State(backend): State<Backend<M>>,
+    Json(request): Json<ExpertiseRequest>,
+) -> (StatusCode, Json<ApiResponse>) {
+    let session_id = &request.session_id;
+    let wallet = match &request.wallet {
+        Some(wallet) => match wallet_address(&backend, wallet).await {
+            Ok(wallet) => Some(wallet),
+            Err(e) => return e,
+        },
+        None => None,
+    };
+    let Some(agent_state) = backend.app_state.lock().await.agent_state.clone() else {
+        return error_response(StatusCode::SERVICE_UNAVAILABLE, "No agent available");
+    };
+
+    match wallet {
+        Some(wallet) if agent_state.wallet_links.wallets(session_id).contains(&wallet) => {
+            agent_state.expertise.set_wallet(wallet, request.expertise)
+        }
+        Some(_) => return error_response(StatusCode::FORBIDDEN, "Wallet is not linked to this session, link it first"),
+        None => agent_state.expertise.set_session(session_id, request.expertise),
+    }
+    (
+        StatusCode::OK,
+        Json(ApiResponse {
+            status: "success".to_string(),
+            message: format!("Expertise set to {}", request.expertise),
+        }),
+    )
+}
+
+/// Routing decisions taken by the navigator, for intent classification evaluation, requires
+/// `Authorization: Bearer <ADMIN_TOKEN>`
+pub async fn routing_decisions_handler<M: CompletionModel + 'static>(
+    State(backend): State<Backend<M>>,
+    headers: HeaderMap,
+) -> Result<Json<RoutingDecisionsResponse>, (StatusCode, Json<ApiResponse>)> {
+    authorize_admin(&backend, &headers)?;
+    let decisions = backend
+        .app_state
+        .lock()
+        .await
+        .agent_state
+        .as_ref()
+        .map(|agent_state| agent_state.routing_log.decisions())
+        .unwrap_or_default();
+    Ok(Json(RoutingDecisionsResponse { decisions }))
+}
+
+/// Checks `Authorization: Bearer <ADMIN_TOKEN>`
+fn authorize_admin<M: CompletionModel>(
+    backend: &Backend<M>,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, Json<ApiResponse>)> {
+    let authorized = backend.admin_token.as_deref().is_some_and(|token| {
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            == Some(token)
+    });
+    if !authorized {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(ApiResponse {
+                status: "error".to_string(),
+                message: "Invalid admin token".to_string(),
+            }),
+        ));
+    }
+    Ok(())
+}
+
+/// Token usage and cost totals, requires `Authorization: Bearer <ADMIN_TOKEN>`
+pub async fn usage_handler<M: CompletionModel + 'static>(
+    State(backend): State<Backend<M>>,
+    headers: HeaderMap,
+) -> Result<Json<UsageReport>, (StatusCode, Json<ApiResponse>)> {
+    authorize_admin(&backend, &headers)?;
+    Ok(Json(backend.usage.report()))
+}
+
+/// Risk officer verdicts on the drafts, requires `Authorization: Bearer <ADMIN_TOKEN>`
+pub async fn risk_reviews_handler<M: CompletionModel + 'static>(
+    State(backend): State<Backend<M>>,
+    headers: HeaderMap,
+) -> Result<Json<RiskReviewsResponse>, (StatusCode, Json<ApiResponse>)> {
+    authorize_admin(&backend, &headers)?;
+    let reviews = backend
+        .app_state
+        .lock()
+        .await
+        .agent_state
+        .as_ref()
+        .map(|agent_state| agent_state.risk_log.reviews())
+        .unwrap_or_default();
+    Ok(Json(RiskReviewsResponse { reviews }))
+}
 
 pub async fn init_session_handler<M: CompletionModel + 'static>(
     State(backend): State<Backend<M>>,
@@ -0,0 +1,116 @@
+//! Replays eval suites against the navigator configured from the environment.
+//!
+//! `cargo run --bin eval -- evals/core.json [--label NAME] [--out DIR] [--baseline REPORT.json] [--pin NAME=VERSION]`
+use backend_agent::agent_tools::portfolio::rpc_url_from_env;
+use backend_agent::agents::navigator::Tools;
+use backend_agent::agents::registry::AgentRegistry;
+use backend_agent::backend::messaging::ChatHistoryManager;
+use backend_agent::backend::Backend;
+use backend_agent::eval::{run_suite, Suite, SuiteReport};
+use backend_agent::llm::LlmConfig;
+use backend_agent::prices::PriceFeed;
+use backend_agent::prompts::PromptLibrary;
+use backend_agent::token_registry::TokenRegistry;
+use backend_agent::types::YieldAnalyzer;
+use backend_agent::usage::UsageLedger;
+use dotenv::dotenv;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tracing::info;
+
+struct Args {
+    suites: Vec<PathBuf>,
+    label: String,
+    out: PathBuf,
+    baseline: Option<PathBuf>,
+    pins: Vec<(String, String)>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        suites: vec![],
+        label: chrono::Utc::now().format("%Y%m%d-%H%M%S").to_string(),
+        out: PathBuf::from("eval-reports"),
+        baseline: None,
+        pins: vec![],
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or(format!("Missing value for {arg}"));
+        match arg.as_str() {
+            "--label" => args.label = value()?,
+            "--out" => args.out = value()?.into(),
+            "--baseline" => args.baseline = Some(value()?.into()),
+            "--pin" => {
+                let pin = value()?;
+                let (name, version) = pin.split_once('=').ok_or(format!("Invalid pin {pin}, expected NAME=VERSION"))?;
+                args.pins.push((name.to_string(), version.to_string()));
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+            _ => args.suites.push(arg.into()),
+        }
+    }
+    if args.suites.is_empty() {
+        return Err("No suite given".to_string());
+    }
+    Ok(args)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    tracing_subscriber::fmt().init();
+    dotenv().ok();
+    let args = parse_args().map_err(|e| {
+        anyhow::anyhow!("{e}\nUsage: eval <suite.json>... [--label NAME] [--out DIR] [--baseline REPORT.json] [--pin NAME=VERSION]")
+    })?;
+
+    let llm_config = LlmConfig::from_env()?;
+    let usage = UsageLedger::from_env()?;
+    let prompts = PromptLibrary::from_env()?;
+    for (name, version) in &args.pins {
+        prompts.pin(name, Some(version));
+    }
+    let tokens = TokenRegistry::from_env()?;
+    let yields_data = YieldAnalyzer::get_yields_data(&tokens, &PriceFeed::from_env(&[]), &rpc_url_from_env()).await?;
+
+    let (manager, receiver) = ChatHistoryManager::new();
+    let agents = AgentRegistry::from_env()?;
+    let mut models = HashMap::new();
+    for spec in &agents.agents {
+        models.insert(
+            spec.name.clone(),
+            llm_config.profile(&spec.model)?.agent(&spec.name, &llm_config.retry, &usage)?,
+        );
+    }
+    let backend = Backend::new(yields_data.clone(), manager, usage.clone(), prompts, agents);
+    let tools = Tools::new(yields_data, backend.app_state.clone(), tokens);
+    backend.init_agents(models, tools, receiver).await?;
+    let navigator = backend
+        .app_state
+        .lock()
+        .await
+        .agent_state
+        .clone()
+        .expect("Agents not initialized")
+        .navigator;
+
+    std::fs::create_dir_all(&args.out)?;
+    for path in &args.suites {
+        let suite = Suite::load(path)?;
+        let report = run_suite(&navigator, &suite, &args.label).await;
+        let mut markdown = report.to_markdown();
+        if let Some(baseline) = &args.baseline {
+            let baseline: SuiteReport = serde_json::from_str(&std::fs::read_to_string(baseline)?)?;
+            markdown.push('\n');
+            markdown.push_str(&report.compare(&baseline));
+        }
+
+        let stem = args.out.join(format!("{}-{}", report.suite, report.label));
+        std::fs::write(stem.with_extension("json"), serde_json::to_string_pretty(&report)?)?;
+        std::fs::write(stem.with_extension("md"), &markdown)?;
+        info!("Wrote {}.{{json,md}}", stem.display());
+        println!("{markdown}");
+    }
+    info!("Total eval cost: ${:.4}", usage.report().total.cost_usd);
+    Ok(())
+}
@@ -0,0 +1,358 @@
+use crate::agents::guardrails::Check;
+use crate::agents::navigator::{Intent, Navigator};
+use crate::agents::turn::TurnMetadata;
+use crate::backend::messaging::ChatHistoryCommand;
+use chrono::{DateTime, Utc};
+use rig::completion::CompletionModel;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use tracing::info;
+
+const REFUSAL_MARKERS: &[&str] = &["only talk about", "only discuss", "can't help with", "cannot help with", "not able to help"];
+
+/// Golden conversations replayed against a navigator, `evals/*.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Suite {
+    pub name: String,
+    pub conversations: Vec<Conversation>,
+}
+
+impl Suite {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    pub name: String,
+    pub turns: Vec<EvalTurn>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalTurn {
+    pub prompt: String,
+    #[serde(default)]
+    pub expect: Expectation,
+}
+
+/// Checks specific to a turn, the line limit, supported tokens and APYs are always checked
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Expectation {
+    /// Exact tools to call, empty for none; unchecked when absent
+    pub tools: Option<Vec<String>>,
+    /// Answer must mention a token fetched earlier in the conversation
+    pub references_holdings: bool,
+    /// Answer must decline an off-topic request
+    pub refusal: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvalCheck {
+    ToolSelection,
+    ReferencesHoldings,
+    LineLimit,
+    SupportedTokens,
+    ApyAccuracy,
+    OffTopicRefusal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub check: EvalCheck,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnReport {
+    pub prompt: String,
+    pub response: Option<String>,
+    pub error: Option<String>,
+    pub checks: Vec<CheckResult>,
+    pub metadata: Option<TurnMetadata>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationReport {
+    pub name: String,
+    pub turns: Vec<TurnReport>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CheckStats {
+    pub passed: usize,
+    pub total: usize,
+}
+
+impl CheckStats {
+    pub fn rate(&self) -> f64 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.passed as f64 / self.total as f64
+    }
+}
+
+/// Result of a suite run, with what it ran against so runs can be compared
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuiteReport {
+    pub suite: String,
+    pub label: String,
+    pub started_at: DateTime<Utc>,
+    /// Prompt templates used, `name/version`
+    pub prompt_versions: BTreeSet<String>,
+    /// Models that answered, `provider/model`
+    pub models: BTreeSet<String>,
+    pub conversations: Vec<ConversationReport>,
+    pub by_check: BTreeMap<EvalCheck, CheckStats>,
+    /// Share of passed checks over the whole suite
+    pub score: f64,
+}
+
+/// Replays every conversation of `suite` in a fresh session and scores the answers
+pub async fn run_suite<M: CompletionModel + 'static>(navigator: &Navigator<M>, suite: &Suite, label: &str) -> SuiteReport {
+    let started_at = Utc::now();
+    let mut conversations = vec![];
+    for conversation in &suite.conversations {
+        info!("Evaluating {}/{}", suite.name, conversation.name);
+        conversations.push(run_conversation(navigator, conversation).await);
+    }
+
+    let mut by_check: BTreeMap<EvalCheck, CheckStats> = BTreeMap::new();
+    let mut prompt_versions = BTreeSet::new();
+    let mut models = BTreeSet::new();
+    for turn in conversations.iter().flat_map(|c| &c.turns) {
+        for result in &turn.checks {
+            let stats = by_check.entry(result.check).or_default();
+            stats.total += 1;
+            stats.passed += result.passed as usize;
+        }
+        if let Some(metadata) = &turn.metadata {
+            prompt_versions.extend(metadata.prompt_versions.iter().cloned());
+            models.extend(metadata.model_calls.iter().map(|call| call.model.clone()));
+        }
+    }
+    let passed: usize = by_check.values().map(|stats| stats.passed).sum();
+    let total: usize = by_check.values().map(|stats| stats.total).sum();
+
+    SuiteReport {
+        suite: suite.name.clone(),
+        label: label.to_string(),
+        started_at,
+        prompt_versions,
+        models,
+        conversations,
+        by_check,
+        score: CheckStats { passed, total }.rate(),
+    }
+}
+
+async fn run_conversation<M: CompletionModel + 'static>(
+    navigator: &Navigator<M>,
+    conversation: &Conversation,
+) -> ConversationReport {
+    let session_id = format!("eval-{}", uuid::Uuid::new_v4());
+    let _ = navigator
+        .chat_history_sender
+        .send(ChatHistoryCommand::CreateSession(session_id.clone()))
+        .await;
+
+    let mut holdings: Vec<String> = vec![];
+    let mut turns = vec![];
+    for turn in &conversation.turns {
+        let report = match navigator.process_prompt(&turn.prompt, session_id.clone()).await {
+            Ok(outcome) => {
+                holdings.extend(outcome.metadata.holdings.iter().cloned());
+                TurnReport {
+                    prompt: turn.prompt.clone(),
+                    checks: score(navigator, turn, &outcome.response, &outcome.metadata, &holdings),
+                    response: Some(outcome.response),
+                    error: None,
+                    metadata: Some(outcome.metadata),
+                }
+            }
+            Err(e) => TurnReport {
+                prompt: turn.prompt.clone(),
+                response: None,
+                error: Some(e.to_string()),
+                // a failed turn fails everything it was expected to do
+                checks: applicable_checks(&turn.expect)
+                    .into_iter()
+                    .map(|check| CheckResult {
+                        check,
+                        passed: false,
+                        detail: Some("turn failed".to_string()),
+                    })
+                    .collect(),
+                metadata: None,
+            },
+        };
+        turns.push(report);
+    }
+
+    let _ = navigator
+        .chat_history_sender
+        .send(ChatHistoryCommand::DeleteSession(session_id))
+        .await;
+    ConversationReport {
+        name: conversation.name.clone(),
+        turns,
+    }
+}
+
+fn applicable_checks(expect: &Expectation) -> Vec<EvalCheck> {
+    let mut checks = vec![];
+    if expect.tools.is_some() {
+        checks.push(EvalCheck::ToolSelection);
+    }
+    if expect.references_holdings {
+        checks.push(EvalCheck::ReferencesHoldings);
+    }
+    checks.extend([EvalCheck::LineLimit, EvalCheck::SupportedTokens, EvalCheck::ApyAccuracy]);
+    if expect.refusal {
+        checks.push(EvalCheck::OffTopicRefusal);
+    }
+    checks
+}
+
+fn score<M: CompletionModel + 'static>(
+    navigator: &Navigator<M>,
+    turn: &EvalTurn,
+    response: &str,
+    metadata: &TurnMetadata,
+    holdings: &[String],
+) -> Vec<CheckResult> {
+    // what the guardrails caught on the model's answers, `response` already has the repairs
+    let finding_details = |checks: &[Check]| {
+        let mut details: Vec<String> = metadata
+            .guardrails
+            .iter()
+            .filter(|finding| checks.contains(&finding.check))
+            .map(|finding| finding.detail.clone())
+            .collect();
+        details.dedup();
+        (details.is_empty(), (!details.is_empty()).then(|| details.join("; ")))
+    };
+    let called_tool = !metadata.tool_calls.is_empty();
+
+    applicable_checks(&turn.expect)
+        .into_iter()
+        .map(|check| {
+            let (passed, detail) = match check {
+                EvalCheck::ToolSelection => {
+                    let expected = turn.expect.tools.clone().unwrap_or_default();
+                    (
+                        metadata.tool_calls == expected,
+                        Some(format!("called {:?}, expected {:?}", metadata.tool_calls, expected)),
+                    )
+                }
+                EvalCheck::ReferencesHoldings => (
+                    holdings.iter().any(|symbol| response.contains(symbol.as_str())),
+                    Some(format!("holdings {:?}", holdings)),
+                ),
+                // tool outputs aren't bound to the answer format
+                EvalCheck::LineLimit => {
+                    let limit = navigator.guardrails().max_lines(metadata.expertise);
+                    let lines = response.lines().filter(|line| !line.trim().is_empty()).count();
+                    let (fits, truncated) = finding_details(&[Check::Length]);
+                    (
+                        called_tool || (fits && lines <= limit),
+                        Some(truncated.unwrap_or_else(|| format!("{lines} lines, limit is {limit}"))),
+                    )
+                }
+                EvalCheck::SupportedTokens => finding_details(&[Check::UnknownToken, Check::UnsupportedProtocol]),
+                EvalCheck::ApyAccuracy => finding_details(&[Check::ApyMismatch]),
+                EvalCheck::OffTopicRefusal => {
+                    let normalized = response.to_lowercase();
+                    (
+                        metadata.intent == Some(Intent::OffTopic)
+                            || REFUSAL_MARKERS.iter().any(|marker| normalized.contains(marker)),
+                        None,
+                    )
+                }
+            };
+            CheckResult { check, passed, detail }
+        })
+        .collect()
+}
+
+impl SuiteReport {
+    pub fn to_markdown(&self) -> String {
+        let mut md = format!(
+            "# Eval `{}` — {}\n\n{}\n\n- Score: **{:.1}%**\n- Prompts: {}\n- Models: {}\n\n",
+            self.suite,
+            self.label,
+            self.started_at.format("%Y-%m-%d %H:%M UTC"),
+            self.score * 100.0,
+            join_or_none(&self.prompt_versions),
+            join_or_none(&self.models),
+        );
+        md.push_str("| Check | Passed | Rate |\n|---|---|---|\n");
+        for (check, stats) in &self.by_check {
+            md.push_str(&format!(
+                "| {:?} | {}/{} | {:.1}% |\n",
+                check,
+                stats.passed,
+                stats.total,
+                stats.rate() * 100.0
+            ));
+        }
+
+        let failures: Vec<String> = self
+            .conversations
+            .iter()
+            .flat_map(|conversation| {
+                conversation.turns.iter().flat_map(move |turn| {
+                    turn.checks.iter().filter(|result| !result.passed).map(move |result| {
+                        format!(
+                            "- **{}** `{}` — {:?}{}",
+                            conversation.name,
+                            turn.prompt,
+                            result.check,
+                            result.detail.as_ref().map(|detail| format!(": {detail}")).unwrap_or_default()
+                        )
+                    })
+                })
+            })
+            .collect();
+        if !failures.is_empty() {
+            md.push_str("\n## Failures\n\n");
+            md.push_str(&failures.join("\n"));
+            md.push('\n');
+        }
+        md
+    }
+
+    /// Markdown table of pass rates against a `baseline` run
+    pub fn compare(&self, baseline: &SuiteReport) -> String {
+        let mut md = format!(
+            "## `{}` vs `{}`\n\n| Check | {} | {} | Δ |\n|---|---|---|---|\n",
+            self.label, baseline.label, baseline.label, self.label
+        );
+        let checks: BTreeSet<EvalCheck> = self.by_check.keys().chain(baseline.by_check.keys()).copied().collect();
+        for check in checks {
+            let before = baseline.by_check.get(&check).copied().unwrap_or_default().rate() * 100.0;
+            let after = self.by_check.get(&check).copied().unwrap_or_default().rate() * 100.0;
+            md.push_str(&format!("| {:?} | {:.1}% | {:.1}% | {:+.1} |\n", check, before, after, after - before));
+        }
+        md.push_str(&format!(
+            "| **Score** | {:.1}% | {:.1}% | {:+.1} |\n",
+            baseline.score * 100.0,
+            self.score * 100.0,
+            (self.score - baseline.score) * 100.0
+        ));
+        md
+    }
+}
+
+fn join_or_none(items: &BTreeSet<String>) -> String {
+    if items.is_empty() {
+        return "none".to_string();
+    }
+    items.iter().cloned().collect::<Vec<_>>().join(", ")
+}
@@ -0,0 +1,420 @@
+use crate::address::ContractAddress;
+use crate::agents::strategy::Strategy;
+use crate::types::Portfolio;
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Default history file, relative to the working directory
+pub const DEFAULT_HISTORY_FILE: &str = "portfolio_history.jsonl";
+/// Shortest period a realized APY is annualized from, shorter ones blow up small moves
+const MIN_YIELD_WINDOW: Duration = Duration::days(1);
+
+/// How much history is kept, anything past it is dropped on load and on write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    /// Oldest snapshot or prediction kept
+    pub max_age: Duration,
+    /// Latest snapshots kept per wallet
+    pub max_snapshots: usize,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::days(730),
+            max_snapshots: 1000,
+        }
+    }
+}
+
+impl Retention {
+    /// `PORTFOLIO_HISTORY_MAX_DAYS` and `PORTFOLIO_HISTORY_MAX_SNAPSHOTS`, else the defaults
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let var = |name: &str| std::env::var(name).ok();
+        Self {
+            max_age: var("PORTFOLIO_HISTORY_MAX_DAYS")
+                .and_then(|days| days.parse().ok())
+                .map_or(default.max_age, Duration::days),
+            max_snapshots: var("PORTFOLIO_HISTORY_MAX_SNAPSHOTS")
+                .and_then(|count| count.parse().ok())
+                .unwrap_or(default.max_snapshots),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HistoryError {
+    #[error("Failed accessing portfolio history: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid portfolio history line {0}: {1}")]
+    Json(usize, serde_json::Error),
+}
+
+/// A portfolio as fetched at some point: balances, prices and positions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub wallet: ContractAddress,
+    pub taken_at: DateTime<Utc>,
+    pub portfolio: Portfolio,
+}
+
+/// Yield the agent quoted for a token it recommended to a wallet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Prediction {
+    pub wallet: ContractAddress,
+    pub made_at: DateTime<Utc>,
+    pub token: String,
+    pub protocol: String,
+    pub pool: String,
+    pub expected_apy: f64,
+}
+
+/// One line of the history file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    Snapshot(Snapshot),
+    Prediction(Prediction),
+}
+
+/// Change of one token's holdings between two snapshots, positions included
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenPnl {
+    pub symbol: String,
+    pub amount_from: f64,
+    pub amount_to: f64,
+    #[serde(rename = "valueFromUSD")]
+    pub value_from: Option<f64>,
+    #[serde(rename = "valueToUSD")]
+    pub value_to: Option<f64>,
+    /// `None` unless the token was priced in both snapshots
+    #[serde(rename = "pnlUSD")]
+    pub pnl: Option<f64>,
+}
+
+/// Yield a recommended token actually earned, against the one quoted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct YieldCheck {
+    pub token: String,
+    pub protocol: String,
+    pub pool: String,
+    pub predicted_apy: f64,
+    /// Annualized growth of the token inside the positions held at both ends. `None` when the wallet
+    /// balance or the positions changed in between, a deposit isn't yield, or over less than a day.
+    pub realized_apy: Option<f64>,
+}
+
+/// How a wallet's portfolio changed over a period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortfolioChange {
+    pub wallet: ContractAddress,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    #[serde(rename = "valueFromUSD")]
+    pub value_from: f64,
+    #[serde(rename = "valueToUSD")]
+    pub value_to: f64,
+    #[serde(rename = "deltaUSD")]
+    pub delta: f64,
+    /// Delta in percent of the starting value
+    pub delta_percentage: Option<f64>,
+    pub tokens: Vec<TokenPnl>,
+    pub yields: Vec<YieldCheck>,
+}
+
+#[derive(Default)]
+struct Records {
+    snapshots: Vec<Snapshot>,
+    predictions: Vec<Prediction>,
+}
+
+impl Records {
+    fn push(&mut self, record: Record) {
+        match record {
+            Record::Snapshot(snapshot) => self.snapshots.push(snapshot),
+            Record::Prediction(prediction) => self.predictions.push(prediction),
+        }
+    }
+
+    /// Drops what `retention` doesn't keep, `true` if anything was
+    fn prune(&mut self, retention: Retention, now: DateTime<Utc>) -> bool {
+        let (snapshots, predictions) = (self.snapshots.len(), self.predictions.len());
+        let oldest = now - retention.max_age;
+        self.predictions.retain(|prediction| prediction.made_at >= oldest);
+
+        let mut latest_first: Vec<_> = (0..self.snapshots.len()).collect();
+        latest_first.sort_by_key(|&i| std::cmp::Reverse(self.snapshots[i].taken_at));
+        let mut kept = vec![false; self.snapshots.len()];
+        let mut per_wallet = HashMap::<ContractAddress, usize>::new();
+        for i in latest_first {
+            let count = per_wallet.entry(self.snapshots[i].wallet).or_default();
+            if *count < retention.max_snapshots && self.snapshots[i].taken_at >= oldest {
+                *count += 1;
+                kept[i] = true;
+            }
+        }
+        let mut kept = kept.into_iter();
+        self.snapshots.retain(|_| kept.next().unwrap_or_default());
+
+        self.snapshots.len() != snapshots || self.predictions.len() != predictions
+    }
+
+    fn lines(&self) -> impl Iterator<Item = Record> + '_ {
+        let snapshots = self.snapshots.iter().cloned().map(Record::Snapshot);
+        snapshots.chain(self.predictions.iter().cloned().map(Record::Prediction))
+    }
+}
+
+/// Snapshots of every fetched portfolio and the yields the agent predicted, appended to a JSONL
+/// file when one is set. Clones share the history.
+#[derive(Clone, Default)]
+pub struct PortfolioHistory {
+    records: Arc<RwLock<Records>>,
+    file: Option<PathBuf>,
+    retention: Retention,
+}
+
+impl PortfolioHistory {
+    /// History kept in `path`, loading what it already holds within `retention`
+    pub fn open(path: impl AsRef<Path>, retention: Retention) -> Result<Self, HistoryError> {
+        let path = path.as_ref().to_path_buf();
+        let mut records = Records::default();
+        if path.exists() {
+            for (i, line) in BufReader::new(std::fs::File::open(&path)?).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                records.push(serde_json::from_str(&line).map_err(|e| HistoryError::Json(i + 1, e))?);
+            }
+        }
+        let history = Self {
+            records: Arc::default(),
+            file: Some(path),
+            retention,
+        };
+        if records.prune(retention, Utc::now()) {
+            history.rewrite(&records);
+        }
+        *history.records.write() = records;
+        Ok(history)
+    }
+
+    /// History from `PORTFOLIO_HISTORY_FILE`, `portfolio_history.jsonl` by default, kept within [`Retention::from_env`]
+    pub fn from_env() -> Result<Self, HistoryError> {
+        Self::open(
+            std::env::var("PORTFOLIO_HISTORY_FILE").unwrap_or_else(|_| DEFAULT_HISTORY_FILE.to_string()),
+            Retention::from_env(),
+        )
+    }
+
+    pub fn record_snapshot(&self, snapshot: Snapshot) {
+        self.record(vec![Record::Snapshot(snapshot)]);
+    }
+
+    /// Quoted APY of every action of `strategy`
+    pub fn record_predictions(&self, wallet: ContractAddress, strategy: &Strategy) {
+        let predictions = strategy.cards.iter().map(|card| {
+            Record::Prediction(Prediction {
+                wallet,
+                made_at: Utc::now(),
+                token: card.action.token.clone(),
+                protocol: card.action.protocol.clone(),
+                pool: card.action.pool.clone(),
+                expected_apy: card.action.expected_apy,
+            })
+        });
+        self.record(predictions.collect());
+    }
+
+    /// Adds `new` and persists it, the whole file is rewritten when that pushes older records out
+    fn record(&self, new: Vec<Record>) {
+        let mut records = self.records.write();
+        for record in &new {
+            records.push(record.clone());
+        }
+        if records.prune(self.retention, Utc::now()) {
+            self.rewrite(&records);
+        } else {
+            new.iter().for_each(|record| self.append(record));
+        }
+    }
+
+    /// Snapshots of `wallet`, oldest first
+    pub fn snapshots(&self, wallet: ContractAddress) -> Vec<Snapshot> {
+        let mut snapshots: Vec<_> = self
+            .records
+            .read()
+            .snapshots
+            .iter()
+            .filter(|snapshot| snapshot.wallet == wallet)
+            .cloned()
+            .collect();
+        snapshots.sort_by_key(|snapshot| snapshot.taken_at);
+        snapshots
+    }
+
+    /// Change from the last snapshot taken at or before `since` (the first one if none was) to the latest,
+    /// `None` without two snapshots to compare
+    pub fn change(&self, wallet: ContractAddress, since: DateTime<Utc>) -> Option<PortfolioChange> {
+        let snapshots = self.snapshots(wallet);
+        let to = snapshots.last()?;
+        let from = snapshots
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.taken_at <= since)
+            .or(snapshots.first())?;
+        if from.taken_at == to.taken_at {
+            return None;
+        }
+
+        let (holdings_from, holdings_to) = (holdings(&from.portfolio), holdings(&to.portfolio));
+        let tokens = holdings_from
+            .keys()
+            .chain(holdings_to.keys())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .map(|symbol| {
+                let (amount_from, value_from) = holdings_from.get(symbol).copied().unwrap_or((0.0, Some(0.0)));
+                let (amount_to, value_to) = holdings_to.get(symbol).copied().unwrap_or((0.0, Some(0.0)));
+                TokenPnl {
+                    symbol: symbol.clone(),
+                    amount_from,
+                    amount_to,
+                    value_from,
+                    value_to,
+                    pnl: value_from.zip(value_to).map(|(from, to)| to - from),
+                }
+            })
+            .collect();
+
+        let years = (to.taken_at - from.taken_at).num_seconds() as f64 / (365.0 * 24.0 * 3600.0);
+        let mut latest = BTreeMap::new();
+        for prediction in self.records.read().predictions.iter() {
+            if prediction.wallet == wallet && prediction.made_at <= to.taken_at {
+                latest.insert(prediction.token.to_uppercase(), prediction.clone());
+            }
+        }
+        let yields = latest
+            .into_values()
+            .map(|prediction| {
+                let (exposure_from, exposure_to) =
+                    (exposure(&from.portfolio, &prediction.token), exposure(&to.portfolio, &prediction.token));
+                // only positions left alone earn a comparable yield, any transfer shows in the wallet
+                // balance or in which positions hold the token
+                let unchanged = (exposure_from.wallet - exposure_to.wallet).abs() <= exposure_from.wallet.abs() * 1e-9
+                    && exposure_from.positions.keys().eq(exposure_to.positions.keys());
+                let (amount_from, amount_to) =
+                    (exposure_from.positions.values().sum::<f64>(), exposure_to.positions.values().sum::<f64>());
+                let realized_apy = (unchanged
+                    && to.taken_at - from.taken_at >= MIN_YIELD_WINDOW
+                    && amount_from > 0.0
+                    && amount_to > 0.0)
+                    .then(|| ((amount_to / amount_from).powf(1.0 / years) - 1.0) * 100.0);
+                YieldCheck {
+                    token: prediction.token,
+                    protocol: prediction.protocol,
+                    pool: prediction.pool,
+                    predicted_apy: prediction.expected_apy,
+                    realized_apy,
+                }
+            })
+            .collect();
+
+        let delta = to.portfolio.total_value - from.portfolio.total_value;
+        Some(PortfolioChange {
+            wallet,
+            from: from.taken_at,
+            to: to.taken_at,
+            value_from: from.portfolio.total_value,
+            value_to: to.portfolio.total_value,
+            delta,
+            delta_percentage: (from.portfolio.total_value > 0.0).then(|| delta / from.portfolio.total_value * 100.0),
+            tokens,
+            yields,
+        })
+    }
+
+    fn rewrite(&self, records: &Records) {
+        let Some(path) = &self.file else { return };
+        // written aside then moved over, a crash midway doesn't lose the history
+        let temp = path.with_extension("jsonl.tmp");
+        let result = std::fs::File::create(&temp)
+            .and_then(|mut file| {
+                records
+                    .lines()
+                    .try_for_each(|record| writeln!(file, "{}", serde_json::to_string(&record).unwrap_or_default()))
+            })
+            .and_then(|_| std::fs::rename(&temp, path));
+        if let Err(e) = result {
+            warn!("Failed persisting portfolio history: {}", e);
+        }
+    }
+
+    fn append(&self, record: &Record) {
+        let Some(path) = &self.file else { return };
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(record).unwrap_or_default()));
+        if let Err(e) = result {
+            warn!("Failed persisting portfolio history: {}", e);
+        }
+    }
+}
+
+/// Where a portfolio holds a token: the plain wallet balance and the amount per position
+struct Exposure {
+    wallet: f64,
+    positions: BTreeMap<String, f64>,
+}
+
+fn exposure(portfolio: &Portfolio, symbol: &str) -> Exposure {
+    let wallet = portfolio
+        .assets
+        .iter()
+        .filter(|asset| asset.symbol.eq_ignore_ascii_case(symbol))
+        .map(|asset| asset.amount.to_f64())
+        .sum();
+    let mut positions = BTreeMap::new();
+    for holding in &portfolio.positions {
+        let amount: f64 = holding
+            .position
+            .signed_parts()
+            .filter(|(_, part)| part.symbol.eq_ignore_ascii_case(symbol))
+            .map(|(sign, part)| sign * part.amount.to_f64())
+            .sum();
+        if amount != 0.0 {
+            *positions.entry(holding.position.label()).or_default() += amount;
+        }
+    }
+    Exposure { wallet, positions }
+}
+
+/// Amount and USD value held of each token, in plain balances and positions, debts negative
+fn holdings(portfolio: &Portfolio) -> BTreeMap<String, (f64, Option<f64>)> {
+    let mut holdings = BTreeMap::<String, (f64, Option<f64>)>::new();
+    let mut add = |symbol: &str, amount: f64, value: Option<f64>| {
+        let entry = holdings.entry(symbol.to_string()).or_insert((0.0, Some(0.0)));
+        entry.0 += amount;
+        entry.1 = entry.1.zip(value).map(|(total, value)| total + value);
+    };
+    for asset in &portfolio.assets {
+        add(&asset.symbol, asset.amount.to_f64(), asset.balance);
+    }
+    for holding in &portfolio.positions {
+        for (sign, part) in holding.position.signed_parts() {
+            add(&part.symbol, sign * part.amount.to_f64(), part.value.map(|value| sign * value));
+        }
+    }
+    holdings
+}
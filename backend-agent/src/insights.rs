@@ -54,7 +54,7 @@ pub async fn get_insights_context() -> Result<(String, Vec<TwitterInsight>), any
 }
 
 impl TwitterInsight {
-    pub fn format_insights(insights: &Vec<TwitterInsight>) -> String {
+    pub fn format_insights(insights: &[TwitterInsight]) -> String {
         let mut formatted = String::new();
         formatted.push_str("## Latest Twitter Starknet DeFi Insights Context\n\n");
 
@@ -0,0 +1,233 @@
+use crate::types::DefiKnowledge;
+use parking_lot::RwLock;
+use rig::embeddings::{distance::VectorDistance, EmbedError, Embedding, EmbeddingError, EmbeddingModel, EmbeddingsBuilder};
+use rig::vector_store::{VectorStoreError, VectorStoreIndex};
+use rig::OneOrMany;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Default protocol docs directory, relative to the working directory
+pub const DEFAULT_KNOWLEDGE_DIR: &str = "knowledge";
+/// Chunks given to defiproman for each prompt
+pub const DEFAULT_TOP_N: usize = 3;
+const DEFAULT_RELOAD_SECS: u64 = 10;
+
+#[derive(Debug, thiserror::Error)]
+pub enum KnowledgeError {
+    #[error("Failed reading knowledge: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed preparing knowledge chunks: {0}")]
+    Embed(#[from] EmbedError),
+    #[error("Failed embedding knowledge: {0}")]
+    Embedding(#[from] EmbeddingError),
+}
+
+/// Splits a protocol doc on its `##` headings. The protocol name comes from the `#` title,
+/// without trailing details like `(website: ...)`, or from the file name.
+pub fn chunk_markdown(file_stem: &str, source: &str) -> Vec<DefiKnowledge> {
+    let mut protocol = file_stem.trim_end_matches("-lp").to_string();
+    let mut sections: Vec<(String, String)> = vec![];
+    for line in source.lines() {
+        if let Some(title) = line.strip_prefix("# ") {
+            protocol = title.split(" (").next().unwrap_or(title).trim().to_string();
+        } else if let Some(heading) = line.strip_prefix("## ") {
+            sections.push((heading.trim().to_string(), String::new()));
+        } else {
+            match sections.last_mut() {
+                Some((_, content)) => {
+                    content.push_str(line);
+                    content.push('\n');
+                }
+                // text before the first heading
+                None if !line.trim().is_empty() => sections.push(("Overview".to_string(), format!("{line}\n"))),
+                None => {}
+            }
+        }
+    }
+
+    sections
+        .into_iter()
+        .filter(|(_, content)| !content.trim().is_empty())
+        .map(|(section, content)| DefiKnowledge {
+            id: format!("{file_stem}#{}", slug(&section)),
+            protocol: protocol.clone(),
+            section,
+            content: content.trim().to_string(),
+        })
+        .collect()
+}
+
+fn slug(heading: &str) -> String {
+    heading
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+struct IndexedFile {
+    modified: Option<SystemTime>,
+    chunks: Vec<(DefiKnowledge, OneOrMany<Embedding>)>,
+}
+
+/// Embedded chunks of the knowledge docs, served to agents as dynamic context.
+/// Clones share the index, so a watcher keeps every agent up to date.
+#[derive(Clone)]
+pub struct KnowledgeIndex<E: EmbeddingModel> {
+    dir: PathBuf,
+    model: E,
+    files: Arc<RwLock<HashMap<PathBuf, IndexedFile>>>,
+}
+
+impl<E: EmbeddingModel + Clone + 'static> KnowledgeIndex<E> {
+    pub async fn load(dir: impl Into<PathBuf>, model: E) -> Result<Self, KnowledgeError> {
+        let index = Self {
+            dir: dir.into(),
+            model,
+            files: Arc::default(),
+        };
+        index.reindex().await?;
+        Ok(index)
+    }
+
+    /// Docs from `KNOWLEDGE_DIR`, `knowledge` by default
+    pub async fn from_env(model: E) -> Result<Self, KnowledgeError> {
+        Self::load(
+            std::env::var("KNOWLEDGE_DIR").unwrap_or_else(|_| DEFAULT_KNOWLEDGE_DIR.to_string()),
+            model,
+        )
+        .await
+    }
+
+    /// Re-embeds the docs changed since the last run and drops deleted ones.
+    /// Returns how many files changed.
+    pub async fn reindex(&self) -> Result<usize, KnowledgeError> {
+        let mut seen = vec![];
+        let mut changed = 0;
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "md") {
+                continue;
+            }
+            let modified = modified(&path);
+            seen.push(path.clone());
+            let unchanged = self.files.read().get(&path).is_some_and(|file| file.modified == modified);
+            if unchanged {
+                continue;
+            }
+
+            let chunks = self.embed_file(&path).await?;
+            info!("Indexed {} knowledge chunks from {:?}", chunks.len(), path);
+            self.files.write().insert(path, IndexedFile { modified, chunks });
+            changed += 1;
+        }
+
+        self.files.write().retain(|path, _| {
+            let keep = seen.contains(path);
+            if !keep {
+                info!("Dropped knowledge from {:?}", path);
+                changed += 1;
+            }
+            keep
+        });
+        Ok(changed)
+    }
+
+    async fn embed_file(&self, path: &Path) -> Result<Vec<(DefiKnowledge, OneOrMany<Embedding>)>, KnowledgeError> {
+        let file_stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+        let chunks = chunk_markdown(file_stem, &std::fs::read_to_string(path)?);
+        if chunks.is_empty() {
+            return Ok(vec![]);
+        }
+        Ok(EmbeddingsBuilder::new(self.model.clone()).documents(chunks)?.build().await?)
+    }
+
+    /// Re-indexes every `interval` in the background, without restarting the server
+    pub fn watch(&self, interval: Duration) -> JoinHandle<()> {
+        let index = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = index.reindex().await {
+                    warn!("Knowledge reindex failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// [`Self::watch`] every `KNOWLEDGE_RELOAD_SECS`, 10 by default
+    pub fn watch_from_env(&self) -> JoinHandle<()> {
+        let secs = std::env::var("KNOWLEDGE_RELOAD_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(DEFAULT_RELOAD_SECS);
+        self.watch(Duration::from_secs(secs))
+    }
+
+    /// Indexed chunks, sorted by id
+    pub fn chunks(&self) -> Vec<DefiKnowledge> {
+        let mut chunks: Vec<DefiKnowledge> = self
+            .files
+            .read()
+            .values()
+            .flat_map(|file| file.chunks.iter().map(|(chunk, _)| chunk.clone()))
+            .collect();
+        chunks.sort_by(|a, b| a.id.cmp(&b.id));
+        chunks
+    }
+
+    async fn search(&self, query: &str, n: usize) -> Result<Vec<(f64, DefiKnowledge)>, VectorStoreError> {
+        let query = self.model.embed_text(query).await?;
+        let mut ranked: Vec<(f64, DefiKnowledge)> = self
+            .files
+            .read()
+            .values()
+            .flat_map(|file| &file.chunks)
+            .map(|(chunk, embeddings)| {
+                let score = embeddings
+                    .iter()
+                    .map(|embedding| embedding.cosine_similarity(&query, false))
+                    .fold(f64::MIN, f64::max);
+                (score, chunk.clone())
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranked.truncate(n);
+        Ok(ranked)
+    }
+}
+
+impl<E: EmbeddingModel + Clone + 'static> VectorStoreIndex for KnowledgeIndex<E> {
+    async fn top_n<T: for<'a> Deserialize<'a> + Send>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
+        self.search(query, n)
+            .await?
+            .into_iter()
+            .map(|(score, chunk)| Ok((score, chunk.id.clone(), serde_json::from_value(serde_json::to_value(chunk)?)?)))
+            .collect()
+    }
+
+    async fn top_n_ids(&self, query: &str, n: usize) -> Result<Vec<(f64, String)>, VectorStoreError> {
+        Ok(self
+            .search(query, n)
+            .await?
+            .into_iter()
+            .map(|(score, chunk)| (score, chunk.id))
+            .collect())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
@@ -1,9 +1,23 @@
 pub mod agent_tools;
 pub mod agents;
+pub mod address;
+pub mod amount;
 pub mod backend;
+pub mod eval;
+pub mod history;
 pub mod insights;
+pub mod knowledge;
+pub mod llm;
 pub mod market;
 pub mod math;
+pub mod positions;
+pub mod prices;
+pub mod prompts;
+pub mod sources;
+pub mod starknet_id;
+pub mod token_registry;
 pub mod tokens;
 pub mod types;
+pub mod usage;
 pub mod utils;
+pub mod wallets;
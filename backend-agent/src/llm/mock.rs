@@ -0,0 +1,166 @@
+//! Deterministic stand-ins for LLM providers, so agents can be exercised in `cargo test` without network.
+use crate::utils::fnv1a;
+use parking_lot::Mutex;
+use rig::{
+    completion::{
+        CompletionError, CompletionModel, CompletionRequest, CompletionResponse, Document,
+        Message, ModelChoice,
+    },
+    embeddings::{Embedding, EmbeddingError, EmbeddingModel},
+};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// One queued answer of a [`ScriptedModel`]
+#[derive(Debug, Clone)]
+pub enum ScriptedReply {
+    Text(String),
+    ToolCall(String, serde_json::Value),
+    Error(String),
+}
+
+/// Copy of a [`CompletionRequest`] as received by a [`ScriptedModel`]
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub prompt: String,
+    pub preamble: Option<String>,
+    pub chat_history: Vec<Message>,
+    pub documents: Vec<Document>,
+    pub tools: Vec<String>,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u64>,
+}
+
+/// Completion model answering from a queue of scripted replies and recording every request.
+/// Clones share the same script and recordings.
+#[derive(Clone, Default)]
+pub struct ScriptedModel {
+    replies: Arc<Mutex<VecDeque<ScriptedReply>>>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl ScriptedModel {
+    pub fn new(replies: impl IntoIterator<Item = ScriptedReply>) -> Self {
+        Self {
+            replies: Arc::new(Mutex::new(replies.into_iter().collect())),
+            requests: Arc::default(),
+        }
+    }
+
+    pub fn push_text(&self, text: &str) -> &Self {
+        self.replies.lock().push_back(ScriptedReply::Text(text.to_string()));
+        self
+    }
+
+    pub fn push_tool_call(&self, name: &str, args: serde_json::Value) -> &Self {
+        self.replies
+            .lock()
+            .push_back(ScriptedReply::ToolCall(name.to_string(), args));
+        self
+    }
+
+    pub fn push_error(&self, error: &str) -> &Self {
+        self.replies.lock().push_back(ScriptedReply::Error(error.to_string()));
+        self
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().clone()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.replies.lock().len()
+    }
+}
+
+impl CompletionModel for ScriptedModel {
+    type Response = ();
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<()>, CompletionError> {
+        self.requests.lock().push(RecordedRequest {
+            prompt: request.prompt,
+            preamble: request.preamble,
+            chat_history: request.chat_history,
+            documents: request.documents,
+            tools: request.tools.into_iter().map(|tool| tool.name).collect(),
+            temperature: request.temperature,
+            max_tokens: request.max_tokens,
+        });
+
+        let reply = self.replies.lock().pop_front();
+        let choice = match reply {
+            Some(ScriptedReply::Text(text)) => ModelChoice::Message(text),
+            Some(ScriptedReply::ToolCall(name, args)) => ModelChoice::ToolCall(name, args),
+            Some(ScriptedReply::Error(error)) => return Err(CompletionError::ProviderError(error)),
+            None => {
+                return Err(CompletionError::ResponseError(
+                    "Scripted model has no reply left".to_string(),
+                ))
+            }
+        };
+
+        Ok(CompletionResponse {
+            choice,
+            raw_response: (),
+        })
+    }
+}
+
+/// Embedding model hashing words into a fixed size bag-of-words vector.
+/// Same text gives the same vector and texts sharing words end up close, which is all retrieval tests need.
+#[derive(Clone)]
+pub struct HashEmbeddingModel {
+    ndims: usize,
+}
+
+impl HashEmbeddingModel {
+    pub fn new(ndims: usize) -> Self {
+        Self { ndims: ndims.max(1) }
+    }
+
+    pub fn embed(&self, text: &str) -> Vec<f64> {
+        let mut vec = vec![0.0; self.ndims];
+        for word in text
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+        {
+            vec[(fnv1a(word) % self.ndims as u64) as usize] += 1.0;
+        }
+        let norm = vec.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            vec.iter_mut().for_each(|x| *x /= norm);
+        }
+        vec
+    }
+}
+
+impl Default for HashEmbeddingModel {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+
+impl EmbeddingModel for HashEmbeddingModel {
+    const MAX_DOCUMENTS: usize = 1024;
+
+    fn ndims(&self) -> usize {
+        self.ndims
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        Ok(texts
+            .into_iter()
+            .map(|document| Embedding {
+                vec: self.embed(&document),
+                document,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,417 @@
+use crate::usage::UsageLedger;
+use resilient::{ResilientModel, RetryPolicy};
+use rig::{
+    agent::AgentBuilder,
+    completion::{self, CompletionError, CompletionRequest, CompletionResponse},
+    embeddings::{self, EmbeddingError},
+    providers::{anthropic, openai},
+};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub mod mock;
+pub mod resilient;
+
+pub const DEFAULT_CHAT_MODEL: &str = openai::GPT_4O_MINI;
+pub const DEFAULT_EMBEDDING_MODEL: &str = openai::TEXT_EMBEDDING_3_SMALL;
+/// Anthropic refuses requests without `max_tokens`, used when the agent sets none
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u64 = 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LlmConfigError {
+    #[error("Unknown LLM provider: {0}")]
+    UnknownProvider(String),
+    #[error("Invalid value for {0}: {1}")]
+    InvalidValue(String, String),
+    #[error("Missing API key, set {0}")]
+    MissingApiKey(String),
+    #[error("Provider {0} has no embedding models")]
+    NoEmbeddings(Provider),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Provider {
+    OpenAi,
+    Anthropic,
+    /// Any server speaking the OpenAI API (Ollama, llama.cpp, vLLM...)
+    OpenAiCompatible,
+}
+
+impl FromStr for Provider {
+    type Err = LlmConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "openai" => Ok(Provider::OpenAi),
+            "anthropic" => Ok(Provider::Anthropic),
+            "openai-compatible" | "ollama" | "llama.cpp" | "local" => Ok(Provider::OpenAiCompatible),
+            other => Err(LlmConfigError::UnknownProvider(other.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Provider::OpenAi => "openai",
+            Provider::Anthropic => "anthropic",
+            Provider::OpenAiCompatible => "openai-compatible",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl Provider {
+    fn default_api_key_var(&self) -> &'static str {
+        match self {
+            Provider::OpenAi | Provider::OpenAiCompatible => "OPENAI_API_KEY",
+            Provider::Anthropic => "ANTHROPIC_API_KEY",
+        }
+    }
+}
+
+/// Provider, model and sampling settings of one agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelProfile {
+    pub provider: Provider,
+    pub model: String,
+    pub base_url: Option<String>,
+    #[serde(skip_serializing)]
+    pub api_key: Option<String>,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u64>,
+    /// Tried in order when this model keeps failing
+    pub fallbacks: Vec<ModelProfile>,
+}
+
+impl ModelProfile {
+    pub fn new(provider: Provider, model: &str) -> Self {
+        Self {
+            provider,
+            model: model.to_string(),
+            base_url: None,
+            api_key: None,
+            temperature: None,
+            max_tokens: None,
+            fallbacks: Vec::new(),
+        }
+    }
+
+    /// Parses a fallback entry `provider:model[@base_url]`, e.g. `ollama:llama3.1@http://localhost:11434/v1`
+    pub fn parse_fallback(entry: &str) -> Result<Self, LlmConfigError> {
+        let invalid = || LlmConfigError::InvalidValue("fallback model".to_string(), entry.to_string());
+        let (provider, rest) = entry.trim().split_once(':').ok_or_else(invalid)?;
+        let (model, base_url) = match rest.split_once('@') {
+            Some((model, base_url)) => (model, Some(base_url.to_string())),
+            None => (rest, None),
+        };
+        if model.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            base_url,
+            ..Self::new(provider.parse()?, model)
+        })
+    }
+
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Reads `{prefix}_LLM_PROVIDER`, `_MODEL`, `_BASE_URL`, `_API_KEY`, `_TEMPERATURE`, `_MAX_TOKENS`
+    /// and `_FALLBACKS` (comma separated, see [`ModelProfile::parse_fallback`]),
+    /// keeping `default` values for unset variables.
+    pub fn from_env(prefix: &str, default: ModelProfile) -> Result<Self, LlmConfigError> {
+        let provider = match env_var(prefix, "PROVIDER") {
+            Some(provider) => provider.parse()?,
+            None => default.provider,
+        };
+
+        Ok(Self {
+            provider,
+            model: env_var(prefix, "MODEL").unwrap_or(default.model),
+            base_url: env_var(prefix, "BASE_URL").or(default.base_url),
+            api_key: env_var(prefix, "API_KEY").or(default.api_key),
+            temperature: parse_env_var(prefix, "TEMPERATURE")?.or(default.temperature),
+            max_tokens: parse_env_var(prefix, "MAX_TOKENS")?.or(default.max_tokens),
+            fallbacks: match env_var(prefix, "FALLBACKS") {
+                Some(entries) => entries
+                    .split(',')
+                    .filter(|entry| !entry.trim().is_empty())
+                    .map(ModelProfile::parse_fallback)
+                    .collect::<Result<_, _>>()?,
+                None => default.fallbacks,
+            },
+        })
+    }
+
+    /// Model id as recorded in logs and turn metadata, e.g. `openai/gpt-4o-mini`
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.provider, self.model)
+    }
+
+    pub fn completion_model(&self) -> Result<LlmModel, LlmConfigError> {
+        let api_key = resolve_api_key(self.provider, self.api_key.as_deref())?;
+        let model = match self.provider {
+            Provider::OpenAi | Provider::OpenAiCompatible => {
+                LlmModel::OpenAi(openai_client(&api_key, self.base_url.as_deref()).completion_model(&self.model))
+            }
+            Provider::Anthropic => {
+                let mut builder = anthropic::ClientBuilder::new(&api_key);
+                if let Some(base_url) = self.base_url.as_deref() {
+                    builder = builder.base_url(base_url);
+                }
+                LlmModel::Anthropic(builder.build().completion_model(&self.model))
+            }
+        };
+        Ok(model)
+    }
+
+    /// This model and its fallbacks behind retries and circuit breakers, metered into `ledger`.
+    /// `agent` labels the calls.
+    pub fn resilient_model(
+        &self,
+        agent: &str,
+        policy: &RetryPolicy,
+        ledger: &UsageLedger,
+    ) -> Result<ResilientModel<LlmModel>, LlmConfigError> {
+        self.fallbacks.iter().try_fold(
+            ResilientModel::new(agent, &self.id(), self.completion_model()?, policy.clone()).metered(ledger.clone()),
+            |model, fallback| Ok(model.fallback(&fallback.id(), fallback.completion_model()?)),
+        )
+    }
+
+    /// Agent builder with this profile's sampling settings applied
+    pub fn agent(
+        &self,
+        agent: &str,
+        policy: &RetryPolicy,
+        ledger: &UsageLedger,
+    ) -> Result<AgentBuilder<ResilientModel<LlmModel>>, LlmConfigError> {
+        let mut builder = AgentBuilder::new(self.resilient_model(agent, policy, ledger)?);
+        if let Some(temperature) = self.temperature {
+            builder = builder.temperature(temperature);
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            builder = builder.max_tokens(max_tokens);
+        }
+        Ok(builder)
+    }
+}
+
+/// Embedding model settings, only OpenAI-style APIs serve embeddings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingProfile {
+    pub provider: Provider,
+    pub model: String,
+    pub base_url: Option<String>,
+    #[serde(skip_serializing)]
+    pub api_key: Option<String>,
+    /// Required for models rig doesn't know, e.g. `nomic-embed-text` is 768
+    pub ndims: Option<usize>,
+}
+
+impl EmbeddingProfile {
+    pub fn from_env(prefix: &str, default: EmbeddingProfile) -> Result<Self, LlmConfigError> {
+        let provider = match env_var(prefix, "PROVIDER") {
+            Some(provider) => provider.parse()?,
+            None => default.provider,
+        };
+
+        Ok(Self {
+            provider,
+            model: env_var(prefix, "MODEL").unwrap_or(default.model),
+            base_url: env_var(prefix, "BASE_URL").or(default.base_url),
+            api_key: env_var(prefix, "API_KEY").or(default.api_key),
+            ndims: parse_env_var(prefix, "NDIMS")?.or(default.ndims),
+        })
+    }
+
+    /// `provider/model`, the key of the price table
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.provider, self.model)
+    }
+
+    pub fn embedding_model(&self) -> Result<LlmEmbeddingModel, LlmConfigError> {
+        if self.provider == Provider::Anthropic {
+            return Err(LlmConfigError::NoEmbeddings(self.provider));
+        }
+        let api_key = resolve_api_key(self.provider, self.api_key.as_deref())?;
+        let client = openai_client(&api_key, self.base_url.as_deref());
+        let model = match self.ndims {
+            Some(ndims) => client.embedding_model_with_ndims(&self.model, ndims),
+            None => client.embedding_model(&self.model),
+        };
+        Ok(LlmEmbeddingModel::OpenAi(model))
+    }
+}
+
+/// Per-agent model selection, see `.env.example` for the variables
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmConfig {
+    pub navigator: ModelProfile,
+    pub defiproman: ModelProfile,
+    pub embedding: EmbeddingProfile,
+    pub retry: RetryPolicy,
+}
+
+impl LlmConfig {
+    /// Model profile `name` of an agent registry entry: `navigator`, `defiproman`, or any other
+    /// name read from `<NAME>_LLM_*` on top of the defiproman profile
+    pub fn profile(&self, name: &str) -> Result<ModelProfile, LlmConfigError> {
+        match name {
+            "navigator" => Ok(self.navigator.clone()),
+            "defiproman" => Ok(self.defiproman.clone()),
+            _ => ModelProfile::from_env(&name.to_uppercase(), self.defiproman.clone()),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, LlmConfigError> {
+        Ok(Self {
+            navigator: ModelProfile::from_env(
+                "NAVIGATOR",
+                ModelProfile::new(Provider::OpenAi, DEFAULT_CHAT_MODEL),
+            )?,
+            defiproman: ModelProfile::from_env(
+                "DEFIPROMAN",
+                ModelProfile::new(Provider::OpenAi, DEFAULT_CHAT_MODEL).temperature(0.3),
+            )?,
+            embedding: EmbeddingProfile::from_env(
+                "EMBEDDING",
+                EmbeddingProfile {
+                    provider: Provider::OpenAi,
+                    model: DEFAULT_EMBEDDING_MODEL.to_string(),
+                    base_url: None,
+                    api_key: None,
+                    ndims: None,
+                },
+            )?,
+            retry: retry_policy_from_env()?,
+        })
+    }
+}
+
+/// `LLM_MAX_RETRIES`, `LLM_RETRY_BASE_MS`, `LLM_RETRY_MAX_MS`, `LLM_BREAKER_THRESHOLD` and `LLM_BREAKER_COOLDOWN_SECS`
+fn retry_policy_from_env() -> Result<RetryPolicy, LlmConfigError> {
+    let default = RetryPolicy::default();
+    Ok(RetryPolicy {
+        max_retries: parse_var("LLM_MAX_RETRIES")?.unwrap_or(default.max_retries),
+        base_delay: parse_var("LLM_RETRY_BASE_MS")?
+            .map(Duration::from_millis)
+            .unwrap_or(default.base_delay),
+        max_delay: parse_var("LLM_RETRY_MAX_MS")?
+            .map(Duration::from_millis)
+            .unwrap_or(default.max_delay),
+        breaker_threshold: parse_var("LLM_BREAKER_THRESHOLD")?.unwrap_or(default.breaker_threshold),
+        breaker_cooldown: parse_var("LLM_BREAKER_COOLDOWN_SECS")?
+            .map(Duration::from_secs)
+            .unwrap_or(default.breaker_cooldown),
+    })
+}
+
+fn var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn parse_var<T: FromStr>(name: &str) -> Result<Option<T>, LlmConfigError> {
+    var(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| LlmConfigError::InvalidValue(name.to_string(), value))
+        })
+        .transpose()
+}
+
+fn env_var(prefix: &str, name: &str) -> Option<String> {
+    var(&format!("{prefix}_LLM_{name}"))
+}
+
+fn parse_env_var<T: FromStr>(prefix: &str, name: &str) -> Result<Option<T>, LlmConfigError> {
+    parse_var(&format!("{prefix}_LLM_{name}"))
+}
+
+fn resolve_api_key(provider: Provider, api_key: Option<&str>) -> Result<String, LlmConfigError> {
+    if let Some(api_key) = api_key {
+        return Ok(api_key.to_string());
+    }
+    match std::env::var(provider.default_api_key_var()) {
+        Ok(api_key) => Ok(api_key),
+        // local servers usually don't check the key but the client needs a header value
+        Err(_) if provider == Provider::OpenAiCompatible => Ok("local".to_string()),
+        Err(_) => Err(LlmConfigError::MissingApiKey(provider.default_api_key_var().to_string())),
+    }
+}
+
+fn openai_client(api_key: &str, base_url: Option<&str>) -> openai::Client {
+    match base_url {
+        Some(base_url) => openai::Client::from_url(api_key, base_url),
+        None => openai::Client::new(api_key),
+    }
+}
+
+/// Completion model of any configured provider, lets agents on different providers share one type
+#[derive(Clone)]
+pub enum LlmModel {
+    OpenAi(openai::CompletionModel),
+    Anthropic(anthropic::completion::CompletionModel),
+}
+
+#[derive(Debug)]
+pub enum LlmResponse {
+    OpenAi(openai::CompletionResponse),
+    Anthropic(anthropic::completion::CompletionResponse),
+}
+
+impl completion::CompletionModel for LlmModel {
+    type Response = LlmResponse;
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<LlmResponse>, CompletionError> {
+        match self {
+            LlmModel::OpenAi(model) => model.completion(request).await.map(|response| CompletionResponse {
+                choice: response.choice,
+                raw_response: LlmResponse::OpenAi(response.raw_response),
+            }),
+            LlmModel::Anthropic(model) => {
+                let request = CompletionRequest {
+                    max_tokens: request.max_tokens.or(Some(ANTHROPIC_DEFAULT_MAX_TOKENS)),
+                    ..request
+                };
+                model.completion(request).await.map(|response| CompletionResponse {
+                    choice: response.choice,
+                    raw_response: LlmResponse::Anthropic(response.raw_response),
+                })
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum LlmEmbeddingModel {
+    OpenAi(openai::EmbeddingModel),
+}
+
+impl embeddings::EmbeddingModel for LlmEmbeddingModel {
+    const MAX_DOCUMENTS: usize = 1024;
+
+    fn ndims(&self) -> usize {
+        match self {
+            LlmEmbeddingModel::OpenAi(model) => model.ndims(),
+        }
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<embeddings::Embedding>, EmbeddingError> {
+        match self {
+            LlmEmbeddingModel::OpenAi(model) => model.embed_texts(texts).await,
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::agents::turn::{self, ModelCall};
+use crate::usage::{CallKind, ReportsUsage, TokenUsage, UsageLedger};
+use parking_lot::Mutex;
+use rand::Rng;
+use rig::completion::{CompletionError, CompletionModel, CompletionRequest, CompletionResponse};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Retry, backoff and circuit breaker settings shared by every [`ResilientModel`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Retries per model after the first attempt, only for retryable errors
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Consecutive failures opening a model's circuit
+    pub breaker_threshold: u32,
+    /// How long an open circuit skips its model before letting one trial request through
+    pub breaker_cooldown: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(4),
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Full jitter exponential backoff: random delay in `[0, min(max_delay, base * 2^attempt)]`
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let millis = ceiling.as_millis() as u64;
+        if millis == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+    }
+}
+
+/// Whether retrying the same model has a chance to succeed
+pub fn is_retryable(error: &CompletionError) -> bool {
+    match error {
+        CompletionError::HttpError(e) => {
+            e.is_timeout()
+                || e.is_connect()
+                || e.is_request()
+                || e.status().is_some_and(|status| status.as_u16() == 429 || status.is_server_error())
+        }
+        CompletionError::ProviderError(message) => {
+            let message = message.to_lowercase();
+            ["rate limit", "rate_limit", "overloaded", "timeout", "timed out", "server error", "unavailable", "429", "500", "502", "503", "504", "529"]
+                .iter()
+                .any(|needle| message.contains(needle))
+        }
+        CompletionError::JsonError(_)
+        | CompletionError::RequestError(_)
+        | CompletionError::ResponseError(_) => false,
+    }
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    /// Set while the one request let through a half-open circuit runs
+    trial: bool,
+}
+
+/// How a request got through a [`CircuitBreaker`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Closed,
+    /// The single trial of a half-open circuit
+    Trial,
+}
+
+/// Per-model circuit breaker, shared by clones of the model. Only retryable failures count, a model
+/// rejecting a request isn't down.
+#[derive(Debug, Clone, Default)]
+pub struct CircuitBreaker(Arc<Mutex<BreakerState>>);
+
+impl CircuitBreaker {
+    /// `None` while open; once the cooldown is over a single trial is let through (half-open)
+    /// and every other request is still turned away until it finishes
+    pub fn admit(&self, policy: &RetryPolicy) -> Option<Admission> {
+        let mut state = self.0.lock();
+        match state.opened_at {
+            None => Some(Admission::Closed),
+            Some(opened_at) if opened_at.elapsed() < policy.breaker_cooldown || state.trial => None,
+            Some(_) => {
+                state.trial = true;
+                Some(Admission::Trial)
+            }
+        }
+    }
+
+    pub fn on_success(&self) {
+        *self.0.lock() = BreakerState::default();
+    }
+
+    pub fn on_failure(&self, policy: &RetryPolicy, admission: Admission, error: &CompletionError) {
+        let mut state = self.0.lock();
+        if admission == Admission::Trial {
+            state.trial = false;
+        }
+        if !is_retryable(error) {
+            return;
+        }
+        state.consecutive_failures += 1;
+        // a failed trial reopens right away
+        if admission == Admission::Trial || state.consecutive_failures >= policy.breaker_threshold {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.0.lock().opened_at.is_some()
+    }
+}
+
+#[derive(Clone)]
+struct Candidate<M> {
+    id: String,
+    model: M,
+    breaker: CircuitBreaker,
+}
+
+/// Completion model retrying transient errors and failing over to an ordered list of fallbacks.
+/// The model that answered is recorded in the current turn metadata, and its usage in the ledger if metered.
+#[derive(Clone)]
+pub struct ResilientModel<M: CompletionModel> {
+    agent: String,
+    candidates: Vec<Candidate<M>>,
+    policy: RetryPolicy,
+    ledger: Option<UsageLedger>,
+}
+
+impl<M: CompletionModel> ResilientModel<M> {
+    /// `agent` labels the calls in turn metadata, `id` identifies the model, e.g. `openai/gpt-4o-mini`
+    pub fn new(agent: &str, id: &str, model: M, policy: RetryPolicy) -> Self {
+        Self {
+            agent: agent.to_string(),
+            candidates: vec![Candidate {
+                id: id.to_string(),
+                model,
+                breaker: CircuitBreaker::default(),
+            }],
+            policy,
+            ledger: None,
+        }
+    }
+
+    /// Records the tokens and cost of every successful call into `ledger`
+    pub fn metered(mut self, ledger: UsageLedger) -> Self {
+        self.ledger = Some(ledger);
+        self
+    }
+
+    /// Adds a model tried after every previous one failed
+    pub fn fallback(mut self, id: &str, model: M) -> Self {
+        self.candidates.push(Candidate {
+            id: id.to_string(),
+            model,
+            breaker: CircuitBreaker::default(),
+        });
+        self
+    }
+
+    pub fn model_ids(&self) -> Vec<String> {
+        self.candidates.iter().map(|candidate| candidate.id.clone()).collect()
+    }
+
+    /// Breaker of the model with `id`, for monitoring
+    pub fn breaker(&self, id: &str) -> Option<CircuitBreaker> {
+        self.candidates
+            .iter()
+            .find(|candidate| candidate.id == id)
+            .map(|candidate| candidate.breaker.clone())
+    }
+}
+
+impl<M: CompletionModel> CompletionModel for ResilientModel<M>
+where
+    M::Response: ReportsUsage,
+{
+    type Response = M::Response;
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<M::Response>, CompletionError> {
+        let mut last_error = None;
+
+        for (index, candidate) in self.candidates.iter().enumerate() {
+            let Some(admission) = candidate.breaker.admit(&self.policy) else {
+                warn!("Circuit open for {}, skipping", candidate.id);
+                continue;
+            };
+
+            let mut attempt = 0;
+            loop {
+                attempt += 1;
+                match candidate.model.completion(clone_request(&request)).await {
+                    Ok(response) => {
+                        candidate.breaker.on_success();
+                        if let Some(ledger) = &self.ledger {
+                            let usage = response
+                                .raw_response
+                                .token_usage()
+                                .unwrap_or_else(|| TokenUsage::estimate(&request, &response.choice));
+                            ledger.record(&self.agent, &candidate.id, CallKind::Completion, usage);
+                        }
+                        turn::record(|metadata| {
+                            metadata.model_calls.push(ModelCall {
+                                agent: self.agent.clone(),
+                                model: candidate.id.clone(),
+                                attempts: attempt,
+                                fallback: index > 0,
+                            })
+                        });
+                        return Ok(response);
+                    }
+                    Err(error) => {
+                        candidate.breaker.on_failure(&self.policy, admission, &error);
+                        let retry = is_retryable(&error)
+                            && attempt <= self.policy.max_retries
+                            && !candidate.breaker.is_open();
+                        warn!(
+                            "{} completion failed on {} (attempt {}): {}",
+                            self.agent, candidate.id, attempt, error
+                        );
+                        last_error = Some(error);
+                        if !retry {
+                            break;
+                        }
+                        tokio::time::sleep(self.policy.backoff(attempt - 1)).await;
+                    }
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            CompletionError::ProviderError(format!("All models of {} are unavailable", self.agent))
+        }))
+    }
+}
+
+/// `CompletionRequest` isn't `Clone` and each attempt consumes one
+fn clone_request(request: &CompletionRequest) -> CompletionRequest {
+    CompletionRequest {
+        prompt: request.prompt.clone(),
+        preamble: request.preamble.clone(),
+        chat_history: request.chat_history.clone(),
+        documents: request.documents.clone(),
+        tools: request.tools.clone(),
+        temperature: request.temperature,
+        max_tokens: request.max_tokens,
+        additional_params: request.additional_params.clone(),
+    }
+}
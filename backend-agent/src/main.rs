@@ -1,47 +1,52 @@
-use agents::navigator::Tools;
-use backend::Backend;
+use backend_agent::agent_tools::portfolio::rpc_url_from_env;
+use backend_agent::agents::navigator::Tools;
+use backend_agent::agents::registry::AgentRegistry;
+use backend_agent::backend::messaging::ChatHistoryManager;
+use backend_agent::backend::Backend;
+use backend_agent::insights::get_insights_context;
+use backend_agent::knowledge::{KnowledgeIndex, DEFAULT_TOP_N};
+use backend_agent::llm::LlmConfig;
+use backend_agent::prices::PriceFeed;
+use backend_agent::prompts::PromptLibrary;
+use backend_agent::sources::{CitedIndex, Source};
+use backend_agent::token_registry::TokenRegistry;
+use backend_agent::types::YieldAnalyzer;
+use backend_agent::usage::{MeteredEmbeddingModel, UsageLedger};
 use dotenv::dotenv;
-use insights::get_insights_context;
-use rig::{
-    embeddings::EmbeddingsBuilder, providers::openai::TEXT_EMBEDDING_3_SMALL,
-    vector_store::in_memory_store::InMemoryVectorStore,
-};
-use types::YieldAnalyzer;
-use utils::defipro_get_instr;
-use crate::backend::messaging::ChatHistoryManager;
-use std::env;
-
-
-mod agent_tools;
-mod agents;
-mod backend;
-mod insights;
-mod market;
-mod math;
-mod tokens;
-mod types;
-mod utils;
+use rig::{embeddings::EmbeddingsBuilder, vector_store::in_memory_store::InMemoryVectorStore};
+use std::collections::HashMap;
+use tracing::info;
 
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt().init();
     dotenv().ok();
 
-    let openai_api = std::env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY must be set in environment");
-    let yields_data = YieldAnalyzer::get_yields_data()
+    let llm_config = LlmConfig::from_env().expect("Invalid LLM configuration");
+    let usage = UsageLedger::from_env().expect("Invalid LLM price table");
+    let prompts = PromptLibrary::from_env().expect("Failed loading prompt templates");
+    prompts.watch_from_env();
+    let agents = AgentRegistry::from_env().expect("Failed loading agents registry");
+    let tokens = TokenRegistry::from_env().expect("Failed loading token registry");
+    // no yields to fall back on yet, market data is live only
+    let yields_data = YieldAnalyzer::get_yields_data(&tokens, &PriceFeed::from_env(&[]), &rpc_url_from_env())
         .await
         .expect("no yield data");
 
-    let openai_client = rig::providers::openai::Client::new(&openai_api);
-
     // Initiate agents, tools and backend
     let (_, x_insight) = get_insights_context()
         .await
         .expect("Failed getting twitter insights");
 
-    let nav_model = openai_client.completion_model("gpt-4o-mini");
-
-    let defaigent_embd_model = openai_client.embedding_model(TEXT_EMBEDDING_3_SMALL);
+    let defaigent_embd_model = MeteredEmbeddingModel::new(
+        "insights",
+        &llm_config.embedding.id(),
+        llm_config
+            .embedding
+            .embedding_model()
+            .expect("Failed creating embedding model"),
+        usage.clone(),
+    );
     let embeddings = EmbeddingsBuilder::new(defaigent_embd_model.clone())
         .documents(x_insight.clone())
         .expect("Failed embedding Vec<TwitterInsight>")
@@ -50,22 +55,35 @@ async fn main() {
         .expect("Failed building defaiproman");
 
     let vector_store = InMemoryVectorStore::from_documents(embeddings);
-    let index = vector_store.index(defaigent_embd_model);
 
-    let defaigent_model = openai_client
-        .agent("gpt-4o-mini")
-        .dynamic_context(4, index)
-        .preamble(&defipro_get_instr())
-        .temperature(0.3);
+    let knowledge = KnowledgeIndex::from_env(defaigent_embd_model.labeled("knowledge"))
+        .await
+        .expect("Failed indexing protocols knowledge");
+    knowledge.watch_from_env();
 
-    let (manager, receiver) = ChatHistoryManager::new();
+    let mut models = HashMap::new();
+    for spec in &agents.agents {
+        let profile = llm_config.profile(&spec.model).expect("Invalid LLM configuration");
+        let mut model = profile
+            .agent(&spec.name, &llm_config.retry, &usage)
+            .expect("Failed creating agent model");
+        if spec.retrieval {
+            let insights = vector_store.clone().index(defaigent_embd_model.labeled(&spec.name));
+            model = model
+                .dynamic_context(4, CitedIndex::new(insights, Source::insight))
+                .dynamic_context(DEFAULT_TOP_N, CitedIndex::new(knowledge.clone(), Source::knowledge));
+        }
+        info!("Agent {} model: {}", spec.name, profile.id());
+        models.insert(spec.name.clone(), model);
+    }
 
+    let (manager, receiver) = ChatHistoryManager::new();
 
-    let backend = Backend::new(yields_data.clone(), manager);
-    let tools = Tools::new(yields_data, backend.app_state.clone());
+    let backend = Backend::new(yields_data.clone(), manager, usage, prompts, agents);
+    let tools = Tools::new(yields_data, backend.app_state.clone(), tokens);
     let server_task = tokio::spawn(async move {
         backend
-            .start(nav_model, defaigent_model, tools, receiver)
+            .start(models, tools, receiver)
             .await
             .expect("didnt start")
     });
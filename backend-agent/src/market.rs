@@ -1,15 +1,26 @@
+use crate::amount::TokenAmount;
 use crate::math::{calculate_risk_score, STARKNET_TVL_ESTIMATE};
-use crate::tokens::{fetch_token_reserve, fetch_usdc_reserve};
+use crate::token_registry::TokenRegistry;
+use crate::tokens::fetch_token_reserve;
 use crate::types::{ComputeError, PoolType};
-
-use starknet::macros::felt;
+use starknet::providers::Provider;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MarketDataError {
+    #[error("Token {0} missing from the token registry")]
+    UnknownToken(String),
+    #[error("Failed reading the supply of {0}: {1}")]
+    Reserve(String, String),
+    #[error(transparent)]
+    Compute(#[from] ComputeError),
+}
 
 #[derive(Debug)]
 pub struct CoinMarketData {
     pub price: f64,
     pub a_name: String,
-    pub reserve_a: f64,
-    pub reserve_b: f64, // only usdc for now
+    pub reserve_a: TokenAmount,
+    pub reserve_b: TokenAmount, // only usdc for now
     pub volume_24h: f64,
     pub price_change_24h: f64,
 
@@ -24,64 +35,31 @@ pub struct CoinMarketData {
 }
 
 impl CoinMarketData {
-    pub async fn from_gecko_data(
+    /// Reserves are read through `provider`
+    pub async fn from_gecko_data<P: Provider + Sync>(
+        provider: &P,
+        registry: &TokenRegistry,
         token_name: &str,
         price: f64,
         volume_24h: f64,
         price_change_24h: f64,
         pool_type: PoolType,
-    ) -> CoinMarketData {
-        let usdc_total_supply: usize = fetch_usdc_reserve()
-            .await
-            .try_into()
-            .expect("Error converting Felt to usize");
-
-        let usdc_scaled = (usdc_total_supply as f64) / 10_f64.powf(6.0); // Starknet USDC has 6 Decimals https://voyager.online/token/0x053c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8#readFunctions
-        let mut a_name = String::new();
-        let decimals = 18.0; // ERC-20 Token by default use a value of 18 for decimals https://docs.openzeppelin.com/contracts/3.x/erc20#:~:text=By%20default%2C%20ERC20%20uses%20a%20value%20of%2018%20for%20decimals%20.
-        let reserve_a: u128;
-        let scaled_reserve_a: f64;
-        match token_name {
-            "STRK" => {
-                let contract_address =
-                    felt!("0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d");
-                reserve_a = fetch_token_reserve(contract_address)
-                    .await
-                    .try_into()
-                    .expect("Failed converting felt u128");
-
-                scaled_reserve_a = (reserve_a as f64) / 10_f64.powf(decimals);
-                a_name.push_str("STRK");
-            }
-            "ETH" => {
-                let contract_address =
-                    felt!("0x49d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7");
-                reserve_a = fetch_token_reserve(contract_address)
-                    .await
-                    .try_into()
-                    .expect("Failed converting felt to u128");
-                scaled_reserve_a = (reserve_a as f64) / 10_f64.powf(decimals);
-                a_name.push_str("ETH");
-            }
-            "BROTHER" => {
-                let contract_address =
-                    felt!("0x3b405a98c9e795d427fe82cdeeeed803f221b52471e3a757574a2b4180793ee");
-                reserve_a = fetch_token_reserve(contract_address)
-                    .await
-                    .try_into()
-                    .expect("Failed converting felt u128");
-
-                scaled_reserve_a = (reserve_a as f64) / 10_f64.powf(decimals);
-                a_name.push_str("BROTHER");
-            }
-            _ => panic!("Token name didnt match supported addresses: see `tokens.rs`"),
-        }
+    ) -> Result<CoinMarketData, MarketDataError> {
+        let usdc = registry
+            .by_symbol("USDC")
+            .ok_or_else(|| MarketDataError::UnknownToken("USDC".to_string()))?;
+        let token = registry
+            .by_symbol(token_name)
+            .ok_or_else(|| MarketDataError::UnknownToken(token_name.to_string()))?;
+        let usdc_reserve = fetch_token_reserve(provider, usdc.address.felt(), usdc.decimals).await?;
+        let reserve_a = fetch_token_reserve(provider, token.address.felt(), token.decimals).await?;
+        let a_name = token_name.to_string();
 
         let mut res = CoinMarketData {
             price,
             a_name,
-            reserve_a: scaled_reserve_a as f64,
-            reserve_b: usdc_scaled,
+            reserve_a,
+            reserve_b: usdc_reserve,
             volume_24h,
             price_change_24h,
             liquidity: 0.0, //default
@@ -90,19 +68,20 @@ impl CoinMarketData {
             risk_score: 0.0,
             pool_type,
         };
-        res.calculate_metrics().expect("error computing metrics");
+        res.calculate_metrics()?;
 
-        res
+        Ok(res)
     }
 
     pub fn calculate_metrics(&mut self) -> Result<(), ComputeError> {
-        if self.reserve_a == 0.0 || self.reserve_b == 0.0 {
+        if self.reserve_a.is_zero() || self.reserve_b.is_zero() {
             return Err(ComputeError::InvalidPool);
         }
+        let (reserve_a, reserve_b) = (self.reserve_a.to_f64(), self.reserve_b.to_f64());
 
-        self.liquidity = 2.0 * (self.reserve_a * self.reserve_b).sqrt();
+        self.liquidity = 2.0 * (reserve_a * reserve_b).sqrt();
 
-        self.tvl = (self.reserve_a + self.reserve_b) * self.price;
+        self.tvl = (reserve_a + reserve_b) * self.price;
 
         self.apy = self.estimate_apy()?;
 
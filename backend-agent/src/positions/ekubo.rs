@@ -0,0 +1,128 @@
+use super::{call, Position, PositionAmount, PositionError, PositionKind};
+use crate::amount::TokenAmount;
+use crate::token_registry::{TokenInfo, TokenRegistry};
+use crate::address::ContractAddress;
+use serde::{de, Deserialize, Deserializer};
+use starknet::{
+    core::types::{Felt, U256},
+    macros::selector,
+    providers::Provider,
+};
+
+/// A position NFT as listed by the Ekubo API
+#[derive(Deserialize)]
+struct ApiPosition {
+    #[serde(deserialize_with = "felt")]
+    id: Felt,
+    pool_key: PoolKey,
+    bounds: Bounds,
+}
+
+#[derive(Deserialize)]
+struct ApiPositions {
+    data: Vec<ApiPosition>,
+}
+
+#[derive(Deserialize)]
+struct PoolKey {
+    #[serde(deserialize_with = "felt")]
+    token0: Felt,
+    #[serde(deserialize_with = "felt")]
+    token1: Felt,
+    #[serde(deserialize_with = "felt")]
+    fee: Felt,
+    #[serde(deserialize_with = "felt")]
+    tick_spacing: Felt,
+    #[serde(deserialize_with = "felt")]
+    extension: Felt,
+}
+
+#[derive(Deserialize)]
+struct Bounds {
+    lower: i64,
+    upper: i64,
+}
+
+/// Hex or decimal string, or a number
+fn felt<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Felt, D::Error> {
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(n) => n.as_u64().map(Felt::from).ok_or_else(|| de::Error::custom("invalid felt")),
+        serde_json::Value::String(s) if s.starts_with("0x") => Felt::from_hex(&s).map_err(de::Error::custom),
+        serde_json::Value::String(s) => Felt::from_dec_str(&s).map_err(de::Error::custom),
+        _ => Err(de::Error::custom("invalid felt")),
+    }
+}
+
+/// Cairo `i129`: magnitude and sign
+fn i129(tick: i64) -> [Felt; 2] {
+    [Felt::from(tick.unsigned_abs()), Felt::from(tick < 0)]
+}
+
+/// Open positions of the wallet, each valued by `get_token_info` on the positions contract
+pub async fn positions<P: Provider + Sync>(
+    provider: &P,
+    client: &reqwest::Client,
+    tokens: &TokenRegistry,
+    wallet: ContractAddress,
+    positions_contract: ContractAddress,
+    api_url: &str,
+) -> Result<Vec<Position>, PositionError> {
+    let listed: ApiPositions = client
+        .get(format!("{}/positions/{}", api_url.trim_end_matches('/'), wallet))
+        .query(&[("state", "opened")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let mut positions = vec![];
+    for listed in listed.data {
+        let PoolKey {
+            token0,
+            token1,
+            fee,
+            tick_spacing,
+            extension,
+        } = listed.pool_key;
+        let mut calldata = vec![listed.id, token0, token1, fee, tick_spacing, extension];
+        calldata.extend(i129(listed.bounds.lower));
+        calldata.extend(i129(listed.bounds.upper));
+        // (pool_price: (sqrt_ratio: u256, tick: i129), liquidity, amount0, amount1, fees0, fees1)
+        let info = call(provider, positions_contract, "get_token_info", selector!("get_token_info"), calldata).await?;
+        let [amount0, amount1, fees0, fees1] = [5, 6, 7, 8].map(|i| info.get(i).copied());
+        let (Some(amount0), Some(amount1), Some(fees0), Some(fees1)) = (amount0, amount1, fees0, fees1) else {
+            return Err(PositionError::Invalid(positions_contract.to_string(), "token info"));
+        };
+        if [amount0, amount1, fees0, fees1].iter().all(|felt| *felt == Felt::ZERO) {
+            continue;
+        }
+
+        let [token0, token1] = [token0, token1].map(|token| {
+            ContractAddress::new(token).map_err(|_| PositionError::Invalid(positions_contract.to_string(), "pool key"))
+        });
+        let token0 = tokens.resolve(provider, token0?).await?;
+        let token1 = tokens.resolve(provider, token1?).await?;
+        let amount = |token: &TokenInfo, raw: Felt| -> Result<PositionAmount, PositionError> {
+            let raw = u128::try_from(raw).map_err(|_| PositionError::Invalid(positions_contract.to_string(), "amount"))?;
+            let amount = TokenAmount::new(U256::from(raw), token.decimals)
+                .map_err(|_| PositionError::Invalid(positions_contract.to_string(), "amount"))?;
+            Ok(PositionAmount::new(token, amount))
+        };
+        let rewards = [(&token0, fees0), (&token1, fees1)]
+            .into_iter()
+            .filter(|(_, fees)| *fees != Felt::ZERO)
+            .map(|(token, fees)| amount(token, fees))
+            .collect::<Result<_, _>>()?;
+
+        positions.push(Position {
+            protocol: "Ekubo".to_string(),
+            kind: PositionKind::Liquidity,
+            contract: positions_contract,
+            id: Some(listed.id.to_biguint().to_string()),
+            underlying: vec![amount(&token0, amount0)?, amount(&token1, amount1)?],
+            rewards,
+        });
+    }
+    Ok(positions)
+}
@@ -0,0 +1,36 @@
+use super::{call, u256_at, LendingSide, Position, PositionAmount, PositionError, PositionKind};
+use crate::amount::TokenAmount;
+use crate::token_registry::TokenRegistry;
+use crate::address::ContractAddress;
+use starknet::{macros::selector, providers::Provider};
+
+/// Supply or debt token balance, already in units of the underlying
+pub async fn positions<P: Provider + Sync>(
+    provider: &P,
+    tokens: &TokenRegistry,
+    wallet: ContractAddress,
+    protocol: &str,
+    address: ContractAddress,
+    underlying: ContractAddress,
+    side: LendingSide,
+) -> Result<Vec<Position>, PositionError> {
+    let balance = call(provider, address, "balanceOf", selector!("balanceOf"), vec![wallet.felt()]).await?;
+    let balance = u256_at(&balance, 0, address, "balance")?;
+    if balance.is_zero() {
+        return Ok(vec![]);
+    }
+    let underlying = tokens.resolve(provider, underlying).await?;
+    let amount = TokenAmount::new(balance.raw(), underlying.decimals).map_err(|_| PositionError::Invalid(address.to_string(), "balance"))?;
+
+    Ok(vec![Position {
+        protocol: protocol.to_string(),
+        kind: match side {
+            LendingSide::Supply => PositionKind::Supply,
+            LendingSide::Borrow => PositionKind::Borrow,
+        },
+        contract: address,
+        id: None,
+        underlying: vec![PositionAmount::new(&underlying, amount)],
+        rewards: vec![],
+    }])
+}
@@ -0,0 +1,48 @@
+use super::{call, u256_at, Position, PositionAmount, PositionError, PositionKind};
+use crate::amount::TokenAmount;
+use crate::token_registry::TokenRegistry;
+use crate::address::ContractAddress;
+use starknet::{macros::selector, providers::Provider};
+
+/// Pair token balance, worth its share of both reserves
+pub async fn positions<P: Provider + Sync>(
+    provider: &P,
+    tokens: &TokenRegistry,
+    wallet: ContractAddress,
+    protocol: &str,
+    address: ContractAddress,
+) -> Result<Vec<Position>, PositionError> {
+    let invalid = |what| PositionError::Invalid(address.to_string(), what);
+    let balance = call(provider, address, "balanceOf", selector!("balanceOf"), vec![wallet.felt()]).await?;
+    let balance = u256_at(&balance, 0, address, "balance")?;
+    if balance.is_zero() {
+        return Ok(vec![]);
+    }
+    let total_supply = call(provider, address, "totalSupply", selector!("totalSupply"), vec![]).await?;
+    let total_supply = u256_at(&total_supply, 0, address, "total supply")?;
+    // (reserve0: u256, reserve1: u256, block_timestamp_last)
+    let reserves = call(provider, address, "get_reserves", selector!("get_reserves"), vec![]).await?;
+
+    let mut underlying = vec![];
+    for (i, (name, entry_point)) in [("token0", selector!("token0")), ("token1", selector!("token1"))]
+        .into_iter()
+        .enumerate()
+    {
+        let token = call(provider, address, name, entry_point, vec![]).await?;
+        let token = token.first().and_then(|token| ContractAddress::new(*token).ok());
+        let token = tokens.resolve(provider, token.ok_or_else(|| invalid(name))?).await?;
+        let reserve = u256_at(&reserves, i * 2, address, "reserves")?;
+        let reserve = TokenAmount::new(reserve.raw(), token.decimals).map_err(|_| invalid("reserves"))?;
+        let share = reserve.mul_div(balance.raw(), total_supply.raw()).map_err(|_| invalid("total supply"))?;
+        underlying.push(PositionAmount::new(&token, share));
+    }
+
+    Ok(vec![Position {
+        protocol: protocol.to_string(),
+        kind: PositionKind::Liquidity,
+        contract: address,
+        id: None,
+        underlying,
+        rewards: vec![],
+    }])
+}
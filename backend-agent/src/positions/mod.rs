@@ -0,0 +1,242 @@
+//! Positions held through DeFi protocols, which plain token balances miss or misvalue
+
+mod ekubo;
+mod lending;
+mod lp;
+mod vault;
+
+use crate::address::ContractAddress;
+use crate::agent_tools::portfolio::BalanceError;
+use crate::amount::TokenAmount;
+use crate::token_registry::{TokenInfo, TokenRegistry, TokenRegistryError};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use starknet::{
+    core::types::{BlockId, BlockTag, Felt, FunctionCall},
+    providers::Provider,
+};
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Default adapters file, relative to the working directory like `tokens.json`. It only configures
+/// the vSTRK vault and Ekubo, lending and LP pair contracts have to be added to be detected.
+pub const DEFAULT_POSITIONS_FILE: &str = "positions.json";
+
+#[derive(Debug, thiserror::Error)]
+pub enum PositionError {
+    #[error("Failed reading position adapters: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid position adapters: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed calling {0} on {1}: {2}")]
+    Call(&'static str, String, String),
+    #[error("{0} returned an invalid {1}")]
+    Invalid(String, &'static str),
+    #[error(transparent)]
+    Token(#[from] TokenRegistryError),
+    #[error("Indexer request failed: {0}")]
+    Indexer(#[from] reqwest::Error),
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PositionKind {
+    Supply,
+    Borrow,
+    Liquidity,
+    Staking,
+}
+
+/// Amount of a token a position holds or owes
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct PositionAmount {
+    pub symbol: String,
+    pub token: ContractAddress,
+    pub amount: TokenAmount,
+    /// Set when the portfolio is valued
+    #[serde(rename = "valueUSD")]
+    pub value: Option<f64>,
+}
+
+impl PositionAmount {
+    pub fn new(token: &TokenInfo, amount: TokenAmount) -> Self {
+        Self {
+            symbol: token.symbol.clone(),
+            token: token.address,
+            amount,
+            value: None,
+        }
+    }
+}
+
+/// A position in a protocol, in terms of the tokens it's worth
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct Position {
+    pub protocol: String,
+    pub kind: PositionKind,
+    /// Receipt token, LP token or position NFT contract, its plain balance is the position itself
+    pub contract: ContractAddress,
+    /// Id of an NFT position
+    pub id: Option<String>,
+    pub underlying: Vec<PositionAmount>,
+    /// Accrued and unclaimed fees or rewards
+    pub rewards: Vec<PositionAmount>,
+}
+
+impl Position {
+    pub fn label(&self) -> String {
+        let kind = serde_json::to_value(self.kind).ok();
+        let kind = kind.as_ref().and_then(|kind| kind.as_str()).unwrap_or_default();
+        match &self.id {
+            Some(id) => format!("{} {} #{}", self.protocol, kind, id),
+            None => format!("{} {}", self.protocol, kind),
+        }
+    }
+
+    /// Underlying amounts and rewards with the sign they add to a portfolio: a debt's principal
+    /// is owed, its accrued rewards are still owned
+    pub fn signed_parts(&self) -> impl Iterator<Item = (f64, &PositionAmount)> {
+        let sign = if self.kind == PositionKind::Borrow { -1.0 } else { 1.0 };
+        let underlying = self.underlying.iter().map(move |part| (sign, part));
+        underlying.chain(self.rewards.iter().map(|part| (1.0, part)))
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LendingSide {
+    Supply,
+    Borrow,
+}
+
+/// How to find positions in one protocol contract
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "adapter", rename_all = "snake_case")]
+pub enum AdapterSpec {
+    /// Interest bearing supply or debt token whose balance is in underlying units, e.g. Nostra nTokens and dTokens
+    Lending {
+        protocol: String,
+        address: ContractAddress,
+        underlying: ContractAddress,
+        side: LendingSide,
+    },
+    /// Uniswap v2 style pair token, worth its share of the reserves
+    LpPair { protocol: String, address: ContractAddress },
+    /// ERC-4626 vault share, e.g. staked STRK, worth `convert_to_assets`
+    Vault { protocol: String, address: ContractAddress },
+    /// Ekubo position NFTs, listed by the Ekubo API and valued on-chain
+    Ekubo { positions: ContractAddress, api_url: String },
+}
+
+impl AdapterSpec {
+    pub fn protocol(&self) -> &str {
+        match self {
+            Self::Lending { protocol, .. } | Self::LpPair { protocol, .. } | Self::Vault { protocol, .. } => protocol,
+            Self::Ekubo { .. } => "Ekubo",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AdaptersFile {
+    adapters: Vec<AdapterSpec>,
+}
+
+/// Positions of every protocol, and the adapters that failed
+#[derive(Debug, Default)]
+pub struct PositionFetch {
+    pub positions: Vec<Position>,
+    pub errors: Vec<BalanceError>,
+}
+
+/// Protocol contracts checked for positions on each portfolio fetch
+#[derive(Debug, Clone, Default)]
+pub struct PositionAdapters {
+    pub adapters: Vec<AdapterSpec>,
+    client: reqwest::Client,
+}
+
+impl PositionAdapters {
+    pub fn new(adapters: Vec<AdapterSpec>) -> Self {
+        Self {
+            adapters,
+            ..Default::default()
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PositionError> {
+        let file: AdaptersFile = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Ok(Self::new(file.adapters))
+    }
+
+    /// Adapters from `POSITIONS_FILE`, `positions.json` by default
+    pub fn from_env() -> Result<Self, PositionError> {
+        Self::load(std::env::var("POSITIONS_FILE").unwrap_or_else(|_| DEFAULT_POSITIONS_FILE.to_string()))
+    }
+
+    /// Positions of `wallet`, an adapter failing only fails its protocol
+    pub async fn fetch<P: Provider + Sync>(&self, provider: &P, tokens: &TokenRegistry, wallet: ContractAddress) -> PositionFetch {
+        let mut fetch = PositionFetch::default();
+        for spec in &self.adapters {
+            let result = match spec {
+                AdapterSpec::Lending {
+                    protocol,
+                    address,
+                    underlying,
+                    side,
+                } => lending::positions(provider, tokens, wallet, protocol, *address, *underlying, *side).await,
+                AdapterSpec::LpPair { protocol, address } => lp::positions(provider, tokens, wallet, protocol, *address).await,
+                AdapterSpec::Vault { protocol, address } => {
+                    vault::positions(provider, tokens, wallet, protocol, *address).await
+                }
+                AdapterSpec::Ekubo { positions, api_url } => {
+                    ekubo::positions(provider, &self.client, tokens, wallet, *positions, api_url).await
+                }
+            };
+            match result {
+                Ok(positions) => fetch.positions.extend(positions),
+                Err(e) => {
+                    warn!("{} positions unavailable: {}", spec.protocol(), e);
+                    fetch.errors.push(BalanceError {
+                        token: spec.protocol().to_string(),
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+        info!(
+            "Fetched positions: {} found, {} adapters failed",
+            fetch.positions.len(),
+            fetch.errors.len()
+        );
+        fetch
+    }
+}
+
+async fn call<P: Provider + Sync>(
+    provider: &P,
+    address: ContractAddress,
+    name: &'static str,
+    entry_point_selector: Felt,
+    calldata: Vec<Felt>,
+) -> Result<Vec<Felt>, PositionError> {
+    provider
+        .call(
+            FunctionCall {
+                contract_address: address.felt(),
+                entry_point_selector,
+                calldata,
+            },
+            BlockId::Tag(BlockTag::Latest),
+        )
+        .await
+        .map_err(|e| PositionError::Call(name, address.to_string(), e.to_string()))
+}
+
+/// u256 at `offset` of a call result
+fn u256_at(felts: &[Felt], offset: usize, contract: ContractAddress, what: &'static str) -> Result<TokenAmount, PositionError> {
+    felts
+        .get(offset..offset + 2)
+        .and_then(|limbs| TokenAmount::from_u256_felts(limbs, 0).ok())
+        .ok_or_else(|| PositionError::Invalid(contract.to_string(), what))
+}
@@ -0,0 +1,46 @@
+use super::{call, u256_at, Position, PositionAmount, PositionError, PositionKind};
+use crate::amount::TokenAmount;
+use crate::token_registry::TokenRegistry;
+use crate::address::ContractAddress;
+use starknet::{core::types::Felt, macros::selector, providers::Provider};
+
+/// ERC-4626 shares, worth what the vault would redeem them for
+pub async fn positions<P: Provider + Sync>(
+    provider: &P,
+    tokens: &TokenRegistry,
+    wallet: ContractAddress,
+    protocol: &str,
+    address: ContractAddress,
+) -> Result<Vec<Position>, PositionError> {
+    let shares = call(provider, address, "balanceOf", selector!("balanceOf"), vec![wallet.felt()]).await?;
+    let shares = u256_at(&shares, 0, address, "balance")?;
+    if shares.is_zero() {
+        return Ok(vec![]);
+    }
+    let asset = call(provider, address, "asset", selector!("asset"), vec![]).await?;
+    let asset = asset
+        .first()
+        .and_then(|asset| ContractAddress::new(*asset).ok())
+        .ok_or_else(|| PositionError::Invalid(address.to_string(), "asset"))?;
+    let asset = tokens.resolve(provider, asset).await?;
+    let (low, high) = (shares.raw().low(), shares.raw().high());
+    let assets = call(
+        provider,
+        address,
+        "convert_to_assets",
+        selector!("convert_to_assets"),
+        vec![Felt::from(low), Felt::from(high)],
+    )
+    .await?;
+    let assets = u256_at(&assets, 0, address, "assets")?;
+    let amount = TokenAmount::new(assets.raw(), asset.decimals).map_err(|_| PositionError::Invalid(address.to_string(), "assets"))?;
+
+    Ok(vec![Position {
+        protocol: protocol.to_string(),
+        kind: PositionKind::Staking,
+        contract: address,
+        id: None,
+        underlying: vec![PositionAmount::new(&asset, amount)],
+        rewards: vec![],
+    }])
+}
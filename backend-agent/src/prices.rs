@@ -0,0 +1,145 @@
+use crate::address::ContractAddress;
+use crate::types::ProtocolYield;
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Default CoinGecko API, override with `COINGECKO_API_URL`
+pub const COINGECKO_API_URL: &str = "https://api.coingecko.com/api/v3";
+// chain id on coingecko
+const CHAIN_ID: &str = "starknet";
+
+#[derive(Debug, thiserror::Error)]
+pub enum PriceError {
+    #[error("CoinGecko isn't configured, set COINGECKO_API_KEY")]
+    NotConfigured,
+    #[error("CoinGecko request failed: {0}")]
+    Request(#[from] reqwest::Error),
+}
+
+/// Price and 24h market activity of a token
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenMarket {
+    pub price: f64,
+    pub volume_24h: f64,
+    pub price_change_24h: f64,
+}
+
+#[derive(Debug, Clone)]
+struct CoinGecko {
+    url: String,
+    api_key: String,
+}
+
+/// USD prices by token address: CoinGecko when configured, else the prices known from the yields data
+#[derive(Debug, Clone, Default)]
+pub struct PriceFeed {
+    client: reqwest::Client,
+    coingecko: Option<CoinGecko>,
+    known: HashMap<ContractAddress, f64>,
+}
+
+impl PriceFeed {
+    /// Fixed prices only
+    pub fn new(known: impl IntoIterator<Item = (ContractAddress, f64)>) -> Self {
+        Self {
+            known: known.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Prices of the yields tokens, looked up live on CoinGecko when `COINGECKO_API_KEY` is set
+    pub fn from_env(yields: &[ProtocolYield]) -> Self {
+        let feed = Self::from_yields(yields);
+        match std::env::var("COINGECKO_API_KEY").ok().filter(|key| !key.is_empty()) {
+            Some(api_key) => feed.with_coingecko(
+                &std::env::var("COINGECKO_API_URL").unwrap_or_else(|_| COINGECKO_API_URL.to_string()),
+                &api_key,
+            ),
+            None => feed,
+        }
+    }
+
+    pub fn from_yields(yields: &[ProtocolYield]) -> Self {
+        Self::new(yields.iter().map(|y| (y.token.address, y.token.price.to_f64())))
+    }
+
+    pub fn with_coingecko(mut self, url: &str, api_key: &str) -> Self {
+        self.coingecko = Some(CoinGecko {
+            url: url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+        });
+        self
+    }
+
+    /// Prices of `tokens` that could be found, live ones first
+    pub async fn prices(&self, tokens: &[ContractAddress]) -> HashMap<ContractAddress, f64> {
+        let mut prices: HashMap<ContractAddress, f64> = tokens
+            .iter()
+            .filter_map(|token| self.known.get(token).map(|price| (*token, *price)))
+            .collect();
+        if let Some(coingecko) = &self.coingecko {
+            match self.coingecko_prices(coingecko, tokens).await {
+                Ok(live) => prices.extend(live),
+                Err(e) => warn!("CoinGecko prices unavailable, using known prices: {}", e),
+            }
+        }
+        prices
+    }
+
+    /// Live prices and 24h volume and change of `tokens` listed on CoinGecko, which has to be configured
+    pub async fn markets(&self, tokens: &[ContractAddress]) -> Result<HashMap<ContractAddress, TokenMarket>, PriceError> {
+        let coingecko = self.coingecko.as_ref().ok_or(PriceError::NotConfigured)?;
+        let markets = self
+            .token_price(coingecko, tokens, &[("include_24hr_vol", "true"), ("include_24hr_change", "true")])
+            .await?;
+        Ok(markets
+            .into_iter()
+            .filter_map(|(address, data)| {
+                let field = |name: &str| data.get(name).copied().unwrap_or_default();
+                Some((
+                    address,
+                    TokenMarket {
+                        price: *data.get("usd")?,
+                        volume_24h: field("usd_24h_vol"),
+                        price_change_24h: field("usd_24h_change"),
+                    },
+                ))
+            })
+            .collect())
+    }
+
+    async fn coingecko_prices(&self, coingecko: &CoinGecko, tokens: &[ContractAddress]) -> Result<HashMap<ContractAddress, f64>, reqwest::Error> {
+        let prices = self.token_price(coingecko, tokens, &[]).await?;
+        Ok(prices
+            .into_iter()
+            .filter_map(|(address, data)| Some((address, *data.get("usd")?)))
+            .collect())
+    }
+
+    /// `simple/token_price` fields by token, `extra` adds fields next to the USD price
+    async fn token_price(
+        &self,
+        coingecko: &CoinGecko,
+        tokens: &[ContractAddress],
+        extra: &[(&str, &str)],
+    ) -> Result<HashMap<ContractAddress, HashMap<String, f64>>, reqwest::Error> {
+        let addresses = tokens.iter().map(|token| token.to_string()).collect::<Vec<_>>().join(",");
+        let data: HashMap<String, HashMap<String, f64>> = self
+            .client
+            .get(format!("{}/simple/token_price/{CHAIN_ID}", coingecko.url))
+            .query(&[("contract_addresses", addresses.as_str()), ("vs_currencies", "usd")])
+            .query(extra)
+            .header("accept", "application/json")
+            .header("x-cg-demo-api-key", &coingecko.api_key)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        // addresses come back in CoinGecko's own format
+        Ok(data
+            .into_iter()
+            .filter_map(|(address, fields)| Some((address.parse().ok()?, fields)))
+            .collect())
+    }
+}
@@ -0,0 +1,380 @@
+use crate::types::{Expertise, ProtocolYield, RiskProfile};
+use crate::utils::fnv1a;
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Default templates directory, relative to the working directory like `knowledge/`
+pub const DEFAULT_PROMPTS_DIR: &str = "prompts";
+/// Optional A/B weights per template, inside the templates directory
+const EXPERIMENTS_FILE: &str = "experiments.json";
+const DEFAULT_RELOAD_SECS: u64 = 10;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    #[error("Unknown variable {{{{{0}}}}} in {1}")]
+    UnknownVariable(String, String),
+    #[error("Unclosed {{{{ in {0}")]
+    Unclosed(String),
+    #[error("No template named {0}")]
+    NotFound(String),
+    #[error("Failed reading templates: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid {EXPERIMENTS_FILE}: {0}")]
+    Experiments(#[from] serde_json::Error),
+}
+
+/// Variables a template can reference as `{{name}}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptVar {
+    Date,
+    SupportedTokens,
+    RiskProfile,
+    YieldsAsOf,
+    Expertise,
+    /// Lines an answer may take at the user's expertise
+    MaxLines,
+    /// One line per pool with its APY, APR, TVL, risk score and token address
+    Pools,
+}
+
+impl FromStr for PromptVar {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "date" => Ok(Self::Date),
+            "supported_tokens" => Ok(Self::SupportedTokens),
+            "risk_profile" => Ok(Self::RiskProfile),
+            "yields_as_of" => Ok(Self::YieldsAsOf),
+            "expertise" => Ok(Self::Expertise),
+            "max_lines" => Ok(Self::MaxLines),
+            "pools" => Ok(Self::Pools),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Values of the template variables for one turn
+#[derive(Debug, Clone)]
+pub struct PromptVars {
+    pub date: DateTime<Utc>,
+    pub supported_tokens: Vec<String>,
+    pub risk_profile: RiskProfile,
+    pub yields_as_of: Option<DateTime<Utc>>,
+    pub expertise: Expertise,
+    pub max_lines: usize,
+    pub pools: Vec<ProtocolYield>,
+}
+
+impl Default for PromptVars {
+    fn default() -> Self {
+        Self {
+            date: Utc::now(),
+            supported_tokens: vec![],
+            risk_profile: RiskProfile::default(),
+            yields_as_of: None,
+            expertise: Expertise::default(),
+            max_lines: 3,
+            pools: vec![],
+        }
+    }
+}
+
+impl PromptVars {
+    fn value(&self, var: PromptVar) -> String {
+        match var {
+            PromptVar::Date => self.date.format("%Y-%m-%d").to_string(),
+            PromptVar::SupportedTokens => self.supported_tokens.join(", "),
+            PromptVar::RiskProfile => self.risk_profile.to_string(),
+            PromptVar::YieldsAsOf => self
+                .yields_as_of
+                .map_or("unknown".to_string(), |as_of| as_of.format("%Y-%m-%d %H:%M UTC").to_string()),
+            PromptVar::Expertise => self.expertise.to_string(),
+            PromptVar::MaxLines => self.max_lines.to_string(),
+            PromptVar::Pools => self
+                .pools
+                .iter()
+                .map(|y| {
+                    format!(
+                        "- {} {:?} pool: APY {:.2}% (APR {:.2}% compounded daily), TVL ${:.0}, 24h volume ${:.0}, risk score {:.0}/100, token {}",
+                        y.token.name, y.pool_type, y.apy, y.apr(), y.tvl, y.volume_24h, y.risk_score, y.token.address
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Var(PromptVar),
+}
+
+/// A parsed template, `prompts/<name>/<version>.md`
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub version: String,
+    segments: Vec<Segment>,
+    modified: Option<SystemTime>,
+}
+
+impl PromptTemplate {
+    /// Parses `source`, rejecting variables that aren't [`PromptVar`]s
+    pub fn parse(name: &str, version: &str, source: &str) -> Result<Self, TemplateError> {
+        let id = format!("{name}/{version}");
+        let mut segments = vec![];
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            segments.push(Segment::Text(rest[..start].to_string()));
+            let end = rest[start..].find("}}").ok_or_else(|| TemplateError::Unclosed(id.clone()))?;
+            let var_name = rest[start + 2..start + end].trim();
+            let var = var_name
+                .parse()
+                .map_err(|_| TemplateError::UnknownVariable(var_name.to_string(), id.clone()))?;
+            segments.push(Segment::Var(var));
+            rest = &rest[start + end + 2..];
+        }
+        segments.push(Segment::Text(rest.to_string()));
+
+        Ok(Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            segments,
+            modified: None,
+        })
+    }
+
+    /// `name/version`, as recorded in turn metadata
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.name, self.version)
+    }
+
+    pub fn render(&self, vars: &PromptVars) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Var(var) => vars.value(*var),
+            })
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+}
+
+/// Version weights per template name to run several versions side by side,
+/// `experiments.json`: `{"defiproman": {"v1": 50, "v2": 50}}`
+pub type Experiments = HashMap<String, BTreeMap<String, u32>>;
+
+#[derive(Default)]
+struct Library {
+    /// Versions per template name
+    templates: HashMap<String, BTreeMap<String, PromptTemplate>>,
+    experiments: Experiments,
+    experiments_modified: Option<SystemTime>,
+    /// Versions forced regardless of experiments, e.g. by evals
+    pinned: HashMap<String, String>,
+}
+
+/// Prompt templates loaded from a directory, changed files are picked up by [`PromptLibrary::watch`].
+/// Clones share the templates.
+#[derive(Clone)]
+pub struct PromptLibrary {
+    dir: PathBuf,
+    library: Arc<RwLock<Library>>,
+}
+
+impl PromptLibrary {
+    pub fn load(dir: impl Into<PathBuf>) -> Result<Self, TemplateError> {
+        let library = Self {
+            dir: dir.into(),
+            library: Arc::default(),
+        };
+        library.reload()?;
+        Ok(library)
+    }
+
+    /// Templates from `PROMPTS_DIR`, `prompts` by default
+    pub fn from_env() -> Result<Self, TemplateError> {
+        Self::load(std::env::var("PROMPTS_DIR").unwrap_or_else(|_| DEFAULT_PROMPTS_DIR.to_string()))
+    }
+
+    /// Re-reads templates whose file changed since the last load.
+    /// A template failing to parse keeps its previous version.
+    pub fn reload(&self) -> Result<(), TemplateError> {
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.reload_templates(&path)?;
+            }
+        }
+
+        let experiments_path = self.dir.join(EXPERIMENTS_FILE);
+        let modified = modified(&experiments_path);
+        if modified != self.library.read().experiments_modified {
+            let experiments = match modified {
+                Some(_) => serde_json::from_str(&std::fs::read_to_string(&experiments_path)?)?,
+                None => HashMap::new(),
+            };
+            let mut library = self.library.write();
+            library.experiments = experiments;
+            library.experiments_modified = modified;
+            info!("Loaded prompt experiments: {:?}", library.experiments);
+        }
+        Ok(())
+    }
+
+    /// Reloads every `interval` in the background, the file reads run on the blocking pool
+    pub fn watch(&self, interval: Duration) -> JoinHandle<()> {
+        let library = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let library = library.clone();
+                if let Ok(Err(e)) = tokio::task::spawn_blocking(move || library.reload()).await {
+                    warn!("Prompt templates reload failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// [`Self::watch`] every `PROMPTS_RELOAD_SECS`, 10 by default
+    pub fn watch_from_env(&self) -> JoinHandle<()> {
+        let secs = std::env::var("PROMPTS_RELOAD_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(DEFAULT_RELOAD_SECS);
+        self.watch(Duration::from_secs(secs))
+    }
+
+    fn reload_templates(&self, dir: &Path) -> Result<(), TemplateError> {
+        let Some(name) = dir.file_name().and_then(|name| name.to_str()) else {
+            return Ok(());
+        };
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let Some(version) = path
+                .extension()
+                .filter(|extension| *extension == "md")
+                .and_then(|_| path.file_stem())
+                .and_then(|version| version.to_str())
+            else {
+                continue;
+            };
+            let modified = modified(&path);
+            let unchanged = self
+                .library
+                .read()
+                .templates
+                .get(name)
+                .and_then(|versions| versions.get(version))
+                .is_some_and(|template| template.modified == modified);
+            if unchanged {
+                continue;
+            }
+
+            match PromptTemplate::parse(name, version, &std::fs::read_to_string(&path)?) {
+                Ok(mut template) => {
+                    template.modified = modified;
+                    info!("Loaded prompt template {}", template.id());
+                    self.library
+                        .write()
+                        .templates
+                        .entry(name.to_string())
+                        .or_default()
+                        .insert(version.to_string(), template);
+                }
+                Err(e) => warn!("Keeping previous {}/{}: {}", name, version, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Template `name` for `session_id`: the pinned version, else a version picked by experiment
+    /// weights (stable per session), else the latest version
+    pub fn select(&self, name: &str, session_id: &str) -> Result<PromptTemplate, TemplateError> {
+        let library = self.library.read();
+        let versions = library
+            .templates
+            .get(name)
+            .ok_or_else(|| TemplateError::NotFound(name.to_string()))?;
+
+        let version = library
+            .pinned
+            .get(name)
+            .cloned()
+            .or_else(|| library.experiments.get(name).and_then(|weights| pick(weights, session_id)));
+        version
+            .and_then(|version| versions.get(&version))
+            .or_else(|| versions.values().max_by(|a, b| version_order(&a.version, &b.version)))
+            .cloned()
+            .ok_or_else(|| TemplateError::NotFound(name.to_string()))
+    }
+
+    /// Whether a template `name` is loaded, e.g. an expertise variant
+    pub fn contains(&self, name: &str) -> bool {
+        self.library.read().templates.contains_key(name)
+    }
+
+    /// Forces `version` of `name`, `None` goes back to experiments
+    pub fn pin(&self, name: &str, version: Option<&str>) {
+        let mut library = self.library.write();
+        match version {
+            Some(version) => library.pinned.insert(name.to_string(), version.to_string()),
+            None => library.pinned.remove(name),
+        };
+    }
+
+    /// Loaded versions per template name, oldest first
+    pub fn versions(&self) -> HashMap<String, Vec<String>> {
+        self.library
+            .read()
+            .templates
+            .iter()
+            .map(|(name, versions)| {
+                let mut versions: Vec<String> = versions.keys().cloned().collect();
+                versions.sort_by(|a, b| version_order(a, b));
+                (name.clone(), versions)
+            })
+            .collect()
+    }
+}
+
+/// `v2` before `v10`, versions without a number first and by name
+fn version_order(a: &str, b: &str) -> std::cmp::Ordering {
+    let number = |version: &str| version.strip_prefix('v').unwrap_or(version).parse::<u64>().ok();
+    number(a).cmp(&number(b)).then_with(|| a.cmp(b))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Weighted pick, deterministic for a session so it keeps the same variant
+fn pick(weights: &BTreeMap<String, u32>, session_id: &str) -> Option<String> {
+    let total: u32 = weights.values().sum();
+    if total == 0 {
+        return None;
+    }
+    let mut ticket = (fnv1a(session_id) % total as u64) as u32;
+    weights.iter().find_map(|(version, weight)| {
+        if ticket < *weight {
+            Some(version.clone())
+        } else {
+            ticket -= weight;
+            None
+        }
+    })
+}
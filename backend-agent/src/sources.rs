@@ -0,0 +1,135 @@
+use crate::address::ContractAddress;
+use crate::agents::turn;
+use chrono::{DateTime, Utc};
+use rig::vector_store::{VectorStoreError, VectorStoreIndex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Where a piece of context came from, returned with the answer so it can be cited.
+/// Ids are stable for the same data: `tweet:<author>-<unix time>`, `kb:<chunk id>`,
+/// `yields:<unix time>` and `portfolio:<wallet>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Source {
+    Insight {
+        id: String,
+        author: String,
+        timestamp: String,
+    },
+    Knowledge {
+        id: String,
+        protocol: String,
+        section: String,
+    },
+    Yields {
+        id: String,
+        as_of: DateTime<Utc>,
+    },
+    Portfolio {
+        id: String,
+        wallet: ContractAddress,
+        fetched_at: DateTime<Utc>,
+    },
+}
+
+impl Source {
+    pub fn id(&self) -> &str {
+        match self {
+            Source::Insight { id, .. }
+            | Source::Knowledge { id, .. }
+            | Source::Yields { id, .. }
+            | Source::Portfolio { id, .. } => id,
+        }
+    }
+
+    /// From a serialized [`crate::types::TwitterInsight`]
+    pub fn insight(document: &Value) -> Option<Self> {
+        let author = document["author"].as_str()?.to_string();
+        let timestamp = document["timestamp"].as_str()?.to_string();
+        let unix = chrono::NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%d %H:%M:%S UTC")
+            .map(|datetime| datetime.and_utc().timestamp().to_string())
+            .unwrap_or_else(|_| timestamp.clone());
+        Some(Source::Insight {
+            id: format!("tweet:{author}-{unix}"),
+            author,
+            timestamp,
+        })
+    }
+
+    /// From a serialized [`crate::types::DefiKnowledge`]
+    pub fn knowledge(document: &Value) -> Option<Self> {
+        Some(Source::Knowledge {
+            id: format!("kb:{}", document["id"].as_str()?),
+            protocol: document["protocol"].as_str()?.to_string(),
+            section: document["section"].as_str()?.to_string(),
+        })
+    }
+
+    pub fn yields(as_of: DateTime<Utc>) -> Self {
+        Source::Yields {
+            id: format!("yields:{}", as_of.timestamp()),
+            as_of,
+        }
+    }
+
+    pub fn portfolio(wallet: ContractAddress) -> Self {
+        Source::Portfolio {
+            id: format!("portfolio:{wallet}"),
+            wallet,
+            fetched_at: Utc::now(),
+        }
+    }
+}
+
+/// Records `source` as used by the current turn, once per id. Returns the id, to label the data
+/// handed to the model as `[<id>]` like the retrieved documents.
+pub fn cite(source: Source) -> String {
+    let id = source.id().to_string();
+    turn::record(|metadata| {
+        if !metadata.sources.iter().any(|cited| cited.id() == source.id()) {
+            metadata.sources.push(source);
+        }
+    });
+    id
+}
+
+/// Vector index whose results are cited, and handed to the model under their source id
+pub struct CitedIndex<I> {
+    index: I,
+    source: fn(&Value) -> Option<Source>,
+}
+
+impl<I: VectorStoreIndex> CitedIndex<I> {
+    pub fn new(index: I, source: fn(&Value) -> Option<Source>) -> Self {
+        Self { index, source }
+    }
+}
+
+impl<I: VectorStoreIndex> VectorStoreIndex for CitedIndex<I> {
+    async fn top_n<T: for<'a> Deserialize<'a> + Send>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
+        self.index
+            .top_n::<Value>(query, n)
+            .await?
+            .into_iter()
+            .map(|(score, id, document)| {
+                let id = match (self.source)(&document) {
+                    Some(source) => {
+                        let id = source.id().to_string();
+                        cite(source);
+                        id
+                    }
+                    None => id,
+                };
+                Ok((score, id, serde_json::from_value(document)?))
+            })
+            .collect()
+    }
+
+    async fn top_n_ids(&self, query: &str, n: usize) -> Result<Vec<(f64, String)>, VectorStoreError> {
+        self.index.top_n_ids(query, n).await
+    }
+}
@@ -0,0 +1,260 @@
+use crate::address::ContractAddress;
+use parking_lot::RwLock;
+use starknet::{
+    core::types::{BlockId, BlockTag, Felt, FunctionCall},
+    macros::{felt, selector},
+    providers::{
+        jsonrpc::{HttpTransport, JsonRpcClient},
+        Provider, Url,
+    },
+};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Starknet ID naming contract on mainnet, override with `STARKNET_ID_CONTRACT`
+pub const NAMING_CONTRACT: Felt = felt!("0x06ac597f8116f886fa1c97a23fa4e08299975ecaf6b598873ca6792b9bbfb678");
+
+/// How long a lookup is trusted, domains can be transferred or repointed
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(3600);
+/// Lookups kept per direction
+const DEFAULT_CACHE_SIZE: usize = 10_000;
+
+/// Characters a label is encoded with, a trailing `a` is escaped
+const BASIC_ALPHABET: &str = "abcdefghijklmnopqrstuvwxyz0123456789-";
+const BASIC_SIZE: u64 = 37;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StarknetIdError {
+    #[error("Invalid Starknet ID domain {0}")]
+    InvalidDomain(String),
+    #[error("{0} is neither a Starknet address nor a .stark domain")]
+    InvalidAddress(String),
+    #[error("Domain {0} doesn't point to any address")]
+    NotFound(String),
+    #[error("Failed calling the naming contract: {0}")]
+    Call(String),
+}
+
+/// True for inputs such as `alice.stark` or `wallet.alice.stark`
+pub fn is_domain(input: &str) -> bool {
+    input.len() > ".stark".len() && input.to_ascii_lowercase().ends_with(".stark")
+}
+
+/// First Starknet ID domain typed in the prompt, lowercased
+pub fn domain_in_prompt(prompt: &str) -> Option<String> {
+    prompt
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '-'))
+        .map(|word| word.trim_end_matches('.'))
+        .find(|word| is_domain(word) && encode_domain(word).is_ok())
+        .map(|word| word.to_ascii_lowercase())
+}
+
+/// Labels of `domain`, subdomains first, as the naming contract expects them
+pub fn encode_domain(domain: &str) -> Result<Vec<Felt>, StarknetIdError> {
+    let invalid = || StarknetIdError::InvalidDomain(domain.to_string());
+    let lowercase = domain.to_ascii_lowercase();
+    let name = lowercase.strip_suffix(".stark").filter(|_| is_domain(domain)).ok_or_else(invalid)?;
+    name.split('.').map(|label| encode_label(label).ok_or_else(invalid)).collect()
+}
+
+/// `None` for empty labels, or labels outside the basic alphabet
+pub fn encode_label(label: &str) -> Option<Felt> {
+    if label.is_empty() {
+        return None;
+    }
+    let (mut encoded, mut multiplier) = (Felt::ZERO, Felt::ONE);
+    let last = label.chars().count() - 1;
+    for (i, c) in label.chars().enumerate() {
+        let index = BASIC_ALPHABET.find(c)? as u64;
+        // a trailing `a` would encode to nothing, it's escaped
+        let code = if i == last && index == 0 { BASIC_SIZE } else { index };
+        encoded += multiplier * Felt::from(code);
+        multiplier *= Felt::from(BASIC_SIZE + 1);
+    }
+    Some(encoded)
+}
+
+/// `None` for labels using characters beyond the basic alphabet
+pub fn decode_label(encoded: Felt) -> Option<String> {
+    let mut rest = encoded.to_bytes_be();
+    let mut decoded = String::new();
+    while rest != [0; 32] {
+        let code = div_rem(&mut rest, BASIC_SIZE + 1);
+        if code == BASIC_SIZE {
+            // only the escaped trailing `a` is supported
+            if rest != [0; 32] {
+                return None;
+            }
+            decoded.push('a');
+        } else {
+            decoded.push(BASIC_ALPHABET.as_bytes()[code as usize] as char);
+        }
+    }
+    Some(decoded)
+}
+
+/// Divides the big-endian `value` by `divisor` in place, returns the remainder
+fn div_rem(value: &mut [u8; 32], divisor: u64) -> u64 {
+    let mut remainder = 0;
+    for byte in value.iter_mut() {
+        let current = (remainder << 8) | u64::from(*byte);
+        *byte = (current / divisor) as u8;
+        remainder = current % divisor;
+    }
+    remainder
+}
+
+/// Lookups expiring after `ttl`, the oldest evicted past `capacity`
+struct LookupCache<K, V> {
+    entries: HashMap<K, (V, Instant)>,
+    ttl: Duration,
+    capacity: usize,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> LookupCache<K, V> {
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            ttl,
+            capacity,
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        let (value, cached_at) = self.entries.get(key)?;
+        (cached_at.elapsed() < self.ttl).then(|| value.clone())
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let ttl = self.ttl;
+        self.entries.retain(|_, (_, cached_at)| cached_at.elapsed() < ttl);
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, cached_at))| *cached_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, (value, Instant::now()));
+    }
+}
+
+/// Resolves Starknet ID domains to addresses and back through the naming contract. Clones share
+/// the lookups already made, which are kept for an hour by default.
+#[derive(Clone)]
+pub struct StarknetId {
+    rpc_url: Url,
+    naming_contract: Felt,
+    domains: Arc<RwLock<LookupCache<String, ContractAddress>>>,
+    names: Arc<RwLock<LookupCache<ContractAddress, Option<String>>>>,
+}
+
+impl StarknetId {
+    /// Names are read from the naming contract on the node at `rpc_url`
+    pub fn new(rpc_url: Url, naming_contract: Felt) -> Self {
+        Self {
+            rpc_url,
+            naming_contract,
+            domains: Arc::new(RwLock::new(LookupCache::new(DEFAULT_CACHE_TTL, DEFAULT_CACHE_SIZE))),
+            names: Arc::new(RwLock::new(LookupCache::new(DEFAULT_CACHE_TTL, DEFAULT_CACHE_SIZE))),
+        }
+    }
+
+    /// Keeps lookups for `ttl`, at most `capacity` of them each way
+    pub fn with_cache(self, ttl: Duration, capacity: usize) -> Self {
+        Self {
+            domains: Arc::new(RwLock::new(LookupCache::new(ttl, capacity))),
+            names: Arc::new(RwLock::new(LookupCache::new(ttl, capacity))),
+            ..self
+        }
+    }
+
+    /// Naming contract from `STARKNET_ID_CONTRACT`, the mainnet one by default, lookups kept
+    /// `STARKNET_ID_CACHE_TTL_SECS` and at most `STARKNET_ID_CACHE_SIZE` of them
+    pub fn from_env(rpc_url: Url) -> Self {
+        let naming_contract = std::env::var("STARKNET_ID_CONTRACT")
+            .ok()
+            .map(|contract| Felt::from_hex(&contract).expect("Invalid STARKNET_ID_CONTRACT"))
+            .unwrap_or(NAMING_CONTRACT);
+        let var = |name: &str| std::env::var(name).ok();
+        let ttl = var("STARKNET_ID_CACHE_TTL_SECS")
+            .and_then(|secs| secs.parse().ok())
+            .map_or(DEFAULT_CACHE_TTL, Duration::from_secs);
+        let capacity = var("STARKNET_ID_CACHE_SIZE")
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_CACHE_SIZE);
+        Self::new(rpc_url, naming_contract).with_cache(ttl, capacity)
+    }
+
+    /// Address `domain` points to
+    pub async fn resolve(&self, domain: &str) -> Result<ContractAddress, StarknetIdError> {
+        let domain = domain.to_ascii_lowercase();
+        if let Some(address) = self.domains.read().get(&domain) {
+            return Ok(address);
+        }
+        let labels = encode_domain(&domain)?;
+        // domain_to_address(domain: Span<felt252>, hint: Span<felt252>)
+        let mut calldata = vec![Felt::from(labels.len())];
+        calldata.extend(labels);
+        calldata.push(Felt::ZERO);
+        let result = self.call(selector!("domain_to_address"), calldata).await?;
+        let address = result
+            .first()
+            .filter(|address| **address != Felt::ZERO)
+            .and_then(|address| ContractAddress::new(*address).ok());
+        let address = address.ok_or_else(|| StarknetIdError::NotFound(domain.clone()))?;
+        self.domains.write().insert(domain, address);
+        Ok(address)
+    }
+
+    /// Main domain of `address`, `None` when it has none
+    pub async fn reverse(&self, address: ContractAddress) -> Result<Option<String>, StarknetIdError> {
+        if let Some(name) = self.names.read().get(&address) {
+            return Ok(name);
+        }
+        // address_to_domain(address, hint: Span<felt252>) -> Span<felt252>
+        let result = self.call(selector!("address_to_domain"), vec![address.felt(), Felt::ZERO]).await?;
+        let length = result.first().and_then(|length| usize::try_from(*length).ok()).unwrap_or_default();
+        let labels = result.get(1..=length).unwrap_or_default();
+        let labels = labels
+            .iter()
+            .map(|label| decode_label(*label).filter(|label| !label.is_empty()))
+            .collect::<Option<Vec<_>>>();
+        let name = labels
+            .filter(|labels| !labels.is_empty())
+            .map(|labels| format!("{}.stark", labels.join(".")));
+        self.names.write().insert(address, name.clone());
+        Ok(name)
+    }
+
+    /// Address typed as hex or as a Starknet ID domain
+    pub async fn wallet(&self, input: &str) -> Result<ContractAddress, StarknetIdError> {
+        let input = input.trim();
+        if is_domain(input) {
+            return self.resolve(input).await;
+        }
+        input.parse().map_err(|_| StarknetIdError::InvalidAddress(input.to_string()))
+    }
+
+    async fn call(&self, entry_point_selector: Felt, calldata: Vec<Felt>) -> Result<Vec<Felt>, StarknetIdError> {
+        JsonRpcClient::new(HttpTransport::new(self.rpc_url.clone()))
+            .call(
+                FunctionCall {
+                    contract_address: self.naming_contract,
+                    entry_point_selector,
+                    calldata,
+                },
+                BlockId::Tag(BlockTag::Latest),
+            )
+            .await
+            .map_err(|e| StarknetIdError::Call(e.to_string()))
+    }
+}
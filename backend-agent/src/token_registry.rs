@@ -0,0 +1,206 @@
+use crate::address::ContractAddress;
+use crate::types::{Token, TokenCategory};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use starknet::{
+    core::{
+        codec::Decode,
+        types::{BlockId, BlockTag, ByteArray, Felt, FunctionCall},
+        utils::parse_cairo_short_string,
+    },
+    macros::selector,
+    providers::Provider,
+};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::info;
+
+/// Default registry file, relative to the working directory like `agents.json`
+pub const DEFAULT_TOKENS_FILE: &str = "tokens.json";
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenRegistryError {
+    #[error("Failed reading token registry: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid token registry: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Token {0} is listed twice")]
+    Duplicate(String),
+    #[error("Failed calling {0} on token {1}: {2}")]
+    Call(&'static str, String, String),
+    #[error("Token {0} returned an invalid {1}")]
+    InvalidMetadata(String, &'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenTag {
+    Stable,
+    Lst,
+    Meme,
+}
+
+/// An ERC-20 as listed in the registry file, or discovered on-chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenInfo {
+    pub address: ContractAddress,
+    pub symbol: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub decimals: u8,
+    #[serde(default)]
+    pub coingecko_id: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<TokenTag>,
+    /// Listed by us, discovered tokens never are
+    #[serde(default)]
+    pub verified: bool,
+}
+
+impl TokenInfo {
+    pub fn category(&self) -> TokenCategory {
+        if self.tags.contains(&TokenTag::Stable) {
+            TokenCategory::Stablecoin
+        } else if self.tags.contains(&TokenTag::Lst) {
+            TokenCategory::LiquidStaking
+        } else {
+            TokenCategory::Volatile
+        }
+    }
+
+    /// As a [`Token`], without a price
+    pub fn token(&self) -> Token {
+        Token {
+            name: self.symbol.clone(),
+            address: self.address,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokensFile {
+    tokens: Vec<TokenInfo>,
+}
+
+/// Tokens the app knows about, loaded from the registry file and grown by on-chain discovery.
+/// Clones share the tokens.
+#[derive(Debug, Clone, Default)]
+pub struct TokenRegistry(Arc<RwLock<Vec<TokenInfo>>>);
+
+impl TokenRegistry {
+    pub fn new(tokens: Vec<TokenInfo>) -> Result<Self, TokenRegistryError> {
+        let mut addresses = HashSet::new();
+        let mut symbols = HashSet::new();
+        for token in &tokens {
+            // a symbol is only ambiguous between verified tokens, anyone can deploy a fake USDC
+            if !addresses.insert(token.address) || token.verified && !symbols.insert(token.symbol.as_str()) {
+                return Err(TokenRegistryError::Duplicate(token.symbol.clone()));
+            }
+        }
+        Ok(Self(Arc::new(RwLock::new(tokens))))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TokenRegistryError> {
+        let file: TokensFile = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Self::new(file.tokens)
+    }
+
+    /// Registry from `TOKENS_FILE`, `tokens.json` by default
+    pub fn from_env() -> Result<Self, TokenRegistryError> {
+        Self::load(std::env::var("TOKENS_FILE").unwrap_or_else(|_| DEFAULT_TOKENS_FILE.to_string()))
+    }
+
+    pub fn tokens(&self) -> Vec<TokenInfo> {
+        self.0.read().clone()
+    }
+
+    pub fn verified(&self) -> Vec<TokenInfo> {
+        self.0.read().iter().filter(|token| token.verified).cloned().collect()
+    }
+
+    pub fn get(&self, address: ContractAddress) -> Option<TokenInfo> {
+        self.0.read().iter().find(|token| token.address == address).cloned()
+    }
+
+    /// Verified token with this exact symbol
+    pub fn by_symbol(&self, symbol: &str) -> Option<TokenInfo> {
+        self.0
+            .read()
+            .iter()
+            .find(|token| token.verified && token.symbol == symbol)
+            .cloned()
+    }
+
+    /// Category of a token, unknown ones are volatile
+    pub fn category(&self, address: ContractAddress) -> TokenCategory {
+        self.get(address).map_or(TokenCategory::Volatile, |token| token.category())
+    }
+
+    /// Registry entry of `address`, read from the contract's `decimals`, `symbol` and `name`
+    /// the first time and kept as an unverified token
+    pub async fn resolve<P: Provider + Sync>(&self, provider: &P, address: ContractAddress) -> Result<TokenInfo, TokenRegistryError> {
+        if let Some(token) = self.get(address) {
+            return Ok(token);
+        }
+        let decimals = call(provider, address, "decimals", selector!("decimals")).await?;
+        let decimals = decimals
+            .first()
+            .and_then(|felt| u8::try_from(*felt).ok())
+            .ok_or_else(|| TokenRegistryError::InvalidMetadata(address.to_string(), "decimals"))?;
+        let symbol = decode_string(&call(provider, address, "symbol", selector!("symbol")).await?)
+            .ok_or_else(|| TokenRegistryError::InvalidMetadata(address.to_string(), "symbol"))?;
+        // name is informative only
+        let name = match call(provider, address, "name", selector!("name")).await {
+            Ok(name) => decode_string(&name),
+            Err(_) => None,
+        };
+        let token = TokenInfo {
+            address,
+            symbol,
+            name,
+            decimals,
+            coingecko_id: None,
+            tags: vec![],
+            verified: false,
+        };
+        info!("Discovered token {} at {address}", token.symbol);
+
+        let mut tokens = self.0.write();
+        // another fetch may have resolved it meanwhile
+        if let Some(known) = tokens.iter().find(|known| known.address == address) {
+            return Ok(known.clone());
+        }
+        tokens.push(token.clone());
+        Ok(token)
+    }
+}
+
+async fn call<P: Provider + Sync>(
+    provider: &P,
+    address: ContractAddress,
+    name: &'static str,
+    entry_point_selector: Felt,
+) -> Result<Vec<Felt>, TokenRegistryError> {
+    provider
+        .call(
+            FunctionCall {
+                contract_address: address.felt(),
+                entry_point_selector,
+                calldata: vec![],
+            },
+            BlockId::Tag(BlockTag::Latest),
+        )
+        .await
+        .map_err(|e| TokenRegistryError::Call(name, address.to_string(), e.to_string()))
+}
+
+/// Cairo 0 tokens return a short string felt, Cairo 1 ones a `ByteArray`
+fn decode_string(felts: &[Felt]) -> Option<String> {
+    let decoded = match felts {
+        [felt] => parse_cairo_short_string(felt).ok()?,
+        felts => String::try_from(ByteArray::decode(felts).ok()?).ok()?,
+    };
+    (!decoded.is_empty()).then_some(decoded)
+}
@@ -1,150 +1,83 @@
-use crate::types::{PoolType, Price, StringContractAddress};
-use crate::{market::CoinMarketData, types::Token};
+use crate::amount::TokenAmount;
+use crate::token_registry::TokenRegistry;
+use crate::types::{PoolType, Price};
+use crate::market::{CoinMarketData, MarketDataError};
+use crate::prices::{PriceError, PriceFeed};
+use crate::types::Token;
 use starknet::{
     core::types::{BlockId, BlockTag, Felt, FunctionCall},
-    macros::{felt, selector},
+    macros::selector,
     providers::{
         jsonrpc::{HttpTransport, JsonRpcClient},
         Provider, Url,
     },
 };
-use std::collections::HashMap;
+use tracing::error;
 
-// some verified tokens addresses on Starknet
-const BROTHER: &str = "0x3b405a98c9e795d427fe82cdeeeed803f221b52471e3a757574a2b4180793ee";
-const STRK: &str = "0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d";
-const ETH: &str = "0x49d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7";
-// const USDC: &str = "0x53c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8";
-
-// chain id on coingecko
-const CHAIN_ID: &str = "starknet";
+// tokens with yields data, looked up in the registry
+const YIELD_TOKENS: [&str; 3] = ["BROTHER", "STRK", "ETH"];
 
 // vec1[x] related to vec2[x] and so on (vec2[x] countains Market Data about vec1[x])
-pub async fn fetch_all_tokens() -> (Vec<Token>, Vec<CoinMarketData>) {
-    let api_key = std::env::var("COINGECKO_API_KEY").expect("COINGECKO_API_KEY must be set in environment");
+// markets come from CoinGecko through `prices`, reserves are read on the node at `rpc_url`
+pub async fn fetch_all_tokens(
+    registry: &TokenRegistry,
+    prices: &PriceFeed,
+    rpc_url: &Url,
+) -> Result<(Vec<Token>, Vec<CoinMarketData>), PriceError> {
     let mut tokens = Vec::new();
     let mut market_data = Vec::new();
-    let client = reqwest::Client::new();
-
-    let addresses = vec![BROTHER, STRK, ETH];
-    let addresses_str = addresses.join(",");
-
-    let url = format!(
-        "https://api.coingecko.com/api/v3/simple/token_price/{CHAIN_ID}?contract_addresses={addresses_str}&vs_currencies=usd&include_market_cap=true&include_24hr_vol=true&include_24hr_change=true"
-    );
-
-    if let Ok(resp) = client
-        .get(&url)
-        .header("accept", "application/json")
-        .header("x-cg-demo-api-key", api_key)
-        .send()
-        .await
-    {
-        if let Ok(prices) = resp.json::<HashMap<String, HashMap<String, f64>>>().await {
-            // Process each token
-            for (address, data) in prices {
-                let price = data.get("usd").copied().unwrap_or_default();
-                let volume_24h = data.get("usd_24h_vol").copied().unwrap_or_default();
-                let price_change_24h = data.get("usd_24h_change").copied().unwrap_or_default();
-
-                let token_name = match address.as_str() {
-                    addr if addr == BROTHER => "BROTHER",
-                    addr if addr == STRK => "STRK",
-                    addr if addr == ETH => "ETH",
-                    _ => continue,
-                };
-
-                match token_name {
-                    "STRK" | "ETH" => {
-                        // Create token entries for both pool types
-                        tokens.push(Token {
-                            name: token_name.to_string(),
-                            address: StringContractAddress::from(address.as_str()),
-                            price: Price::from_f64(price, 18),
-                        });
-                        tokens.push(Token {
-                            name: token_name.to_string(),
-                            address: StringContractAddress::from(address.as_str()),
-                            price: Price::from_f64(price, 18),
-                        });
-
-                        // Create market data for Standard pool
-                        let standard_market = CoinMarketData::from_gecko_data(
-                            token_name,
-                            price,
-                            volume_24h,
-                            price_change_24h,
-                            PoolType::Stable,
-                        )
-                        .await;
-                        market_data.push(standard_market);
-
-                        // Create market data for Degen pool
-                        let degen_market = CoinMarketData::from_gecko_data(
-                            token_name,
-                            price,
-                            volume_24h,
-                            price_change_24h,
-                            PoolType::Degen,
-                        )
-                        .await;
-                        market_data.push(degen_market);
-                    }
-                    "BROTHER" => {
-                        // BROTHER only has degen pool
-                        tokens.push(Token {
-                            name: token_name.to_string(),
-                            address: StringContractAddress::from(address.as_str()),
-                            price: Price::from_f64(price, 18),
-                        });
-
-                        let market_data_entry = CoinMarketData::from_gecko_data(
-                            token_name,
-                            price,
-                            volume_24h,
-                            price_change_24h,
-                            PoolType::Degen,
-                        )
-                        .await;
-                        market_data.push(market_data_entry);
-                    }
-                    _ => continue,
+    let provider = JsonRpcClient::new(HttpTransport::new(rpc_url.clone()));
+
+    let yield_tokens: Vec<_> = YIELD_TOKENS.iter().filter_map(|symbol| registry.by_symbol(symbol)).collect();
+    let addresses: Vec<_> = yield_tokens.iter().map(|token| token.address).collect();
+    let markets = prices.markets(&addresses).await?;
+
+    for token in &yield_tokens {
+        let Some(market) = markets.get(&token.address) else {
+            continue;
+        };
+        let token_name = token.symbol.as_str();
+
+        let pool_types = match token_name {
+            "STRK" | "ETH" => vec![PoolType::Stable, PoolType::Degen],
+            // BROTHER only has degen pool
+            "BROTHER" => vec![PoolType::Degen],
+            _ => continue,
+        };
+        for pool_type in pool_types {
+            // a token without market data is left out, the others are still served
+            match CoinMarketData::from_gecko_data(
+                &provider,
+                registry,
+                token_name,
+                market.price,
+                market.volume_24h,
+                market.price_change_24h,
+                pool_type.clone(),
+            )
+            .await
+            {
+                Ok(data) => {
+                    tokens.push(Token {
+                        name: token_name.to_string(),
+                        address: token.address,
+                        price: Price::from_f64(market.price, 18),
+                    });
+                    market_data.push(data);
                 }
+                Err(e) => error!("Skipping {} {:?} pool: {}", token_name, pool_type, e),
             }
         }
     }
-    (tokens, market_data)
+    Ok((tokens, market_data))
 }
 
-pub async fn fetch_token_reserve(contract_address: Felt) -> Felt {
-    let provider = JsonRpcClient::new(HttpTransport::new(
-        Url::parse("https://starknet-mainnet.public.blastapi.io/rpc/v0_7").unwrap(),
-    ));
-
-    let call_result = provider
-        .call(
-            FunctionCall {
-                contract_address,
-                entry_point_selector: selector!("total_supply"),
-                calldata: Vec::with_capacity(0),
-            },
-            BlockId::Tag(BlockTag::Latest),
-        )
-        .await
-        .expect("failed to call contract");
-
-    let res = call_result;
-    // first item is the total supply
-    res[0]
-}
-pub async fn fetch_usdc_reserve() -> Felt {
-    let provider = JsonRpcClient::new(HttpTransport::new(
-        Url::parse("https://starknet-mainnet.public.blastapi.io/rpc/v0_7").unwrap(),
-    ));
-
-    let contract_address =
-        felt!("0x53c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8");
-
+/// `total_supply` of an ERC-20, a u256
+pub async fn fetch_token_reserve<P: Provider + Sync>(
+    provider: &P,
+    contract_address: Felt,
+    decimals: u8,
+) -> Result<TokenAmount, MarketDataError> {
     let call_result = provider
         .call(
             FunctionCall {
@@ -155,9 +88,8 @@ pub async fn fetch_usdc_reserve() -> Felt {
             BlockId::Tag(BlockTag::Latest),
         )
         .await
-        .expect("failed to call contract");
+        .map_err(|e| MarketDataError::Reserve(format!("{contract_address:#x}"), e.to_string()))?;
 
-    let res = call_result;
-    // first item is the total supply
-    res[0]
+    TokenAmount::from_u256_felts(&call_result, decimals)
+        .map_err(|e| MarketDataError::Reserve(format!("{contract_address:#x}"), e.to_string()))
 }
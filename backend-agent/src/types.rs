@@ -5,36 +5,44 @@ use rig::{
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, convert::From, fmt::Debug};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::From,
+    fmt::Debug,
+};
+
+use crate::address::ContractAddress;
+use crate::amount::TokenAmount;
+use crate::positions::Position;
+use crate::token_registry::TokenRegistry;
 
 #[derive(Debug, thiserror::Error)]
 #[error("Portfolio error: {0}")]
 pub struct PortfolioError(pub String);
 
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+/// A portfolio holding valued in USD
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, PartialEq)]
 pub struct Asset {
-    pub token: StringContractAddress,
+    pub symbol: String,
+    pub token: ContractAddress,
+    pub category: TokenCategory,
+    pub amount: TokenAmount,
+    #[serde(rename = "priceUSD")]
+    pub price_usd: Option<f64>,
     #[serde(rename = "balanceUSD")]
-    pub balance: f64,
+    pub balance: Option<f64>,
+    /// Share of the portfolio value in percent, `None` when the token has no price
+    pub allocation: Option<f64>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Default, JsonSchema, Clone, PartialEq, Eq, Hash)]
 pub struct Token {
     pub name: String,
-    pub address: StringContractAddress,
+    pub address: ContractAddress,
     #[serde(rename = "priceUSD")]
     pub price: Price,
 }
 
-#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq, Default, Eq, Hash)]
-pub struct StringContractAddress(pub String);
-
-impl StringContractAddress {
-    pub fn from(str: &str) -> StringContractAddress {
-        Self(str.to_string())
-    }
-}
-
 impl From<HashMap<String, Token>> for Token {
     fn from(map: HashMap<String, Token>) -> Token {
         // Assuming you want to combine all tokens or take the first one
@@ -62,6 +70,7 @@ pub enum ComputeError {
 #[derive(Deserialize, Serialize, JsonSchema, Default, Debug, Clone)]
 pub struct ProtocolYield {
     pub token: Token,
+    /// In percent, e.g. `12.5` for 12.5%
     pub apy: f64,
     pub tvl: f64,
     pub volume_24h: f64,
@@ -69,11 +78,262 @@ pub struct ProtocolYield {
     pub pool_type: PoolType,
 }
 
-#[derive(Deserialize, Serialize, JsonSchema)]
+impl ProtocolYield {
+    /// Yearly rate before compounding, assuming rewards are compounded daily
+    pub fn apr(&self) -> f64 {
+        ((1.0 + self.apy / 100.0).powf(1.0 / 365.0) - 1.0) * 365.0 * 100.0
+    }
+}
+
+/// How a token behaves, for the allocation breakdown
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenCategory {
+    Stablecoin,
+    Volatile,
+    LiquidStaking,
+}
+
+/// USD value held in a category and its share of the portfolio in percent
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Default)]
+pub struct CategoryAllocation {
+    #[serde(rename = "valueUSD")]
+    pub value: f64,
+    pub allocation: f64,
+}
+
+/// A protocol position valued in USD, debts count negative
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct PositionHolding {
+    #[serde(flatten)]
+    pub position: Position,
+    /// `None` when a token of the position has no price
+    #[serde(rename = "valueUSD")]
+    pub value: Option<f64>,
+    pub allocation: Option<f64>,
+}
+
+/// Balances and positions of a wallet valued in USD, largest holding first
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq)]
 pub struct Portfolio {
-    wallet_address: StringContractAddress,
-    assets: Vec<Asset>,
-    total_value: f64,
+    pub wallet_address: ContractAddress,
+    /// Starknet ID domain of the wallet, for display
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    pub assets: Vec<Asset>,
+    #[serde(default)]
+    pub positions: Vec<PositionHolding>,
+    /// USD value of the priced assets and positions, net of debts
+    pub total_value: f64,
+    pub categories: BTreeMap<TokenCategory, CategoryAllocation>,
+    /// Held tokens and positions without a price, left out of the total
+    pub unpriced: Vec<String>,
+}
+
+impl Portfolio {
+    /// `alice.stark (0x…)` when the wallet has a domain, else its address
+    pub fn wallet_label(&self) -> String {
+        display_wallet(self.wallet_address, self.domain.as_deref())
+    }
+
+    /// Joins `balances` with `prices`, keyed by token address, categories come from `registry`
+    pub fn from_balances(
+        wallet_address: ContractAddress,
+        balances: &HashMap<Token, TokenAmount>,
+        prices: &HashMap<ContractAddress, f64>,
+        registry: &TokenRegistry,
+    ) -> Self {
+        let assets = balances
+            .iter()
+            .map(|(token, amount)| {
+                let price_usd = prices.get(&token.address).copied();
+                Asset {
+                    symbol: token.name.clone(),
+                    token: token.address,
+                    category: registry.category(token.address),
+                    amount: *amount,
+                    price_usd,
+                    balance: price_usd.map(|price| price * amount.to_f64()),
+                    allocation: None,
+                }
+            })
+            .collect();
+        let mut portfolio = Self {
+            wallet_address,
+            domain: None,
+            assets,
+            positions: vec![],
+            total_value: 0.0,
+            categories: BTreeMap::new(),
+            unpriced: vec![],
+        };
+        portfolio.allocate(registry);
+        portfolio
+    }
+
+    /// Adds protocol positions, replacing the plain balances of their receipt or LP tokens
+    pub fn with_positions(mut self, positions: Vec<Position>, prices: &HashMap<ContractAddress, f64>, registry: &TokenRegistry) -> Self {
+        let contracts: Vec<_> = positions.iter().map(|position| position.contract).collect();
+        self.assets.retain(|asset| !contracts.contains(&asset.token));
+
+        for mut position in positions {
+            for part in position.underlying.iter_mut().chain(position.rewards.iter_mut()) {
+                part.value = prices
+                    .get(&part.token)
+                    .map(|price| price * part.amount.to_f64());
+            }
+            let value = position
+                .signed_parts()
+                .map(|(sign, part)| part.value.map(|value| sign * value))
+                .sum::<Option<f64>>();
+            self.positions.push(PositionHolding {
+                position,
+                value,
+                allocation: None,
+            });
+        }
+        self.allocate(registry);
+        self
+    }
+
+    /// Holdings of every portfolio summed under the address of the first one, `None` without any
+    pub fn combine(portfolios: &[Portfolio]) -> Option<Self> {
+        let first = portfolios.first()?;
+        let mut combined = Self {
+            wallet_address: first.wallet_address,
+            domain: first.domain.clone(),
+            assets: vec![],
+            positions: vec![],
+            total_value: 0.0,
+            categories: BTreeMap::new(),
+            unpriced: vec![],
+        };
+        for portfolio in portfolios {
+            for asset in &portfolio.assets {
+                match combined.assets.iter_mut().find(|held| held.token == asset.token) {
+                    Some(held) => {
+                        held.amount = held.amount.checked_add(asset.amount).unwrap_or(held.amount);
+                        held.balance = held.balance.zip(asset.balance).map(|(held, value)| held + value);
+                    }
+                    None => combined.assets.push(asset.clone()),
+                }
+            }
+            combined.positions.extend(portfolio.positions.iter().cloned());
+            for (category, split) in &portfolio.categories {
+                combined.categories.entry(*category).or_default().value += split.value;
+            }
+        }
+        combined.share();
+        Some(combined)
+    }
+
+    /// Category split from the valued holdings, then totals, allocations and ordering
+    fn allocate(&mut self, registry: &TokenRegistry) {
+        let mut categories = BTreeMap::<TokenCategory, CategoryAllocation>::new();
+        for asset in &self.assets {
+            if let Some(value) = asset.balance {
+                categories.entry(asset.category).or_default().value += value;
+            }
+        }
+        for holding in &self.positions {
+            if holding.value.is_none() {
+                continue;
+            }
+            for (sign, part) in holding.position.signed_parts() {
+                let category = registry.category(part.token);
+                categories.entry(category).or_default().value += sign * part.value.unwrap_or_default();
+            }
+        }
+        self.categories = categories;
+        self.share();
+    }
+
+    /// Totals, allocations and ordering from the valued holdings and category values
+    fn share(&mut self) {
+        self.total_value = self.assets.iter().filter_map(|asset| asset.balance).sum::<f64>()
+            + self.positions.iter().filter_map(|holding| holding.value).sum::<f64>();
+        let total_value = self.total_value;
+        let share = |value: f64| if total_value > 0.0 { value / total_value * 100.0 } else { 0.0 };
+
+        for asset in &mut self.assets {
+            asset.allocation = asset.balance.map(share);
+        }
+        for holding in &mut self.positions {
+            holding.allocation = holding.value.map(share);
+        }
+        for category in self.categories.values_mut() {
+            category.allocation = share(category.value);
+        }
+
+        self.assets.sort_by(|a, b| {
+            b.balance
+                .unwrap_or(-1.0)
+                .total_cmp(&a.balance.unwrap_or(-1.0))
+                .then_with(|| a.symbol.cmp(&b.symbol))
+        });
+        self.positions
+            .sort_by(|a, b| b.value.unwrap_or(f64::MIN).total_cmp(&a.value.unwrap_or(f64::MIN)));
+        self.unpriced = self
+            .assets
+            .iter()
+            .filter(|asset| asset.price_usd.is_none())
+            .map(|asset| asset.symbol.clone())
+            .chain(
+                self.positions
+                    .iter()
+                    .filter(|holding| holding.value.is_none())
+                    .map(|holding| holding.position.label()),
+            )
+            .collect();
+    }
+}
+
+/// `alice.stark (0x…)` when `domain` is known, else `address`
+pub fn display_wallet(address: ContractAddress, domain: Option<&str>) -> String {
+    match domain {
+        Some(domain) => format!("{domain} ({address})"),
+        None => address.to_string(),
+    }
+}
+
+/// Value of one wallet of an aggregated portfolio and its share of the combined value in percent
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct WalletTotal {
+    pub wallet_address: ContractAddress,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    #[serde(rename = "valueUSD")]
+    pub value: f64,
+    pub allocation: f64,
+}
+
+/// Portfolios of several wallets of the same user, with per-wallet and combined totals
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct AggregatedPortfolio {
+    pub wallets: Vec<WalletTotal>,
+    /// Holdings of every wallet summed, under the address of the first one
+    pub combined: Portfolio,
+}
+
+impl AggregatedPortfolio {
+    /// `None` without any portfolio
+    pub fn new(portfolios: &[Portfolio]) -> Option<Self> {
+        let combined = Portfolio::combine(portfolios)?;
+        let wallets = portfolios
+            .iter()
+            .map(|portfolio| WalletTotal {
+                wallet_address: portfolio.wallet_address,
+                domain: portfolio.domain.clone(),
+                value: portfolio.total_value,
+                allocation: if combined.total_value > 0.0 {
+                    portfolio.total_value / combined.total_value * 100.0
+                } else {
+                    0.0
+                },
+            })
+            .collect();
+        Some(Self { wallets, combined })
+    }
 }
 
 #[derive(Deserialize, Serialize, JsonSchema)]
@@ -82,7 +342,7 @@ pub struct YieldAnalyzer {
     pub yields_data: Vec<ProtocolYield>,
 }
 
-#[derive(Deserialize, Serialize, JsonSchema, Default, Debug, Clone)]
+#[derive(Deserialize, Serialize, JsonSchema, Default, Debug, Clone, PartialEq, Eq)]
 pub enum PoolType {
     #[default]
     Stable,
@@ -90,6 +350,48 @@ pub enum PoolType {
     Degen,
 }
 
+/// How much risk the user is willing to take, steers strategy suggestions
+#[derive(Deserialize, Serialize, JsonSchema, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskProfile {
+    Conservative,
+    #[default]
+    Balanced,
+    Aggressive,
+}
+
+impl std::fmt::Display for RiskProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RiskProfile::Conservative => write!(f, "conservative"),
+            RiskProfile::Balanced => write!(f, "balanced"),
+            RiskProfile::Aggressive => write!(f, "aggressive"),
+        }
+    }
+}
+
+/// How familiar the user is with DeFi, steers explanations, verbosity and details
+#[derive(Deserialize, Serialize, JsonSchema, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Expertise {
+    /// Explanations and warnings
+    Beginner,
+    #[default]
+    Standard,
+    /// Formulas, APR/APY breakdowns and contract addresses
+    Pro,
+}
+
+impl std::fmt::Display for Expertise {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expertise::Beginner => write!(f, "beginner"),
+            Expertise::Standard => write!(f, "standard"),
+            Expertise::Pro => write!(f, "pro"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Eq, PartialEq)]
 pub struct TwitterInsight {
     pub tweet_text: String,
@@ -135,16 +437,20 @@ where
     serializer.serialize_str(&formatted)
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// A section of a protocol doc from `knowledge/`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct DefiKnowledge {
-    id: String,
-    content: String,
+    /// `<file stem>#<section slug>`
+    pub id: String,
+    pub protocol: String,
+    pub section: String,
+    pub content: String,
 }
 
 impl Embed for DefiKnowledge {
     fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
-        // embed the content field
-        embedder.embed(self.content.clone());
+        // the heading says what the section is about, embed it along the content
+        embedder.embed(format!("{} - {}\n{}", self.protocol, self.section, self.content));
         Ok(())
     }
 }
@@ -0,0 +1,322 @@
+use crate::address::ContractAddress;
+use crate::agents::turn;
+use crate::llm::LlmResponse;
+use chrono::{NaiveDate, Utc};
+use parking_lot::RwLock;
+use rig::completion::{CompletionRequest, ModelChoice};
+use rig::embeddings::{Embedding, EmbeddingError, EmbeddingModel};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Prompt and completion token counts of one call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Rough count when the provider doesn't report usage, ~4 characters per token
+    pub fn estimate(request: &CompletionRequest, choice: &ModelChoice) -> Self {
+        let prompt_chars = request.prompt.len()
+            + request.preamble.as_ref().map_or(0, String::len)
+            + request.chat_history.iter().map(|m| m.content.len()).sum::<usize>()
+            + request.documents.iter().map(|d| d.text.len()).sum::<usize>();
+        let completion_chars = match choice {
+            ModelChoice::Message(text) => text.len(),
+            ModelChoice::ToolCall(name, args) => name.len() + args.to_string().len(),
+        };
+        Self {
+            prompt_tokens: estimate_tokens(prompt_chars),
+            completion_tokens: estimate_tokens(completion_chars),
+        }
+    }
+}
+
+pub fn estimate_tokens(chars: usize) -> u64 {
+    (chars as u64).div_ceil(4)
+}
+
+/// Raw provider responses able to tell how many tokens they consumed
+pub trait ReportsUsage {
+    fn token_usage(&self) -> Option<TokenUsage>;
+}
+
+impl ReportsUsage for LlmResponse {
+    fn token_usage(&self) -> Option<TokenUsage> {
+        match self {
+            LlmResponse::OpenAi(response) => response.usage.as_ref().map(|usage| TokenUsage {
+                prompt_tokens: usage.prompt_tokens as u64,
+                completion_tokens: usage.total_tokens.saturating_sub(usage.prompt_tokens) as u64,
+            }),
+            LlmResponse::Anthropic(response) => Some(TokenUsage {
+                prompt_tokens: response.usage.input_tokens,
+                completion_tokens: response.usage.output_tokens,
+            }),
+        }
+    }
+}
+
+/// Scripted and other local models report nothing, usage gets estimated
+impl ReportsUsage for () {
+    fn token_usage(&self) -> Option<TokenUsage> {
+        None
+    }
+}
+
+/// USD per million tokens
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    #[serde(default)]
+    pub completion: f64,
+}
+
+/// Prices keyed by model id (`provider/model`), unknown models cost nothing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceTable(pub HashMap<String, ModelPrice>);
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        Self(HashMap::from([
+            ("openai/gpt-4o-mini".to_string(), ModelPrice { prompt: 0.15, completion: 0.6 }),
+            ("openai/gpt-4o".to_string(), ModelPrice { prompt: 2.5, completion: 10.0 }),
+            ("openai/text-embedding-3-small".to_string(), ModelPrice { prompt: 0.02, completion: 0.0 }),
+            ("openai/text-embedding-3-large".to_string(), ModelPrice { prompt: 0.13, completion: 0.0 }),
+            ("anthropic/claude-3-5-haiku-latest".to_string(), ModelPrice { prompt: 0.8, completion: 4.0 }),
+            ("anthropic/claude-3-5-sonnet-latest".to_string(), ModelPrice { prompt: 3.0, completion: 15.0 }),
+        ]))
+    }
+}
+
+impl PriceTable {
+    /// Defaults, overridden by the JSON file at `LLM_PRICE_TABLE` if set:
+    /// `{"openai/gpt-4o-mini": {"prompt": 0.15, "completion": 0.6}}`
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        let mut table = Self::default();
+        if let Ok(path) = std::env::var("LLM_PRICE_TABLE") {
+            let overrides: HashMap<String, ModelPrice> =
+                serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+            info!("Loaded {} model prices from {}", overrides.len(), path);
+            table.0.extend(overrides);
+        }
+        Ok(table)
+    }
+
+    pub fn cost(&self, model: &str, usage: TokenUsage) -> f64 {
+        self.0.get(model).map_or(0.0, |price| {
+            (usage.prompt_tokens as f64 * price.prompt + usage.completion_tokens as f64 * price.completion)
+                / 1_000_000.0
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, usage: TokenUsage, cost_usd: f64) {
+        self.requests += 1;
+        self.prompt_tokens += usage.prompt_tokens;
+        self.completion_tokens += usage.completion_tokens;
+        self.cost_usd += cost_usd;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallKind {
+    Completion,
+    Embedding,
+}
+
+/// Spending limits, `None` means unlimited
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Quota {
+    pub session_usd: Option<f64>,
+    pub wallet_daily_usd: Option<f64>,
+}
+
+impl Quota {
+    /// `QUOTA_SESSION_USD` and `QUOTA_WALLET_DAILY_USD`
+    pub fn from_env() -> Self {
+        let parse = |name: &str| std::env::var(name).ok().and_then(|value| value.parse().ok());
+        Self {
+            session_usd: parse("QUOTA_SESSION_USD"),
+            wallet_daily_usd: parse("QUOTA_WALLET_DAILY_USD"),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum QuotaExceeded {
+    #[error("Session spent ${spent:.4} of its ${limit:.4} budget")]
+    Session { spent: f64, limit: f64 },
+    #[error("Wallet spent ${spent:.4} of its ${limit:.4} daily budget")]
+    WalletDaily { spent: f64, limit: f64 },
+}
+
+/// Aggregated usage, as served by the admin endpoint
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub total: UsageTotals,
+    pub by_session: HashMap<String, UsageTotals>,
+    pub by_wallet: HashMap<ContractAddress, UsageTotals>,
+    pub by_agent: HashMap<String, UsageTotals>,
+    pub by_model: HashMap<String, UsageTotals>,
+    pub by_day: HashMap<NaiveDate, UsageTotals>,
+    /// Wallet spending per day, used by the daily quota
+    pub by_wallet_day: HashMap<ContractAddress, HashMap<NaiveDate, UsageTotals>>,
+}
+
+
+/// Token and cost accounting shared by every model wrapper. Clones share the same ledger.
+#[derive(Clone, Default)]
+pub struct UsageLedger {
+    prices: Arc<PriceTable>,
+    quota: Quota,
+    state: Arc<RwLock<UsageReport>>,
+}
+
+impl UsageLedger {
+    pub fn new(prices: PriceTable, quota: Quota) -> Self {
+        Self {
+            prices: Arc::new(prices),
+            quota,
+            state: Arc::default(),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        Ok(Self::new(PriceTable::from_env()?, Quota::from_env()))
+    }
+
+    /// Records a call, attributed to the session of the current turn if any. Wallet spend only goes to a
+    /// wallet linked to the session by signature, the turn's wallet when linked or else the first linked,
+    /// since any address can be typed into a prompt.
+    pub fn record(&self, agent: &str, model: &str, kind: CallKind, usage: TokenUsage) {
+        let cost_usd = self.prices.cost(model, usage);
+        let (session_id, wallet) = turn::current(|metadata| {
+            let wallet = metadata
+                .wallet
+                .filter(|wallet| metadata.linked_wallets.contains(wallet))
+                .or_else(|| metadata.linked_wallets.first().copied());
+            (metadata.session_id.clone(), wallet)
+        })
+        .unwrap_or_default();
+        turn::record(|metadata| metadata.usage.add(usage, cost_usd));
+
+        let today = Utc::now().date_naive();
+        let mut report = self.state.write();
+        report.total.add(usage, cost_usd);
+        report.by_agent.entry(agent.to_string()).or_default().add(usage, cost_usd);
+        report.by_model.entry(model.to_string()).or_default().add(usage, cost_usd);
+        report.by_day.entry(today).or_default().add(usage, cost_usd);
+        if !session_id.is_empty() {
+            report.by_session.entry(session_id).or_default().add(usage, cost_usd);
+        }
+        if let Some(wallet) = wallet {
+            report.by_wallet.entry(wallet).or_default().add(usage, cost_usd);
+            report
+                .by_wallet_day
+                .entry(wallet)
+                .or_default()
+                .entry(today)
+                .or_default()
+                .add(usage, cost_usd);
+        }
+        info!(
+            "{} {:?} on {}: {} prompt + {} completion tokens, ${:.6}",
+            agent, kind, model, usage.prompt_tokens, usage.completion_tokens, cost_usd
+        );
+    }
+
+    pub fn report(&self) -> UsageReport {
+        self.state.read().clone()
+    }
+
+    /// Fails when the session or one of the wallets linked to it already spent its budget
+    pub fn check_quota(&self, session_id: &str, linked_wallets: &[ContractAddress]) -> Result<(), QuotaExceeded> {
+        let report = self.state.read();
+        if let Some(limit) = self.quota.session_usd {
+            let spent = report.by_session.get(session_id).map_or(0.0, |t| t.cost_usd);
+            if spent >= limit {
+                warn!("Session {} over quota", session_id);
+                return Err(QuotaExceeded::Session { spent, limit });
+            }
+        }
+        if let Some(limit) = self.quota.wallet_daily_usd {
+            let today = Utc::now().date_naive();
+            for wallet in linked_wallets {
+                let spent = report
+                    .by_wallet_day
+                    .get(wallet)
+                    .and_then(|days| days.get(&today))
+                    .map_or(0.0, |t| t.cost_usd);
+                if spent >= limit {
+                    warn!("Session {} wallet {} over daily quota", session_id, wallet);
+                    return Err(QuotaExceeded::WalletDaily { spent, limit });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Embedding model recording an estimate of its tokens, providers don't report embedding usage through rig
+#[derive(Clone)]
+pub struct MeteredEmbeddingModel<E: EmbeddingModel> {
+    agent: String,
+    model_id: String,
+    model: E,
+    ledger: UsageLedger,
+}
+
+impl<E: EmbeddingModel> MeteredEmbeddingModel<E> {
+    /// `agent` labels the calls, e.g. `insights` for the index build
+    pub fn new(agent: &str, model_id: &str, model: E, ledger: UsageLedger) -> Self {
+        Self {
+            agent: agent.to_string(),
+            model_id: model_id.to_string(),
+            model,
+            ledger,
+        }
+    }
+
+    /// Same model and ledger under another label
+    pub fn labeled(&self, agent: &str) -> Self {
+        Self {
+            agent: agent.to_string(),
+            ..self.clone()
+        }
+    }
+}
+
+impl<E: EmbeddingModel> EmbeddingModel for MeteredEmbeddingModel<E> {
+    const MAX_DOCUMENTS: usize = E::MAX_DOCUMENTS;
+
+    fn ndims(&self) -> usize {
+        self.model.ndims()
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        let texts: Vec<String> = texts.into_iter().collect();
+        let usage = TokenUsage {
+            prompt_tokens: estimate_tokens(texts.iter().map(String::len).sum()),
+            completion_tokens: 0,
+        };
+        let embeddings = self.model.embed_texts(texts).await?;
+        self.ledger.record(&self.agent, &self.model_id, CallKind::Embedding, usage);
+        Ok(embeddings)
+    }
+}
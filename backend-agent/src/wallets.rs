@@ -0,0 +1,179 @@
+use crate::address::ContractAddress;
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::RwLock;
+use serde_json::{json, Value};
+use starknet::{
+    core::types::{typed_data::TypedDataError, BlockId, BlockTag, Felt, FunctionCall, TypedData},
+    macros::{selector, short_string},
+    providers::{
+        jsonrpc::{HttpTransport, JsonRpcClient},
+        Provider, Url,
+    },
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// How long a link challenge can be signed for
+pub const CHALLENGE_TTL_MINUTES: i64 = 10;
+
+/// What SNIP-6 accounts return for a valid signature, older accounts return 1
+const VALID: Felt = short_string!("VALID");
+
+#[derive(Debug, thiserror::Error)]
+pub enum WalletLinkError {
+    #[error("No pending challenge for wallet {0}, request one first")]
+    NoChallenge(String),
+    #[error("Challenge for wallet {0} expired")]
+    Expired(String),
+    #[error("Invalid link message: {0}")]
+    TypedData(#[from] TypedDataError),
+    #[error("Invalid link message: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed verifying the signature of {0}: {1}")]
+    Call(String, String),
+    #[error("Signature doesn't prove ownership of wallet {0}")]
+    InvalidSignature(String),
+}
+
+/// Message a wallet signs to be linked, and until when
+struct Challenge {
+    typed_data: Value,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct Links {
+    /// Pending challenges, by session and wallet
+    challenges: HashMap<(String, ContractAddress), Challenge>,
+    /// Wallets each session proved it owns, in the order they were linked
+    sessions: HashMap<String, Vec<ContractAddress>>,
+}
+
+/// Wallets linked to each session, each proven by a signature checked by the account contract.
+/// Clones share the links.
+#[derive(Clone)]
+pub struct WalletLinks {
+    rpc_url: Url,
+    links: Arc<RwLock<Links>>,
+}
+
+impl WalletLinks {
+    /// Signatures are checked against the accounts on the node at `rpc_url`
+    pub fn new(rpc_url: Url) -> Self {
+        Self {
+            rpc_url,
+            links: Arc::default(),
+        }
+    }
+
+    /// SNIP-12 message `wallet` has to sign to be linked to `session_id`, replacing any earlier one
+    pub fn challenge(&self, session_id: &str, wallet: ContractAddress) -> Value {
+        let expires_at = Utc::now() + Duration::minutes(CHALLENGE_TTL_MINUTES);
+        let typed_data = link_message(session_id, Felt::from(uuid::Uuid::new_v4().as_u128()), expires_at);
+        self.links.write().challenges.insert(
+            (session_id.to_string(), wallet),
+            Challenge {
+                typed_data: typed_data.clone(),
+                expires_at,
+            },
+        );
+        typed_data
+    }
+
+    /// Links `wallet` to `session_id` when `signature` of its pending challenge is valid for the
+    /// account, returns the session wallets. The challenge can only be used once.
+    pub async fn link(
+        &self,
+        session_id: &str,
+        wallet: ContractAddress,
+        signature: Vec<Felt>,
+    ) -> Result<Vec<ContractAddress>, WalletLinkError> {
+        let challenge = self
+            .links
+            .write()
+            .challenges
+            .remove(&(session_id.to_string(), wallet))
+            .ok_or_else(|| WalletLinkError::NoChallenge(wallet.to_string()))?;
+        if challenge.expires_at < Utc::now() {
+            return Err(WalletLinkError::Expired(wallet.to_string()));
+        }
+        let hash = serde_json::from_value::<TypedData>(challenge.typed_data)?.message_hash(wallet.felt())?;
+
+        let provider = JsonRpcClient::new(HttpTransport::new(self.rpc_url.clone()));
+        if !is_valid_signature(&provider, wallet, hash, signature).await? {
+            return Err(WalletLinkError::InvalidSignature(wallet.to_string()));
+        }
+
+        let mut links = self.links.write();
+        let wallets = links.sessions.entry(session_id.to_string()).or_default();
+        if !wallets.contains(&wallet) {
+            wallets.push(wallet);
+        }
+        Ok(wallets.clone())
+    }
+
+    /// Removes `wallet` from the session, false when it wasn't linked
+    pub fn unlink(&self, session_id: &str, wallet: ContractAddress) -> bool {
+        let mut links = self.links.write();
+        let Some(wallets) = links.sessions.get_mut(session_id) else {
+            return false;
+        };
+        let linked = wallets.len();
+        wallets.retain(|linked| *linked != wallet);
+        linked != wallets.len()
+    }
+
+    /// Wallets linked to `session_id`, first linked first
+    pub fn wallets(&self, session_id: &str) -> Vec<ContractAddress> {
+        self.links.read().sessions.get(session_id).cloned().unwrap_or_default()
+    }
+}
+
+/// SNIP-12 revision 1 message binding a wallet to a session
+pub fn link_message(session_id: &str, nonce: Felt, expires_at: DateTime<Utc>) -> Value {
+    json!({
+        "types": {
+            "StarknetDomain": [
+                {"name": "name", "type": "shortstring"},
+                {"name": "version", "type": "shortstring"},
+                {"name": "chainId", "type": "shortstring"},
+                {"name": "revision", "type": "shortstring"}
+            ],
+            "LinkWallet": [
+                {"name": "session", "type": "string"},
+                {"name": "nonce", "type": "felt"},
+                {"name": "expires", "type": "timestamp"}
+            ]
+        },
+        "primaryType": "LinkWallet",
+        "domain": {"name": "Brother Yields", "version": "1", "chainId": "SN_MAIN", "revision": "1"},
+        "message": {
+            "session": session_id,
+            "nonce": nonce.to_hex_string(),
+            "expires": expires_at.timestamp()
+        }
+    })
+}
+
+/// Asks the account contract whether `signature` signs `hash`
+async fn is_valid_signature<P: Provider + Sync>(
+    provider: &P,
+    wallet: ContractAddress,
+    hash: Felt,
+    signature: Vec<Felt>,
+) -> Result<bool, WalletLinkError> {
+    let mut calldata = vec![hash, Felt::from(signature.len())];
+    calldata.extend(signature);
+    let result = provider
+        .call(
+            FunctionCall {
+                contract_address: wallet.felt(),
+                entry_point_selector: selector!("is_valid_signature"),
+                calldata,
+            },
+            BlockId::Tag(BlockTag::Latest),
+        )
+        .await
+        .map_err(|e| WalletLinkError::Call(wallet.to_string(), e.to_string()))?;
+    Ok(result.first().is_some_and(|result| *result == VALID || *result == Felt::ONE))
+}
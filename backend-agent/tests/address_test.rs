@@ -0,0 +1,54 @@
+use backend_agent::address::{AddressError, ContractAddress};
+use backend_agent::types::Token;
+use schemars::schema_for;
+use serde_json::json;
+use starknet::core::types::Felt;
+
+const STRK: &str = "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d";
+
+#[test]
+fn test_addresses_are_normalized() {
+    let canonical: ContractAddress = STRK.parse().unwrap();
+    for input in [
+        "0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d",
+        "0x4718F5A0FC34CC1AF16A1CDEE98FFB20C31F5CD61D6AB07201858F4287C938D",
+        "4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d",
+        " 0X0004718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d ",
+    ] {
+        assert_eq!(input.parse::<ContractAddress>().unwrap(), canonical, "{input}");
+    }
+    assert_eq!(canonical.to_string(), STRK);
+    assert_eq!("0x1".parse::<ContractAddress>().unwrap().to_string(), format!("0x{:0>64}", "1"));
+}
+
+#[test]
+fn test_invalid_addresses_are_rejected() {
+    for input in ["", "0x", "0xzz", "alice.stark", "0x12 34"] {
+        assert!(matches!(input.parse::<ContractAddress>(), Err(AddressError::Parse(_))), "{input}");
+    }
+    // 2^251 - 256 and above are felts, not addresses
+    let bound = "0x7ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff00";
+    assert!(matches!(bound.parse::<ContractAddress>(), Err(AddressError::OutOfRange(_))));
+    assert!("0x7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffeff".parse::<ContractAddress>().is_ok());
+    assert!("0x7ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".parse::<ContractAddress>().is_err());
+    let two_pow_251 = "0x800000000000000000000000000000000000000000000000000000000000000";
+    assert!(matches!(two_pow_251.parse::<ContractAddress>(), Err(AddressError::OutOfRange(_))));
+    assert!(ContractAddress::new(Felt::MAX).is_err());
+    assert!(matches!(format!("0x1{}", "0".repeat(64)).parse::<ContractAddress>(), Err(AddressError::OutOfRange(_))));
+}
+
+#[test]
+fn test_addresses_serialize_canonically() {
+    let token: Token = serde_json::from_value(json!({
+        "name": "STRK",
+        "address": "0x4718F5A0FC34CC1AF16A1CDEE98FFB20C31F5CD61D6AB07201858F4287C938D",
+        "priceUSD": {"integral": 0, "fractional": 0, "decimals": 0}
+    }))
+    .unwrap();
+    assert_eq!(serde_json::to_value(&token).unwrap()["address"], STRK);
+    assert!(serde_json::from_value::<ContractAddress>(json!("0xnope")).is_err());
+
+    let schema = serde_json::to_value(schema_for!(ContractAddress)).unwrap();
+    assert_eq!(schema["type"], "string");
+    assert_eq!(schema["pattern"], "^(0x)?[0-9a-fA-F]{1,64}$");
+}
@@ -0,0 +1,60 @@
+use backend_agent::amount::{AmountError, TokenAmount};
+use starknet::core::types::{Felt, U256};
+
+#[test]
+fn test_u256_is_decoded_from_both_limbs() {
+    let amount = TokenAmount::from_u256_felts(&[Felt::from(5u8), Felt::ONE], 0).unwrap();
+    assert_eq!(amount.raw(), U256::from_words(5, 1));
+    assert_eq!(amount.to_string(), "340282366920938463463374607431768211461");
+
+    // a felt above 128 bits isn't a u256 limb, it must not panic
+    let error = TokenAmount::from_u256_felts(&[Felt::MAX, Felt::ZERO], 18).unwrap_err();
+    assert!(matches!(error, AmountError::InvalidLimb(_)));
+    assert_eq!(TokenAmount::from_u256_felts(&[], 18), Err(AmountError::Empty));
+}
+
+#[test]
+fn test_amounts_are_exact() {
+    // 1.000000000000000001 ETH doesn't survive an f64
+    let wei = TokenAmount::from_u256_felts(&[Felt::from(1_000_000_000_000_000_001u128), Felt::ZERO], 18).unwrap();
+    assert_eq!(wei.to_string(), "1.000000000000000001");
+    assert_eq!(wei, TokenAmount::parse("1.000000000000000001", 18).unwrap());
+
+    let sum = wei.checked_add(TokenAmount::parse("0.5", 18).unwrap()).unwrap();
+    assert_eq!(sum.to_string(), "1.500000000000000001");
+    assert_eq!(sum.checked_sub(wei).unwrap().to_string(), "0.5");
+    assert_eq!(wei.checked_sub(sum), Err(AmountError::Underflow));
+    assert_eq!(
+        wei.checked_add(TokenAmount::parse("1", 6).unwrap()),
+        Err(AmountError::DecimalsMismatch(18, 6))
+    );
+    let max = TokenAmount::new(U256::from_words(u128::MAX, u128::MAX), 18).unwrap();
+    assert_eq!(max.checked_add(wei), Err(AmountError::Overflow));
+
+    let share = sum.mul_div(U256::from(1u8), U256::from(3u8)).unwrap();
+    assert_eq!(share.to_string(), "0.5");
+    assert_eq!(sum.mul_div(U256::from(1u8), U256::from(0u8)), Err(AmountError::DivisionByZero));
+}
+
+#[test]
+fn test_formatting_and_parsing() {
+    assert_eq!(TokenAmount::parse("12", 6).unwrap().to_string(), "12");
+    assert_eq!(TokenAmount::parse("0.05", 6).unwrap().to_full_string(), "0.050000");
+    assert_eq!(TokenAmount::parse(".5", 6).unwrap().to_string(), "0.5");
+    assert_eq!(TokenAmount::zero(18).to_string(), "0");
+    assert!(TokenAmount::parse("0.0000001", 6).is_err());
+    assert!(TokenAmount::parse("-1", 6).is_err());
+    assert!(TokenAmount::parse("1e6", 6).is_err());
+    assert!((TokenAmount::parse("2.5", 18).unwrap().to_f64() - 2.5).abs() < f64::EPSILON);
+}
+
+#[test]
+fn test_serde_as_decimal_string() {
+    let amount = TokenAmount::parse("2.5", 6).unwrap();
+    let json = serde_json::to_value(amount).unwrap();
+    assert_eq!(json, "2.500000");
+    let back: TokenAmount = serde_json::from_value(json).unwrap();
+    assert_eq!(back, amount);
+    assert_eq!(back.decimals(), 6);
+    assert!(serde_json::from_value::<TokenAmount>("abc".into()).is_err());
+}
@@ -0,0 +1,181 @@
+#![allow(dead_code)]
+use axum::{routing::post, Json, Router};
+use backend_agent::agent_tools::portfolio::PortfolioFetch;
+use backend_agent::agents::navigator::Tools;
+use backend_agent::agents::registry::AgentRegistry;
+use backend_agent::agents::risk_officer::RiskLog;
+use backend_agent::backend::{messaging::ChatHistoryManager, Backend};
+use backend_agent::llm::mock::ScriptedModel;
+use backend_agent::history::PortfolioHistory;
+use backend_agent::positions::PositionAdapters;
+use backend_agent::prices::PriceFeed;
+use backend_agent::prompts::PromptLibrary;
+use backend_agent::starknet_id::{StarknetId, NAMING_CONTRACT};
+use backend_agent::token_registry::TokenRegistry;
+use backend_agent::types::ProtocolYield;
+use backend_agent::usage::UsageLedger;
+use parking_lot::Mutex;
+use rig::agent::AgentBuilder;
+use serde_json::{json, Value};
+use starknet::core::types::Felt;
+use std::collections::HashMap;
+use std::sync::Arc;
+use url::Url;
+
+/// Contract and selector of a call
+pub type Call = (Felt, Felt);
+
+/// Minimal Starknet JSON-RPC answering `starknet_call` with fixed `balanceOf` results
+#[derive(Clone, Default)]
+pub struct FakeRpc {
+    pub balances: Arc<Mutex<HashMap<Felt, Vec<Felt>>>>,
+    /// Results of other calls, by contract and selector
+    pub results: Arc<Mutex<HashMap<Call, Vec<Felt>>>>,
+    /// Tokens whose `balanceOf` answers with a JSON-RPC error
+    pub failing: Arc<Mutex<Vec<Felt>>>,
+    pub calls: Arc<Mutex<Vec<Value>>>,
+    /// HTTP requests received, a batch counts once
+    pub round_trips: Arc<Mutex<usize>>,
+}
+
+impl FakeRpc {
+    pub fn with_balance(self, token: Felt, raw_balance: u128) -> Self {
+        self.balances
+            .lock()
+            .insert(token, vec![Felt::from(raw_balance), Felt::ZERO]);
+        self
+    }
+
+    pub fn with_result(self, contract: Felt, selector: Felt, result: Vec<Felt>) -> Self {
+        self.results.lock().insert((contract, selector), result);
+        self
+    }
+
+    pub fn with_failure(self, token: Felt) -> Self {
+        self.failing.lock().push(token);
+        self
+    }
+
+    /// Serves the fake RPC on a random local port and returns its url
+    pub async fn serve(&self) -> Url {
+        let rpc = self.clone();
+        let app = Router::new().route(
+            "/",
+            post(move |Json(body): Json<Value>| {
+                let rpc = rpc.clone();
+                async move { Json(rpc.handle(body)) }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        Url::parse(&format!("http://{addr}/")).unwrap()
+    }
+
+    fn handle(&self, body: Value) -> Value {
+        *self.round_trips.lock() += 1;
+        match body {
+            Value::Array(requests) => Value::Array(requests.into_iter().map(|r| self.handle_one(r)).collect()),
+            request => self.handle_one(request),
+        }
+    }
+
+    fn handle_one(&self, request: Value) -> Value {
+        self.calls.lock().push(request.clone());
+        let id = request["id"].clone();
+        let call = if request["params"].is_array() {
+            request["params"][0].clone()
+        } else {
+            request["params"]["request"].clone()
+        };
+        let contract = call["contract_address"]
+            .as_str()
+            .and_then(|address| Felt::from_hex(address).ok())
+            .unwrap_or_default();
+        if self.failing.lock().contains(&contract) {
+            return json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": 40, "message": "Contract error"},
+            });
+        }
+        let selector = call["entry_point_selector"]
+            .as_str()
+            .and_then(|selector| Felt::from_hex(selector).ok())
+            .unwrap_or_default();
+        let result = self
+            .results
+            .lock()
+            .get(&(contract, selector))
+            .or(self.balances.lock().get(&contract))
+            .cloned()
+            .unwrap_or_else(|| vec![Felt::ZERO, Felt::ZERO]);
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": result.iter().map(|felt| format!("{felt:#x}")).collect::<Vec<_>>(),
+        })
+    }
+}
+
+pub struct TestApp {
+    pub backend: Backend<ScriptedModel>,
+    pub navigator_model: ScriptedModel,
+    pub defiproman_model: ScriptedModel,
+    /// Model of every registry agent, by agent name
+    pub models: HashMap<String, ScriptedModel>,
+}
+
+/// Backend wired with scripted models and a portfolio tool pointed at `rpc_url`
+pub async fn test_app(yields_data: Vec<ProtocolYield>, rpc_url: Url) -> TestApp {
+    test_app_with_registry(yields_data, rpc_url, AgentRegistry::load("agents.json").unwrap()).await
+}
+
+/// Same as [`test_app`] with a scripted model for each agent of `registry`
+pub async fn test_app_with_registry(yields_data: Vec<ProtocolYield>, rpc_url: Url, registry: AgentRegistry) -> TestApp {
+    let models: HashMap<String, ScriptedModel> = registry
+        .agents
+        .iter()
+        .map(|spec| (spec.name.clone(), ScriptedModel::default()))
+        .collect();
+
+    let (manager, receiver) = ChatHistoryManager::new();
+    let mut backend = Backend::new(
+        yields_data.clone(),
+        manager,
+        UsageLedger::default(),
+        PromptLibrary::load("prompts").unwrap(),
+        registry,
+    );
+    // snapshots and reviews kept in memory, never in the working directory
+    backend.app_state.lock().await.history = PortfolioHistory::default();
+    backend.risk_log = RiskLog::default();
+    // prices of the yields tokens only, never CoinGecko, and no protocol indexer
+    let prices = PriceFeed::from_yields(&yields_data);
+    let mut tools = Tools::new(yields_data, backend.app_state.clone(), TokenRegistry::load("tokens.json").unwrap());
+    tools.portfolio_tool = PortfolioFetch {
+        rpc_url: rpc_url.clone(),
+        prices,
+        positions: PositionAdapters::default(),
+        starknet_id: StarknetId::new(rpc_url, NAMING_CONTRACT),
+        ..tools.portfolio_tool
+    };
+    let builders = models
+        .iter()
+        .map(|(name, model)| {
+            let builder = match name.as_str() {
+                "defiproman" => AgentBuilder::new(model.clone()).preamble("You are DEFIPROMAN"),
+                _ => AgentBuilder::new(model.clone()),
+            };
+            (name.clone(), builder)
+        })
+        .collect();
+    backend.init_agents(builders, tools, receiver).await.unwrap();
+
+    TestApp {
+        backend,
+        navigator_model: models.get("navigator").cloned().unwrap_or_default(),
+        defiproman_model: models.get("defiproman").cloned().unwrap_or_default(),
+        models,
+    }
+}
@@ -0,0 +1,694 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use backend_agent::llm::mock::{HashEmbeddingModel, ScriptedModel};
+use backend_agent::starknet_id::{encode_label, NAMING_CONTRACT};
+use backend_agent::types::{PoolType, Price, ProtocolYield, Token};
+use common::{test_app, FakeRpc};
+use http_body_util::BodyExt;
+use rig::{
+    agent::AgentBuilder,
+    completion::{Chat, Prompt},
+    embeddings::EmbeddingModel,
+};
+use serde_json::{json, Value};
+use starknet::core::types::{Felt, TypedData};
+use starknet::macros::{felt, selector, short_string};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const WALLET: &str = "0x0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+fn strk_yields() -> Vec<ProtocolYield> {
+    vec![ProtocolYield {
+        token: Token {
+            name: "STRK".to_string(),
+            address: "0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d".parse().unwrap(),
+            price: Price::from_f64(0.5, 6),
+        },
+        apy: 12.5,
+        tvl: 1_000_000.0,
+        volume_24h: 50_000.0,
+        risk_score: 40.0,
+        pool_type: PoolType::Stable,
+    }]
+}
+
+async fn call(app: &axum::Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+fn post_json(uri: &str, body: Value) -> Request<Body> {
+    Request::post(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_scripted_model_replays_and_records() {
+    let model = ScriptedModel::default();
+    model.push_text("first").push_error("boom");
+    let agent = AgentBuilder::new(model.clone()).preamble("be brief").build();
+
+    assert_eq!(agent.prompt("hi").await.unwrap(), "first");
+    assert!(agent.chat("again", vec![]).await.is_err());
+    // script exhausted
+    assert!(agent.prompt("more").await.is_err());
+
+    let requests = model.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[0].prompt, "hi");
+    assert_eq!(requests[0].preamble.as_deref(), Some("be brief"));
+}
+
+#[tokio::test]
+async fn test_hash_embeddings_are_deterministic() {
+    let model = HashEmbeddingModel::default();
+    let a = model.embed_text("STRK staking on Nostra").await.unwrap();
+    let b = model.embed_text("STRK staking on Nostra").await.unwrap();
+    assert_eq!(a.vec, b.vec);
+    assert_eq!(a.vec.len(), model.ndims());
+}
+
+#[tokio::test]
+async fn test_conversation_through_http_handlers() {
+    let rpc = FakeRpc::default().with_balance(
+        felt!("0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d"),
+        2_500_000_000_000_000_000,
+    );
+    let rpc_url = rpc.serve().await;
+    let mut app = test_app(strk_yields(), rpc_url).await;
+    app.backend.admin_token = Some("secret".to_string());
+    let router = app.backend.router();
+
+    let (status, body) = call(&router, Request::get("/init-session").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    let session_id = body["message"].as_str().unwrap().to_string();
+
+    // greeting is answered without any LLM call
+    let (status, body) = call(&router, post_json("/prompt", json!({"prompt": "gm", "session_id": session_id}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["message"].as_str().unwrap().contains("Starknet brother"));
+    assert_eq!(body["metadata"]["intent"], "greeting");
+    assert_eq!(body["metadata"]["route"], "deterministic");
+    assert!(app.navigator_model.requests().is_empty());
+    assert!(app.defiproman_model.requests().is_empty());
+
+    // portfolio request goes straight to defiproman, which calls the portfolio tool
+    app.defiproman_model.push_tool_call(
+        "mainnet_fetch_portfolio_balance",
+        json!({"wallet_address": WALLET}),
+    );
+    let (status, body) = call(
+        &router,
+        post_json("/prompt", json!({"prompt": format!("check my wallet {WALLET}"), "session_id": session_id})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert!(body["message"].as_str().unwrap().contains("STRK"));
+    assert!(!rpc.calls.lock().is_empty());
+    assert!(app.navigator_model.requests().is_empty());
+
+    // the fetched portfolio is valued with the yields prices
+    let (status, body) = call(&router, Request::get(format!("/portfolio/{WALLET}")).body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total_value"], 1.25);
+    assert_eq!(body["assets"][0]["symbol"], "STRK");
+    assert_eq!(body["assets"][0]["amount"], "2.500000000000000000");
+    assert_eq!(body["assets"][0]["allocation"], 100.0);
+    assert_eq!(body["categories"]["volatile"]["allocation"], 100.0);
+    let (status, _) = call(&router, Request::get("/portfolio/0x42").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    // each fetch is kept as a snapshot, PnL needs a second one
+    let (status, body) = call(&router, Request::get(format!("/portfolio/{WALLET}/history")).body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["snapshots"][0]["portfolio"]["total_value"], 1.25);
+    let (status, _) = call(&router, Request::get(format!("/portfolio/{WALLET}/pnl?days=1")).body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // the next turn sees the portfolio in its history
+    app.defiproman_model.push_text("Stake your 2.5 STRK, Starknet brother.");
+    let (status, body) = call(
+        &router,
+        post_json("/prompt", json!({"prompt": "where should I put my STRK?", "session_id": session_id})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    // guardrails append the disclaimer to advice
+    assert_eq!(body["message"], "Stake your 2.5 STRK, Starknet brother.\nNot financial advice, DYOR.");
+    let last_request = app.defiproman_model.requests().pop().unwrap();
+    assert!(last_request
+        .chat_history
+        .iter()
+        .any(|message| message.content.contains("PORTFOLIO DATA") && message.content.contains("STRK: 2.5 tokens, $1.25")));
+    // and where it came from, to cite it
+    assert!(last_request.chat_history.iter().any(|message| message.content.contains(&format!("[portfolio:{WALLET}]"))));
+    assert!(last_request.tools.contains(&"mainnet_fetch_portfolio_balance".to_string()));
+    // the session id never reaches the model
+    assert!(last_request.chat_history.iter().all(|message| !message.content.contains(&session_id)));
+    // preamble comes from the defiproman template, rendered for this turn
+    let preamble = last_request.preamble.unwrap();
+    assert!(preamble.starts_with("You are 'DEFIPROMAN'"));
+    assert!(preamble.contains("STRK") && preamble.contains("balanced"));
+    assert_eq!(body["metadata"]["prompt_versions"], json!(["defiproman/v2", "risk_officer/v1"]));
+
+    // admin only, and without the session ids
+    let (status, _) = call(&router, Request::get("/admin/routing-decisions").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let request = Request::get("/admin/routing-decisions").header("authorization", "Bearer secret");
+    let (_, body) = call(&router, request.body(Body::empty()).unwrap()).await;
+    assert!(!body.to_string().contains(&session_id));
+    let routes: Vec<&str> = body["decisions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|decision| decision["route"].as_str().unwrap())
+        .collect();
+    assert_eq!(routes, ["deterministic", "defiproman", "defiproman"]);
+}
+
+#[tokio::test]
+async fn test_yield_question_is_answered_from_yields_data() {
+    let app = test_app(strk_yields(), "http://127.0.0.1:9/".parse().unwrap()).await;
+    let router = app.backend.router();
+    let (_, body) = call(&router, Request::get("/init-session").body(Body::empty()).unwrap()).await;
+    let session_id = body["message"].as_str().unwrap().to_string();
+
+    let (status, body) = call(&router, post_json("/prompt", json!({"prompt": "what is the APY of STRK?", "session_id": session_id}))).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["metadata"]["route"], "deterministic");
+    // the APY is already a percentage
+    let message = body["message"].as_str().unwrap();
+    assert!(message.contains("STRK/USDC Stable pool: APY 12.50%, TVL $1000000.00"), "{message}");
+
+    // the tool defiproman can call quotes the same percentage as the guardrails and prompts
+    app.defiproman_model.push_tool_call("estimate_yield_returns", json!({"token": "STRK"}));
+    let (status, body) = call(&router, post_json("/prompt", json!({"prompt": "should I farm STRK or ETH?", "session_id": session_id}))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert!(body["message"].as_str().unwrap().contains("APY 12.50%"), "{body}");
+    assert!(body["message"].as_str().unwrap().contains("[yields:"), "{body}");
+    assert!(body["metadata"]["guardrails"].as_array().unwrap().is_empty(), "{body}");
+}
+
+#[tokio::test]
+async fn test_unclassified_prompt_is_refined_by_navigator() {
+    let app = test_app(strk_yields(), "http://127.0.0.1:9/".parse().unwrap()).await;
+    let router = app.backend.router();
+    let (_, body) = call(&router, Request::get("/init-session").body(Body::empty()).unwrap()).await;
+    let session_id = body["message"].as_str().unwrap().to_string();
+
+    app.navigator_model.push_text("brother defiproman is it a good time to enter?");
+    app.defiproman_model.push_text("Wait for volatility to calm down.");
+    let (status, body) = call(
+        &router,
+        post_json("/prompt", json!({"prompt": "is it a good time to enter?", "session_id": session_id})),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["message"], "Wait for volatility to calm down.");
+    assert_eq!(
+        app.defiproman_model.requests()[0].prompt,
+        "brother defiproman is it a good time to enter?"
+    );
+}
+
+#[tokio::test]
+async fn test_llm_failure_is_reported_as_error() {
+    let app = test_app(strk_yields(), "http://127.0.0.1:9/".parse().unwrap()).await;
+    let router = app.backend.router();
+    let (_, body) = call(&router, Request::get("/init-session").body(Body::empty()).unwrap()).await;
+    let session_id = body["message"].as_str().unwrap().to_string();
+
+    app.defiproman_model.push_error("rate limited");
+    let (status, body) = call(
+        &router,
+        post_json("/prompt", json!({"prompt": "explain impermanent loss", "session_id": session_id})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body["status"], "error");
+}
+
+#[tokio::test]
+async fn test_guardrails_reask_on_hallucinated_token() {
+    let app = test_app(strk_yields(), "http://127.0.0.1:9/".parse().unwrap()).await;
+    let router = app.backend.router();
+    let (_, body) = call(&router, Request::get("/init-session").body(Body::empty()).unwrap()).await;
+    let session_id = body["message"].as_str().unwrap().to_string();
+
+    app.defiproman_model
+        .push_text("Ape into $MOONX on Uniswap for 300% APY.")
+        .push_text("Provide STRK liquidity on Ekubo at 15% APY, Starknet brother.");
+    let (status, body) = call(
+        &router,
+        post_json("/prompt", json!({"prompt": "best strategy for my STRK?", "session_id": session_id})),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    // second answer kept, its APY corrected from the yields data and a disclaimer added
+    assert_eq!(
+        body["message"],
+        "Provide STRK liquidity on Ekubo at 12.50% APY, Starknet brother.\nNot financial advice, DYOR."
+    );
+    let checks: Vec<&str> = body["metadata"]["guardrails"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|finding| finding["check"].as_str().unwrap())
+        .collect();
+    assert_eq!(checks, ["unknown_token", "unsupported_protocol", "disclaimer", "apy_mismatch", "disclaimer"]);
+    let requests = app.defiproman_model.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].prompt.contains("MOONX is not a supported token"));
+}
+
+#[tokio::test]
+async fn test_linked_wallets_are_proven_and_combined() {
+    let (hot, smart, stranger) = (felt!("0x111"), felt!("0x222"), felt!("0x333"));
+    // wallets are typed in any form and listed in canonical form
+    let (hot_address, smart_address) = (hot.to_fixed_hex_string(), smart.to_fixed_hex_string());
+    let rpc = FakeRpc::default()
+        .with_balance(felt!("0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d"), 2_500_000_000_000_000_000)
+        .with_result(hot, selector!("is_valid_signature"), vec![short_string!("VALID")])
+        .with_result(smart, selector!("is_valid_signature"), vec![felt!("0x1")]);
+    let app = test_app(strk_yields(), rpc.serve().await).await;
+    let router = app.backend.router();
+    let (_, body) = call(&router, Request::get("/init-session").body(Body::empty()).unwrap()).await;
+    let session_id = body["message"].as_str().unwrap().to_string();
+
+    let link = |wallet: Felt| {
+        let (router, session_id) = (router.clone(), session_id.clone());
+        async move {
+            let (_, challenge) = call(
+                &router,
+                post_json("/wallets/challenge", json!({"session_id": session_id, "wallet": wallet.to_hex_string()})),
+            )
+            .await;
+            let typed_data: TypedData = serde_json::from_value(challenge["typed_data"].clone()).unwrap();
+            let request = json!({"session_id": session_id, "wallet": wallet.to_hex_string(), "signature": ["0xa", "0xb"]});
+            let (status, body) = call(&router, post_json("/wallets/link", request)).await;
+            (status, body, typed_data.message_hash(wallet).unwrap())
+        }
+    };
+    let (status, _, hash) = link(hot).await;
+    assert_eq!(status, StatusCode::OK);
+    // the account is asked about the hash of the challenge it was given
+    let params = rpc.calls.lock().last().unwrap()["params"].clone();
+    let calldata = if params.is_array() { &params[0] } else { &params["request"] }["calldata"].clone();
+    assert_eq!(calldata, json!([hash.to_hex_string(), "0x2", "0xa", "0xb"]));
+    let (status, body, _) = link(smart).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["wallets"], json!([hot_address, smart_address]));
+    let (status, _, _) = link(stranger).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    // a challenge is only good once
+    let request = json!({"session_id": session_id, "wallet": "0x222", "signature": ["0xa"]});
+    let (status, _) = call(&router, post_json("/wallets/link", request)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // without a wallet in the prompt, the portfolio tool fetches every linked wallet
+    app.defiproman_model.push_tool_call("mainnet_fetch_portfolio_balance", json!({}));
+    let (status, body) = call(&router, post_json("/prompt", json!({"prompt": "show my portfolio", "session_id": session_id}))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["metadata"]["wallet"], hot_address);
+    assert_eq!(body["metadata"]["linked_wallets"], json!([hot_address, smart_address]));
+    // and the next turn reasons over the combined holdings
+    app.defiproman_model.push_text("Your STRK is idle, Starknet brother.");
+    call(&router, post_json("/prompt", json!({"prompt": "what should I do with my holdings?", "session_id": session_id}))).await;
+    let history = app.defiproman_model.requests().pop().unwrap().chat_history;
+    assert!(history.iter().any(|message| message.content.contains("User wallets combined portfolio balances, total value $2.50")
+        && message.content.contains(&format!("Per wallet: {hot_address} $1.25 (50.0%), {smart_address} $1.25 (50.0%)"))
+        && message.content.contains("STRK: 5 tokens")));
+
+    let (status, body) = call(&router, post_json("/wallets/portfolio", json!({"session_id": session_id}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["combined"]["total_value"], 2.5);
+    assert_eq!(body["combined"]["assets"][0]["amount"], "5.000000000000000000");
+    assert_eq!(body["wallets"][1]["valueUSD"], 1.25);
+
+    let request = json!({"session_id": session_id, "wallet": "0x0111"});
+    let (status, body) = call(&router, post_json("/wallets/unlink", request.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["wallets"], json!([smart_address]));
+    let (status, _) = call(&router, post_json("/wallets/unlink", request)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, body) = call(&router, post_json("/wallets", json!({"session_id": session_id}))).await;
+    assert_eq!(body["wallets"], json!([smart_address]));
+}
+
+#[tokio::test]
+async fn test_starknet_id_domains_resolve_to_wallets() {
+    let wallet = Felt::from_hex(WALLET).unwrap();
+    let rpc = FakeRpc::default()
+        .with_balance(felt!("0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d"), 2_500_000_000_000_000_000)
+        .with_result(NAMING_CONTRACT, selector!("domain_to_address"), vec![wallet])
+        .with_result(NAMING_CONTRACT, selector!("address_to_domain"), vec![Felt::ONE, encode_label("alice").unwrap()]);
+    let app = test_app(strk_yields(), rpc.serve().await).await;
+    let router = app.backend.router();
+    let (_, body) = call(&router, Request::get("/init-session").body(Body::empty()).unwrap()).await;
+    let session_id = body["message"].as_str().unwrap().to_string();
+
+    // the domain is resolved before the turn, tools get the address
+    app.defiproman_model.push_tool_call("mainnet_fetch_portfolio_balance", json!({"wallet_address": "alice.stark"}));
+    let (status, body) = call(&router, post_json("/prompt", json!({"prompt": "check alice.stark", "session_id": session_id}))).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["metadata"]["intent"], "portfolio_request");
+    assert_eq!(body["metadata"]["wallet"], WALLET);
+
+    // and the portfolio is shown under the domain
+    let (status, body) = call(&router, Request::get("/portfolio/alice.stark").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["wallet_address"], WALLET);
+    assert_eq!(body["domain"], "alice.stark");
+    let (_, body) = call(&router, Request::get(format!("/starknet-id/{WALLET}")).body(Body::empty()).unwrap()).await;
+    assert_eq!(body, json!({"address": WALLET, "domain": "alice.stark"}));
+    let (status, _) = call(&router, Request::get("/portfolio/b%C3%A9n.stark").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    app.defiproman_model.push_text("Nice bag, Starknet brother.");
+    call(&router, post_json("/prompt", json!({"prompt": "anything to improve in my holdings?", "session_id": session_id}))).await;
+    let history = app.defiproman_model.requests().pop().unwrap().chat_history;
+    assert!(history
+        .iter()
+        .any(|message| message.content.contains(&format!("User wallet alice.stark ({WALLET}) portfolio balances"))));
+}
+
+#[tokio::test]
+async fn test_portfolio_tool_refuses_wallets_the_user_did_not_share() {
+    let rpc = FakeRpc::default();
+    let rpc_url = rpc.serve().await;
+    let app = test_app(strk_yields(), rpc_url).await;
+    let router = app.backend.router();
+    let (_, body) = call(&router, Request::get("/init-session").body(Body::empty()).unwrap()).await;
+    let session_id = body["message"].as_str().unwrap().to_string();
+
+    // e.g. a prompt injection steering the model to another wallet
+    app.defiproman_model
+        .push_tool_call("mainnet_fetch_portfolio_balance", json!({"wallet_address": "0x1234567890abcdef"}));
+    let (status, _) = call(
+        &router,
+        post_json("/prompt", json!({"prompt": format!("check my wallet {WALLET}"), "session_id": session_id})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(rpc.calls.lock().is_empty());
+
+    // without an argument the tool uses the wallet shared earlier in the session
+    app.defiproman_model.push_tool_call("mainnet_fetch_portfolio_balance", json!({}));
+    let (status, body) = call(
+        &router,
+        post_json("/prompt", json!({"prompt": "show my portfolio again", "session_id": session_id})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert!(!rpc.calls.lock().is_empty());
+}
+
+#[tokio::test]
+async fn test_structured_strategy_is_validated_against_yields() {
+    let app = test_app(strk_yields(), "http://127.0.0.1:9/".parse().unwrap()).await;
+    let router = app.backend.router();
+    let (_, body) = call(&router, Request::get("/init-session").body(Body::empty()).unwrap()).await;
+    let session_id = body["message"].as_str().unwrap().to_string();
+
+    app.defiproman_model
+        .push_text("Put half of your STRK in the Ekubo STRK/USDC pool at 12.5% APY. Not financial advice, DYOR.")
+        .push_tool_call(
+            "submit_strategy",
+            json!({"actions": [
+                {
+                    "protocol": "Ekubo", "pool": "STRK/USDC", "pool_type": "Stable", "token": "STRK",
+                    "amount": null, "portfolio_percentage": 50.0, "expected_apy": 20.0, "risk_score": 10.0,
+                    "rationale": "Stable yield on your main holding"
+                },
+                {
+                    "protocol": "Uniswap", "pool": "ETH/USDC", "pool_type": "Volatile", "token": "ETH",
+                    "amount": 1.0, "portfolio_percentage": null, "expected_apy": 8.0, "risk_score": 50.0,
+                    "rationale": "Diversify"
+                }
+            ]}),
+        );
+    let (status, body) = call(
+        &router,
+        post_json(
+            "/prompt",
+            json!({"prompt": "where should I put my STRK?", "session_id": session_id, "structured": true}),
+        ),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let cards = body["strategy"]["cards"].as_array().unwrap();
+    assert_eq!(cards.len(), 1);
+    // the model's numbers are replaced by the live ones
+    assert_eq!(cards[0]["expected_apy"], 12.5);
+    assert_eq!(cards[0]["risk_score"], 40.0);
+    assert_eq!(cards[0]["live"]["tvl"], 1_000_000.0);
+    assert!(cards[0]["warnings"][0].as_str().unwrap().contains("live is 12.50%"));
+    assert_eq!(body["strategy"]["rejected"].as_array().unwrap().len(), 1);
+
+    let requests = app.defiproman_model.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].tools.iter().any(|tool| tool == "submit_strategy"));
+
+    // without the flag there's no second call
+    app.defiproman_model.push_text("Stake your STRK on Ekubo. Not financial advice, DYOR.");
+    let (_, body) = call(
+        &router,
+        post_json("/prompt", json!({"prompt": "where should I put my STRK?", "session_id": session_id})),
+    )
+    .await;
+    assert!(body.get("strategy").is_none());
+    assert_eq!(app.defiproman_model.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn test_compound_question_is_planned_over_several_tools() {
+    let rpc = FakeRpc::default().with_balance(
+        felt!("0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d"),
+        2_500_000_000_000_000_000,
+    );
+    let rpc_url = rpc.serve().await;
+    let app = test_app(strk_yields(), rpc_url).await;
+    let router = app.backend.router();
+    let (_, body) = call(&router, Request::get("/init-session").body(Body::empty()).unwrap()).await;
+    let session_id = body["message"].as_str().unwrap().to_string();
+
+    app.defiproman_model
+        .push_tool_call("mainnet_fetch_portfolio_balance", json!({}))
+        .push_tool_call("estimate_yield_returns", json!({"token": "STRK", "risk_score": null}))
+        .push_text("Put your 2.5 STRK in the Ekubo STRK pool at 12.5% APY. Not financial advice, DYOR.");
+    let (status, body) = call(
+        &router,
+        post_json(
+            "/prompt",
+            json!({"prompt": format!("given my wallet {WALLET}, where should I put my STRK?"), "session_id": session_id}),
+        ),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["message"], "Put your 2.5 STRK in the Ekubo STRK pool at 12.5% APY. Not financial advice, DYOR.");
+    assert_eq!(body["metadata"]["route"], "plan");
+    let steps: Vec<(&str, Option<&str>)> = body["metadata"]["plan"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|step| (step["kind"].as_str().unwrap(), step["tool"].as_str()))
+        .collect();
+    assert_eq!(
+        steps,
+        [
+            ("tool_call", Some("mainnet_fetch_portfolio_balance")),
+            ("tool_call", Some("estimate_yield_returns")),
+            ("answer", None)
+        ]
+    );
+
+    // sources the tools used are returned and tagged in their results for the model to cite
+    let sources: Vec<&str> = body["sources"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|source| source["kind"].as_str().unwrap())
+        .collect();
+    assert_eq!(sources, ["portfolio", "yields"]);
+    assert_eq!(body["sources"][0]["wallet"], body["metadata"]["wallet"]);
+
+    // each step sees the previous results, which never reach the chat history
+    let requests = app.defiproman_model.requests();
+    assert_eq!(requests.len(), 3);
+    assert!(requests[2].chat_history.iter().any(|message| message.content.starts_with("Result of estimate_yield_returns")
+        && message.content.contains("[yields:")));
+    app.defiproman_model.push_text("Nothing new, Starknet brother.");
+    call(&router, post_json("/prompt", json!({"prompt": "where should I put my ETH?", "session_id": session_id}))).await;
+    let requests = app.defiproman_model.requests();
+    assert_eq!(requests.len(), 4);
+    let last_request = &requests[3];
+    assert!(last_request.chat_history.iter().all(|message| !message.content.starts_with("Result of")));
+}
+
+#[tokio::test]
+async fn test_planner_stops_at_step_limit() {
+    let app = test_app(strk_yields(), "http://127.0.0.1:9/".parse().unwrap()).await;
+    {
+        let mut state = app.backend.app_state.lock().await;
+        let navigator = &mut state.agent_state.as_mut().unwrap().navigator;
+        Arc::get_mut(navigator).unwrap().planner.max_steps = 2;
+    }
+    let router = app.backend.router();
+    let (_, body) = call(&router, Request::get("/init-session").body(Body::empty()).unwrap()).await;
+    let session_id = body["message"].as_str().unwrap().to_string();
+
+    app.defiproman_model
+        .push_tool_call("estimate_yield_returns", json!({"token": "STRK", "risk_score": null}))
+        .push_tool_call("estimate_yield_returns", json!({"token": "ETH", "risk_score": null}));
+    let (status, _) = call(
+        &router,
+        post_json("/prompt", json!({"prompt": "should I rebalance my portfolio?", "session_id": session_id})),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let requests = app.defiproman_model.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].prompt.contains("out of steps"));
+}
+
+#[tokio::test]
+async fn test_risk_officer_revises_then_annotates_answer() {
+    let mut yields = strk_yields();
+    yields.push(ProtocolYield {
+        apy: 180.0,
+        risk_score: 95.0,
+        pool_type: PoolType::Degen,
+        ..yields[0].clone()
+    });
+    let mut app = test_app(yields, "http://127.0.0.1:9/".parse().unwrap()).await;
+    app.backend.admin_token = Some("secret".to_string());
+    let router = app.backend.router();
+    let (_, body) = call(&router, Request::get("/init-session").body(Body::empty()).unwrap()).await;
+    let session_id = body["message"].as_str().unwrap().to_string();
+
+    app.defiproman_model
+        .push_text("Ape your STRK in the Degen pool at 180% APY. Not financial advice, DYOR.")
+        .push_text("Put your STRK in the Stable pool at 12.5% APY. Not financial advice, DYOR.");
+    app.models["risk_officer"]
+        .push_tool_call(
+            "submit_verdict",
+            json!({"verdict": "revise", "reason": "Degen pools don't fit a conservative user"}),
+        )
+        .push_tool_call(
+            "submit_verdict",
+            json!({"verdict": "annotate", "warnings": ["Stable pools still carry smart contract risk."]}),
+        );
+    let (status, body) = call(
+        &router,
+        post_json("/prompt", json!({"prompt": "where should I put my STRK? I want the safest option", "session_id": session_id})),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(
+        body["message"],
+        "Put your STRK in the Stable pool at 12.5% APY. Not financial advice, DYOR.\n\
+         Risk warning: Stable pools still carry smart contract risk."
+    );
+    let reviews = body["metadata"]["risk_reviews"].as_array().unwrap();
+    assert_eq!(reviews.len(), 2);
+    assert_eq!(reviews[0]["verdict"], "revise");
+    assert_eq!(reviews[0]["risk_profile"], "conservative");
+    assert_eq!(reviews[1]["verdict"], "annotate");
+
+    // the reviewer sees the pools' risk scores, the specialist the reason of the revision
+    let review_request = &app.models["risk_officer"].requests()[0];
+    assert!(review_request.prompt.contains("Degen: risk score 95/100"));
+    assert!(review_request.tools.iter().any(|tool| tool == "submit_verdict"));
+    let revision_request = app.defiproman_model.requests().pop().unwrap();
+    assert!(revision_request.prompt.contains("Degen pools don't fit a conservative user"));
+    assert!(revision_request.chat_history.iter().any(|message| message.content.contains("Ape your STRK")));
+
+    let (status, body) = call(
+        &router,
+        Request::get("/admin/risk-reviews")
+            .header("authorization", "Bearer secret")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["reviews"].as_array().unwrap().len(), 2);
+    assert_eq!(body["reviews"][0]["session_id"], session_id);
+}
+
+#[tokio::test]
+async fn test_expertise_selects_preamble_set_by_api_or_inferred() {
+    let rpc = FakeRpc::default().with_result(Felt::from_hex(WALLET).unwrap(), selector!("is_valid_signature"), vec![short_string!("VALID")]);
+    let app = test_app(strk_yields(), rpc.serve().await).await;
+    let router = app.backend.router();
+    let (_, body) = call(&router, Request::get("/init-session").body(Body::empty()).unwrap()).await;
+    let session_id = body["message"].as_str().unwrap().to_string();
+
+    // inferred from the conversation
+    app.defiproman_model.push_text("Staking means locking STRK to earn rewards, Starknet brother.");
+    let (_, body) = call(
+        &router,
+        post_json("/prompt", json!({"prompt": "I'm new to DeFi, what is staking?", "session_id": session_id})),
+    )
+    .await;
+    assert_eq!(body["metadata"]["expertise"], "beginner");
+    assert_eq!(body["metadata"]["prompt_versions"][0], "defiproman_beginner/v1");
+    let preamble = app.defiproman_model.requests().pop().unwrap().preamble.unwrap();
+    assert!(preamble.contains("under 6 lines"));
+
+    // a wallet's expertise is only set by a session it's linked to
+    let set_wallet = json!({"session_id": session_id, "wallet": WALLET, "expertise": "pro"});
+    let (status, _) = call(&router, post_json("/expertise", set_wallet.clone())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    // session_id is required
+    let response = router.clone().oneshot(post_json("/expertise", json!({"wallet": WALLET, "expertise": "pro"}))).await;
+    assert_eq!(response.unwrap().status(), StatusCode::UNPROCESSABLE_ENTITY);
+    call(&router, post_json("/wallets/challenge", json!({"session_id": session_id, "wallet": WALLET}))).await;
+    let link = json!({"session_id": session_id, "wallet": WALLET, "signature": ["0xa", "0xb"]});
+    let (status, _) = call(&router, post_json("/wallets/link", link)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // a wallet set to pro by the API is overridden by the session's own setting
+    let (status, _) = call(&router, post_json("/expertise", set_wallet)).await;
+    assert_eq!(status, StatusCode::OK);
+    app.defiproman_model.push_text("STRK Stable pool, 12.50% APY, Starknet brother. DYOR.");
+    let (_, body) = call(
+        &router,
+        post_json("/prompt", json!({"prompt": format!("best pool for {WALLET}?"), "session_id": session_id})),
+    )
+    .await;
+    assert_eq!(body["metadata"]["expertise"], "pro");
+    let preamble = app.defiproman_model.requests().pop().unwrap().preamble.unwrap();
+    assert!(preamble.contains("APR 11.78% compounded daily"));
+    assert!(preamble.contains("0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d"));
+
+    let (status, _) = call(
+        &router,
+        post_json("/expertise", json!({"session_id": session_id, "expertise": "standard"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    app.defiproman_model.push_text("Stake your STRK, Starknet brother. DYOR.");
+    let (_, body) = call(&router, post_json("/prompt", json!({"prompt": "where should I stake STRK?", "session_id": session_id}))).await;
+    assert_eq!(body["metadata"]["expertise"], "standard");
+    assert_eq!(body["metadata"]["prompt_versions"][0], "defiproman/v2");
+
+    let response = router.clone().oneshot(post_json("/expertise", json!({"expertise": "pro"}))).await;
+    assert_eq!(response.unwrap().status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let invalid = json!({"session_id": session_id, "wallet": "not a wallet", "expertise": "pro"});
+    let (status, _) = call(&router, post_json("/expertise", invalid)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use backend_agent::eval::{run_suite, EvalCheck, Suite};
+use backend_agent::types::{PoolType, Price, ProtocolYield, Token};
+use common::{test_app, FakeRpc};
+use serde_json::json;
+use starknet::macros::felt;
+
+const WALLET: &str = "0x0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+fn strk_yields() -> Vec<ProtocolYield> {
+    vec![ProtocolYield {
+        token: Token {
+            name: "STRK".to_string(),
+            address: "0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d".parse().unwrap(),
+            price: Price::from_f64(0.5, 6),
+        },
+        apy: 12.5,
+        tvl: 1_000_000.0,
+        volume_24h: 50_000.0,
+        risk_score: 40.0,
+        pool_type: PoolType::Stable,
+    }]
+}
+
+fn suite() -> Suite {
+    serde_json::from_value(json!({
+        "name": "test",
+        "conversations": [
+            {"name": "off-topic", "turns": [{"prompt": "write me a poem", "expect": {"tools": [], "refusal": true}}]},
+            {"name": "portfolio", "turns": [
+                {"prompt": format!("check my wallet {WALLET}"), "expect": {"tools": ["mainnet_fetch_portfolio_balance"]}},
+                {"prompt": "what should I do with my tokens?", "expect": {"tools": [], "references_holdings": true}}
+            ]},
+            {"name": "hallucination", "turns": [{"prompt": "best strategy for yield?"}]}
+        ]
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_bundled_suites_parse() {
+    for entry in std::fs::read_dir("evals").unwrap() {
+        let suite = Suite::load(entry.unwrap().path()).unwrap();
+        assert!(!suite.conversations.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_suite_is_scored_and_reported() {
+    let rpc = FakeRpc::default().with_balance(
+        felt!("0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d"),
+        2_500_000_000_000_000_000,
+    );
+    let app = test_app(strk_yields(), rpc.serve().await).await;
+    app.defiproman_model
+        .push_tool_call("mainnet_fetch_portfolio_balance", json!({"wallet_address": WALLET}))
+        .push_text("Stake your STRK on Endur, Starknet brother.")
+        // hallucinated token, still there after the guardrails re-ask
+        .push_text("Buy $PEPE now.")
+        .push_text("Buy $PEPE now, really.");
+
+    let navigator = app.backend.app_state.lock().await.agent_state.clone().unwrap().navigator;
+    let report = run_suite(&navigator, &suite(), "baseline").await;
+
+    assert_eq!(report.conversations.len(), 3);
+    assert_eq!(report.by_check[&EvalCheck::ToolSelection].passed, 3);
+    assert_eq!(report.by_check[&EvalCheck::ReferencesHoldings].passed, 1);
+    assert_eq!(report.by_check[&EvalCheck::OffTopicRefusal].passed, 1);
+    let tokens = report.by_check[&EvalCheck::SupportedTokens];
+    assert_eq!((tokens.passed, tokens.total), (3, 4));
+    assert!(report.score < 1.0);
+    assert!(report.prompt_versions.contains("defiproman/v2"));
+
+    let markdown = report.to_markdown();
+    assert!(markdown.contains("## Failures"));
+    assert!(markdown.contains("PEPE is not a supported token"));
+
+    let mut candidate = report.clone();
+    candidate.label = "candidate".to_string();
+    candidate.score = 1.0;
+    assert!(candidate.compare(&report).contains("| **Score** |"));
+}
+
+#[tokio::test]
+async fn test_repaired_answers_still_fail_their_checks() {
+    let app = test_app(strk_yields(), FakeRpc::default().serve().await).await;
+    app.defiproman_model
+        .push_text("Provide STRK liquidity at 15% APY, DYOR")
+        .push_text("one\ntwo\nthree\nfour\nfive");
+    let suite: Suite = serde_json::from_value(json!({
+        "name": "repairs",
+        "conversations": [
+            {"name": "apy", "turns": [{"prompt": "best strategy for yield?"}]},
+            {"name": "long", "turns": [{"prompt": "best strategy for yield?"}]}
+        ]
+    }))
+    .unwrap();
+
+    let navigator = app.backend.app_state.lock().await.agent_state.clone().unwrap().navigator;
+    let report = run_suite(&navigator, &suite, "baseline").await;
+
+    let apy = &report.conversations[0].turns[0];
+    assert!(apy.response.as_ref().unwrap().contains("12.50% APY"));
+    let failed: Vec<EvalCheck> = report
+        .conversations
+        .iter()
+        .flat_map(|c| &c.turns)
+        .flat_map(|t| &t.checks)
+        .filter(|result| !result.passed)
+        .map(|result| result.check)
+        .collect();
+    assert_eq!(failed, [EvalCheck::ApyAccuracy, EvalCheck::LineLimit]);
+}
@@ -0,0 +1,128 @@
+use backend_agent::agents::guardrails::{Check, GuardrailConfig, Guardrails};
+use backend_agent::agents::turn::{self, TurnMetadata};
+use backend_agent::types::{Expertise, PoolType, Price, ProtocolYield, Token};
+
+fn guardrails() -> Guardrails {
+    let yields = vec![
+        ProtocolYield {
+            token: Token {
+                name: "BROTHER".to_string(),
+                address: "0x3b405a98c9e795d427fe82cdeeeed803f221b52471e3a757574a2b4180793ee".parse().unwrap(),
+                price: Price::from_f64(0.01, 6),
+            },
+            apy: 48.0,
+            tvl: 10_000.0,
+            volume_24h: 5_000.0,
+            risk_score: 90.0,
+            pool_type: PoolType::Degen,
+        },
+        ProtocolYield {
+            token: Token {
+                name: "STRK".to_string(),
+                address: "0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d".parse().unwrap(),
+                price: Price::from_f64(0.5, 6),
+            },
+            apy: 12.5,
+            tvl: 1_000_000.0,
+            volume_24h: 50_000.0,
+            risk_score: 20.0,
+            pool_type: PoolType::Stable,
+        },
+    ];
+    Guardrails::new(GuardrailConfig::default(), ["STRK".to_string(), "ETH".to_string()], yields)
+}
+
+fn checks(response: &str) -> Vec<Check> {
+    guardrails().review(response).findings.iter().map(|finding| finding.check).collect()
+}
+
+#[test]
+fn test_clean_answer_passes() {
+    let review = guardrails().review("GM Starknet brother, what can I do for you?");
+    assert!(review.findings.is_empty());
+    assert_eq!(review.response, "GM Starknet brother, what can I do for you?");
+}
+
+#[test]
+fn test_long_answer_is_truncated() {
+    let review = guardrails().review("one\ntwo\n\nthree\nfour");
+    assert_eq!(review.response, "one\ntwo\nthree");
+    assert!(!review.needs_reask());
+}
+
+#[test]
+fn test_disclaimer_is_kept_within_the_line_limit() {
+    let review = guardrails().review("one\ntwo\nthree\nStake STRK");
+    assert_eq!(review.response, "one\ntwo\nNot financial advice, DYOR.");
+    assert!(!review.needs_reask());
+}
+
+#[test]
+fn test_unknown_tokens_and_foreign_protocols_need_reask() {
+    assert_eq!(checks("Swap your ETH for PEPE."), [Check::UnknownToken, Check::Disclaimer]);
+    assert_eq!(checks("Aave has good rates for ETH"), [Check::UnsupportedProtocol]);
+    // acronyms and supported tokens are fine
+    assert!(checks("The TVL of STRK pools is high").is_empty());
+    assert!(guardrails().review("Buy $WAGMI").needs_reask());
+    assert_eq!(checks("Buy 100 MOONX, DYOR"), [Check::UnknownToken]);
+    // protocols and plain caps words aren't tickers
+    assert!(checks("Lend STRK on NOSTRA, swap on AVNU or LP on EKUBO. RISK: LOW, DEGEN pools and LST yields vary, DYOR").is_empty());
+    let review = guardrails().with_protocols(["vSTRK".to_string()]).review("Your VSTRK vault is fine, DYOR");
+    assert!(review.findings.is_empty());
+}
+
+#[test]
+fn test_degen_pool_gets_warning() {
+    let review = guardrails().review("BROTHER pays 48% APY");
+    assert_eq!(review.findings.len(), 1);
+    assert!(review.response.contains("Degen pools can lose most of their value"));
+    assert!(review.response.ends_with("Not financial advice, DYOR."));
+}
+
+#[test]
+fn test_apy_within_tolerance_is_kept() {
+    assert_eq!(checks("BROTHER yields about 50% right now, DYOR"), []);
+    let review = guardrails().review("BROTHER yields 90% right now, DYOR");
+    assert_eq!(review.response, "BROTHER yields 48.00% right now, DYOR");
+}
+
+#[test]
+fn test_only_apy_quotes_are_checked() {
+    // allocations aren't APY quotes
+    let answer = "Move 30% of your STRK into BROTHER, put 20% in STRK staking, DYOR";
+    assert_eq!(guardrails().review(answer).response, answer);
+
+    // the quoted number is replaced, not its first occurrence
+    let review = guardrails().review("Put 15% in STRK, it pays 15% APY, DYOR");
+    assert_eq!(review.response, "Put 15% in STRK, it pays 12.50% APY, DYOR");
+
+    // can't tell which token the APY is about, so the model is asked again
+    let answer = "BROTHER and STRK both pay 30% APY, DYOR";
+    let review = guardrails().review(answer);
+    assert_eq!(review.response, answer);
+    assert_eq!(checks(answer), [Check::ApyMismatch]);
+    assert!(review.needs_reask());
+}
+
+#[tokio::test]
+async fn test_expertise_changes_length_and_degen_warning() {
+    let at = |expertise| TurnMetadata {
+        expertise,
+        ..TurnMetadata::new("session")
+    };
+    let long = "one\ntwo\nthree\nfour\nfive\nsix\nseven";
+
+    let review = turn::scope(at(Expertise::Beginner), async { guardrails().review(long) }).await.0;
+    assert_eq!(review.response.lines().count(), 6);
+    let review = turn::scope(at(Expertise::Pro), async { guardrails().review(long) }).await.0;
+    assert_eq!(review.response, long);
+
+    // beginners get the Degen warning even when the answer has a disclaimer
+    let answer = "BROTHER pays 48% APY, DYOR";
+    let review = turn::scope(at(Expertise::Beginner), async { guardrails().review(answer) }).await.0;
+    assert!(review.response.contains("Degen pools can lose most of their value"));
+    assert_eq!(guardrails().review(answer).response, answer);
+
+    // APRs quoted in breakdowns aren't APY mismatches
+    assert!(checks("BROTHER: APY 48% from APR 39.3%, DYOR").is_empty());
+}
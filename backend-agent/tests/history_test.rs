@@ -0,0 +1,175 @@
+use backend_agent::address::ContractAddress;
+use backend_agent::agent_tools::performance::format_change;
+use backend_agent::agents::strategy::Strategy;
+use backend_agent::amount::TokenAmount;
+use backend_agent::history::{PortfolioHistory, Retention, Snapshot};
+use backend_agent::positions::{Position, PositionAmount, PositionKind};
+use backend_agent::token_registry::TokenRegistry;
+use backend_agent::types::{Portfolio, Token};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::json;
+use starknet::core::types::Felt;
+use starknet::macros::felt;
+use std::collections::HashMap;
+
+const STRK: Felt = felt!("0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d");
+
+fn wallet() -> ContractAddress {
+    "0x123".parse().unwrap()
+}
+
+/// `strk` staked on vSTRK
+fn snapshot(days_ago: i64, strk: &str, price: f64) -> Snapshot {
+    snapshot_at(Utc::now() - Duration::days(days_ago), "0", strk, price)
+}
+
+fn snapshot_at(taken_at: DateTime<Utc>, in_wallet: &str, staked: &str, price: f64) -> Snapshot {
+    let token = Token {
+        name: "STRK".to_string(),
+        address: ContractAddress::new(STRK).unwrap(),
+        ..Default::default()
+    };
+    let registry = TokenRegistry::load("tokens.json").unwrap();
+    let balances = HashMap::from([(token.clone(), TokenAmount::parse(in_wallet, 18).unwrap())]);
+    let prices = HashMap::from([(token.address, price)]);
+    let staking = Position {
+        protocol: "vSTRK".to_string(),
+        kind: PositionKind::Staking,
+        contract: registry.by_symbol("vSTRK").unwrap().address,
+        id: None,
+        underlying: vec![PositionAmount::new(
+            &registry.get(token.address).unwrap(),
+            TokenAmount::parse(staked, 18).unwrap(),
+        )],
+        rewards: vec![],
+    };
+    Snapshot {
+        wallet: wallet(),
+        taken_at,
+        portfolio: Portfolio::from_balances(wallet(), &balances, &prices, &registry)
+            .with_positions(vec![staking], &prices, &registry),
+    }
+}
+
+fn strategy(token: &str, expected_apy: f64) -> Strategy {
+    serde_json::from_value(json!({
+        "cards": [{
+            "protocol": "Endur", "pool": "xSTRK", "pool_type": "Stable", "token": token,
+            "amount": null, "portfolio_percentage": 100.0, "expected_apy": expected_apy,
+            "risk_score": 10.0, "rationale": "staking",
+            "live": {"apy": expected_apy, "tvl": 1e6, "volume_24h": 0.0, "risk_score": 10.0, "pool_type": "Stable"},
+            "warnings": []
+        }],
+        "rejected": []
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_change_reports_pnl_and_realized_yield() {
+    let history = PortfolioHistory::default();
+    history.record_predictions(wallet(), &strategy("STRK", 12.0));
+    history.record_snapshot(snapshot(365, "100", 0.5));
+    history.record_snapshot(snapshot(30, "105", 0.5));
+    history.record_snapshot(snapshot(0, "110", 0.4));
+    assert!(history.change("0x456".parse().unwrap(), Utc::now()).is_none());
+
+    // since a date before every snapshot: the whole year
+    let change = history.change(wallet(), Utc::now() - Duration::days(400)).unwrap();
+    assert_eq!(change.value_from, 50.0);
+    assert_eq!(change.value_to, 44.0);
+    assert_eq!(change.delta, -6.0);
+    assert_eq!(change.delta_percentage, Some(-12.0));
+    assert_eq!(change.tokens.len(), 1);
+    assert_eq!(change.tokens[0].amount_from, 100.0);
+    assert_eq!(change.tokens[0].amount_to, 110.0);
+    assert_eq!(change.tokens[0].pnl, Some(-6.0));
+    // 10% more tokens over a year, against the 12% quoted
+    assert_eq!(change.yields.len(), 1);
+    assert_eq!(change.yields[0].predicted_apy, 12.0);
+    let realized = change.yields[0].realized_apy.unwrap();
+    assert!((realized - 10.0).abs() < 0.1, "{realized}");
+
+    // the last week starts at the snapshot of 30 days ago
+    let week = history.change(wallet(), Utc::now() - Duration::days(7)).unwrap();
+    assert_eq!(week.value_from, 52.5);
+    let content = format_change(&week);
+    assert!(content.contains("$52.50 -> $44.00 ($-8.50"), "{content}");
+    assert!(content.contains("STRK: 105 -> 110 tokens"), "{content}");
+    assert!(content.contains("STRK on Endur (xSTRK): predicted 12.00% APY"), "{content}");
+}
+
+#[test]
+fn test_transfers_and_short_windows_have_no_realized_yield() {
+    let history = PortfolioHistory::default();
+    history.record_predictions(wallet(), &strategy("STRK", 12.0));
+    let start = Utc::now() - Duration::days(30);
+    history.record_snapshot(snapshot_at(start, "50", "100", 0.5));
+    // 50 STRK moved from the wallet into staking isn't yield
+    history.record_snapshot(snapshot_at(Utc::now(), "0", "150", 0.5));
+    let change = history.change(wallet(), start).unwrap();
+    assert_eq!(change.tokens[0].amount_to, 150.0);
+    assert_eq!(change.yields[0].realized_apy, None);
+
+    // staking left alone, but over less than a day
+    let history = PortfolioHistory::default();
+    history.record_predictions(wallet(), &strategy("STRK", 12.0));
+    let start = Utc::now() - Duration::hours(1);
+    history.record_snapshot(snapshot_at(start, "0", "100", 0.5));
+    history.record_snapshot(snapshot_at(Utc::now(), "0", "100.01", 0.5));
+    assert_eq!(history.change(wallet(), start).unwrap().yields[0].realized_apy, None);
+}
+
+#[test]
+fn test_history_is_persisted_as_jsonl() {
+    let path = std::env::temp_dir().join(format!("history-{}.jsonl", uuid::Uuid::new_v4()));
+    let history = PortfolioHistory::open(&path, Retention::default()).unwrap();
+    history.record_snapshot(snapshot(1, "1", 0.5));
+    history.record_predictions(wallet(), &strategy("STRK", 8.0));
+    history.record_snapshot(snapshot(0, "2", 0.5));
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+
+    let reopened = PortfolioHistory::open(&path, Retention::default()).unwrap();
+    assert_eq!(reopened.snapshots(wallet()), history.snapshots(wallet()));
+    let change = reopened.change(wallet(), Utc::now() - Duration::days(7)).unwrap();
+    assert_eq!(change.delta, 0.5);
+    assert_eq!(change.yields[0].predicted_apy, 8.0);
+
+    std::fs::write(&path, "{\"type\":\"snapshot\"}\n").unwrap();
+    assert!(PortfolioHistory::open(&path, Retention::default()).is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_history_past_retention_is_dropped_on_load_and_on_write() {
+    let path = std::env::temp_dir().join(format!("history-{}.jsonl", uuid::Uuid::new_v4()));
+    let history = PortfolioHistory::open(&path, Retention::default()).unwrap();
+    for days_ago in [40, 20, 3, 2] {
+        history.record_snapshot(snapshot(days_ago, "1", 0.5));
+    }
+    history.record_predictions(wallet(), &strategy("STRK", 8.0));
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 5);
+
+    // loading drops the snapshots older than a month and rewrites the file without them
+    let retention = Retention {
+        max_age: Duration::days(30),
+        max_snapshots: 3,
+    };
+    let reopened = PortfolioHistory::open(&path, retention).unwrap();
+    assert_eq!(reopened.snapshots(wallet()).len(), 3);
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 4);
+
+    // a fourth snapshot pushes the oldest one out, other wallets keep theirs
+    reopened.record_snapshot(snapshot(0, "2", 0.5));
+    reopened.record_snapshot(Snapshot {
+        wallet: "0x456".parse().unwrap(),
+        ..snapshot(0, "2", 0.5)
+    });
+    let kept: Vec<_> = reopened.snapshots(wallet()).iter().map(|snapshot| (Utc::now() - snapshot.taken_at).num_days()).collect();
+    assert_eq!(kept, [3, 2, 0]);
+    assert_eq!(reopened.snapshots("0x456".parse().unwrap()).len(), 1);
+    let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+    assert_eq!(lines, 5);
+    assert_eq!(PortfolioHistory::open(&path, retention).unwrap().snapshots(wallet()), reopened.snapshots(wallet()));
+    std::fs::remove_file(path).unwrap();
+}
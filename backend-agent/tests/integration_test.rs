@@ -1,23 +1,43 @@
-use backend_agent::market::CoinMarketData;
+mod common;
+
+use axum::{extract::RawQuery, routing::get, Json, Router};
+use backend_agent::amount::TokenAmount;
+use backend_agent::market::{CoinMarketData, MarketDataError};
 use backend_agent::math::{calculate_risk_score, STARKNET_TVL_ESTIMATE};
-use backend_agent::types::{ComputeError, StringContractAddress, Token};
-use std::f64::EPSILON;
+use backend_agent::prices::{PriceError, PriceFeed};
+use backend_agent::tokens::fetch_all_tokens;
+use backend_agent::token_registry::TokenRegistry;
+use backend_agent::address::ContractAddress;
+use backend_agent::agent_tools::portfolio::MAINNET_RPC_URL;
+use backend_agent::types::{PoolType, Price, Token};
+use common::FakeRpc;
+use serde_json::json;
+use starknet::core::types::Felt;
+use starknet::macros::selector;
+use starknet::providers::jsonrpc::{HttpTransport, JsonRpcClient};
+use starknet::providers::Url;
 
 const MOCK_PRICE: f64 = 1000.0;
 const MOCK_VOLUME: f64 = 50000.0;
 const MOCK_PRICE_CHANGE: f64 = 5.0;
 
+fn mainnet() -> JsonRpcClient<HttpTransport> {
+    JsonRpcClient::new(HttpTransport::new(Url::parse(MAINNET_RPC_URL).unwrap()))
+}
+
 fn setup_market_data() -> CoinMarketData {
     CoinMarketData {
         price: MOCK_PRICE,
-        reserve_a: 1000.0,
-        reserve_b: 1000.0,
+        a_name: "ETH".to_string(),
+        reserve_a: TokenAmount::parse("1000", 18).unwrap(),
+        reserve_b: TokenAmount::parse("1000", 6).unwrap(),
         volume_24h: MOCK_VOLUME,
         price_change_24h: MOCK_PRICE_CHANGE,
         liquidity: 0.0,
         tvl: STARKNET_TVL_ESTIMATE,
         apy: 0.0,
         risk_score: 0.0,
+        pool_type: PoolType::Stable,
     }
 }
 
@@ -28,11 +48,11 @@ fn test_market_data_metrics_calculation() {
 
     // Test liquidity calculation (2 * sqrt(reserve_a * reserve_b))
     let expected_liquidity = 2.0 * (1000.0 * 1000.0_f64).sqrt();
-    assert!((market_data.liquidity - expected_liquidity).abs() < EPSILON);
+    assert!((market_data.liquidity - expected_liquidity).abs() < f64::EPSILON);
 
     // Test TVL calculation
-    let expected_tvl = (market_data.reserve_a + market_data.reserve_b) * market_data.price;
-    assert!((market_data.tvl - expected_tvl).abs() < EPSILON);
+    let expected_tvl = (market_data.reserve_a.to_f64() + market_data.reserve_b.to_f64()) * market_data.price;
+    assert!((market_data.tvl - expected_tvl).abs() < f64::EPSILON);
 }
 
 #[test]
@@ -40,10 +60,10 @@ fn test_base_apy_calculation() {
     let mut market_data = setup_market_data();
     market_data.calculate_metrics().unwrap();
 
-    // Expected APY = (yearly_volume / tvl) * 100
+    // Expected APY = (yearly_volume / tvl) * fee_rate * 100, stable ETH pool: 0.10% fee, no amp
     let yearly_volume = MOCK_VOLUME * 365.0;
-    let expected_apy = (yearly_volume / market_data.tvl) * 100.0;
-    assert!((market_data.apy - expected_apy).abs() < EPSILON);
+    let expected_apy = (yearly_volume / market_data.tvl) * 0.001 * 100.0;
+    assert!((market_data.apy - expected_apy).abs() < f64::EPSILON);
 }
 
 #[test]
@@ -53,41 +73,51 @@ fn test_risk_score_calculation() {
     let price_change = 10.0;
 
     let risk_score = calculate_risk_score(tvl, volume, price_change);
-    assert!(risk_score >= 0.0 && risk_score <= 100.0);
+    assert!((0.0..=100.0).contains(&risk_score));
 }
 
 #[test]
 fn test_token_creation() {
     let token = Token {
         name: "TEST".to_string(),
-        address: StringContractAddress::from("0x123"),
-        price: 100.0,
+        address: "0x123".parse().unwrap(),
+        price: Price::from_f64(100.0, 6),
     };
 
     assert_eq!(token.name, "TEST");
-    assert_eq!(token.address.0, "0x123");
-    assert!((token.price - 100.0).abs() < EPSILON);
+    assert_eq!(token.address.to_string(), format!("0x{:0>64}", "123"));
+    assert!((token.price.to_f64() - 100.0).abs() < f64::EPSILON);
 }
 
 #[test]
-fn test_string_contract_address() {
-    let address = StringContractAddress::from("0xabc");
-    assert_eq!(address.0, "0xabc");
+fn test_contract_address() {
+    let address: ContractAddress = "0xABC".parse().unwrap();
+    assert_eq!(address, "0x0abc".parse().unwrap());
+    assert_eq!(address.to_string(), format!("0x{:0>64}", "abc"));
 }
 
 #[test]
 fn test_market_data_from_gecko() {
     tokio_test::block_on(async {
         let market_data =
-            CoinMarketData::from_gecko_data("ETH", MOCK_PRICE, MOCK_VOLUME, MOCK_PRICE_CHANGE)
-                .await;
+            CoinMarketData::from_gecko_data(
+                &mainnet(),
+                &TokenRegistry::load("tokens.json").unwrap(),
+                "ETH",
+                MOCK_PRICE,
+                MOCK_VOLUME,
+                MOCK_PRICE_CHANGE,
+                PoolType::Stable,
+            )
+                .await
+                .unwrap();
 
-        assert!((market_data.price - MOCK_PRICE).abs() < EPSILON);
-        assert!((market_data.volume_24h - MOCK_VOLUME).abs() < EPSILON);
-        assert!((market_data.price_change_24h - MOCK_PRICE_CHANGE).abs() < EPSILON);
+        assert!((market_data.price - MOCK_PRICE).abs() < f64::EPSILON);
+        assert!((market_data.volume_24h - MOCK_VOLUME).abs() < f64::EPSILON);
+        assert!((market_data.price_change_24h - MOCK_PRICE_CHANGE).abs() < f64::EPSILON);
         assert!(market_data.tvl > 0.0);
         assert!(market_data.apy >= 0.0);
-        assert!(market_data.risk_score >= 0.0 && market_data.risk_score <= 100.0);
+        assert!((0.0..=100.0).contains(&market_data.risk_score));
     });
 }
 
@@ -98,21 +128,23 @@ fn test_extreme_values() {
     let high_price_change = 50.0;
 
     let risk_score = calculate_risk_score(high_tvl, low_volume, high_price_change);
-    assert!(risk_score >= 0.0 && risk_score <= 100.0);
+    assert!((0.0..=100.0).contains(&risk_score));
 }
 
 #[test]
 fn test_market_data_with_zero_values() {
     let mut market_data = CoinMarketData {
         price: 0.0,
-        reserve_a: 0.0,
-        reserve_b: 0.0,
+        a_name: String::new(),
+        reserve_a: TokenAmount::zero(18),
+        reserve_b: TokenAmount::zero(6),
         volume_24h: 0.0,
         price_change_24h: 0.0,
         liquidity: 0.0,
         tvl: 0.0,
         apy: 0.0,
         risk_score: 0.0,
+        pool_type: PoolType::Stable,
     };
 
     assert!(market_data.calculate_metrics().is_err());
@@ -158,8 +190,8 @@ fn test_market_data_with_negative_values() {
 fn test_token_default() {
     let default_token = Token::default();
     assert_eq!(default_token.name, "");
-    assert_eq!(default_token.address.0, "");
-    assert_eq!(default_token.price, 0.0);
+    assert_eq!(default_token.address, ContractAddress::default());
+    assert_eq!(default_token.price, Price::default());
 }
 
 #[test]
@@ -208,16 +240,84 @@ fn test_risk_score_consistency() {
 #[test]
 fn test_from_gecko_data_with_different_tokens() {
     tokio_test::block_on(async {
+        let registry = TokenRegistry::load("tokens.json").unwrap();
         let tokens = ["ETH", "STRK", "BROTHER"];
         for token in tokens.iter() {
             let market_data =
-                CoinMarketData::from_gecko_data(token, MOCK_PRICE, MOCK_VOLUME, MOCK_PRICE_CHANGE)
-                    .await;
+                CoinMarketData::from_gecko_data(
+                    &mainnet(),
+                    &registry,
+                    token,
+                    MOCK_PRICE,
+                    MOCK_VOLUME,
+                    MOCK_PRICE_CHANGE,
+                    PoolType::Degen,
+                )
+                    .await
+                    .unwrap();
             assert_eq!(market_data.price, MOCK_PRICE);
             assert_eq!(market_data.volume_24h, MOCK_VOLUME);
             assert_eq!(market_data.price_change_24h, MOCK_PRICE_CHANGE);
-            assert!(market_data.reserve_a > 0.0);
-            assert!(market_data.reserve_b > 0.0);
+            assert!(!market_data.reserve_a.is_zero());
+            assert!(!market_data.reserve_b.is_zero());
         }
     });
 }
+
+#[test]
+fn test_from_gecko_data_with_unknown_token_fails() {
+    tokio_test::block_on(async {
+        let result = CoinMarketData::from_gecko_data(
+            &mainnet(),
+            &TokenRegistry::load("tokens.json").unwrap(),
+            "NOPE",
+            MOCK_PRICE,
+            MOCK_VOLUME,
+            MOCK_PRICE_CHANGE,
+            PoolType::Degen,
+        )
+        .await;
+        assert!(matches!(result, Err(MarketDataError::UnknownToken(symbol)) if symbol == "NOPE"));
+    });
+}
+
+#[tokio::test]
+async fn test_all_tokens_are_fetched_from_the_configured_coingecko_and_node() {
+    let registry = TokenRegistry::load("tokens.json").unwrap();
+    let (strk, usdc) = (registry.by_symbol("STRK").unwrap(), registry.by_symbol("USDC").unwrap());
+    let api = Router::new().route(
+        "/simple/token_price/starknet",
+        get(|RawQuery(query): RawQuery| async move {
+            assert!(query.unwrap_or_default().contains("include_24hr_vol=true"));
+            // CoinGecko drops the leading zeros, ETH and BROTHER aren't listed
+            Json(json!({
+                "0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d":
+                    {"usd": 0.5, "usd_24h_vol": 1_000_000.0, "usd_24h_change": -2.0}
+            }))
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let api_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, api).await.unwrap() });
+    let rpc = FakeRpc::default()
+        .with_result(strk.address.felt(), selector!("total_supply"), vec![Felt::from(10u128.pow(27)), Felt::ZERO])
+        .with_result(usdc.address.felt(), selector!("total_supply"), vec![Felt::from(10u128.pow(14)), Felt::ZERO]);
+    let rpc_url = rpc.serve().await;
+
+    let prices = PriceFeed::default().with_coingecko(&api_url, "key");
+    let (tokens, market_data) = fetch_all_tokens(&registry, &prices, &rpc_url).await.unwrap();
+
+    let pools: Vec<_> = market_data.iter().map(|market| (market.a_name.as_str(), market.pool_type.clone())).collect();
+    assert_eq!(pools, [("STRK", PoolType::Stable), ("STRK", PoolType::Degen)]);
+    assert!(tokens.iter().all(|token| token.address == strk.address && token.price.to_f64() == 0.5));
+    assert_eq!(market_data[0].volume_24h, 1_000_000.0);
+    assert_eq!(market_data[0].price_change_24h, -2.0);
+    assert_eq!(market_data[0].reserve_b.to_string(), "100000000");
+}
+
+#[tokio::test]
+async fn test_all_tokens_fail_without_coingecko() {
+    let registry = TokenRegistry::load("tokens.json").unwrap();
+    let result = fetch_all_tokens(&registry, &PriceFeed::default(), &Url::parse(MAINNET_RPC_URL).unwrap()).await;
+    assert!(matches!(result, Err(PriceError::NotConfigured)));
+}
@@ -0,0 +1,76 @@
+use backend_agent::agents::turn::{self, TurnMetadata};
+use backend_agent::knowledge::{chunk_markdown, KnowledgeIndex};
+use backend_agent::llm::mock::HashEmbeddingModel;
+use backend_agent::sources::{CitedIndex, Source};
+use backend_agent::types::DefiKnowledge;
+use rig::vector_store::VectorStoreIndex;
+use std::path::PathBuf;
+
+fn temp_knowledge_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("knowledge-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_docs_are_chunked_by_heading() {
+    let chunks = chunk_markdown(
+        "nostra-lp",
+        &std::fs::read_to_string("knowledge/nostra-lp.md").unwrap(),
+    );
+
+    assert_eq!(chunks.len(), 9);
+    assert!(chunks.iter().all(|chunk| chunk.protocol == "Nostra Finance"));
+    let risks = chunks.iter().find(|chunk| chunk.section == "Risks and Considerations").unwrap();
+    assert_eq!(risks.id, "nostra-lp#risks-and-considerations");
+    assert!(risks.content.starts_with("- Smart contract risk"));
+    assert!(!risks.content.contains("Performance Metrics"));
+}
+
+#[tokio::test]
+async fn test_index_retrieves_sections_and_follows_file_changes() {
+    let dir = temp_knowledge_dir();
+    std::fs::write(
+        dir.join("ekubo-lp.md"),
+        "# Ekubo (website: ekubo.org)\n\n## Concentrated liquidity\nPositions earn fees inside a price range.\n\n## Fees\nSwap fees depend on the pool tier.\n",
+    )
+    .unwrap();
+    let index = KnowledgeIndex::load(&dir, HashEmbeddingModel::default()).await.unwrap();
+    assert_eq!(index.chunks().len(), 2);
+
+    let results: Vec<(f64, String, DefiKnowledge)> = index.top_n("how are swap fees set on Ekubo?", 1).await.unwrap();
+    assert_eq!(results[0].1, "ekubo-lp#fees");
+    assert_eq!(results[0].2.protocol, "Ekubo");
+    assert_eq!(results[0].2.section, "Fees");
+
+    // unchanged files aren't embedded again
+    assert_eq!(index.reindex().await.unwrap(), 0);
+
+    std::fs::write(dir.join("vesu-lp.md"), "# Vesu\n\n## Lending\nPermissionless lending pools.\n").unwrap();
+    std::fs::remove_file(dir.join("ekubo-lp.md")).unwrap();
+    assert_eq!(index.reindex().await.unwrap(), 2);
+    let ids: Vec<String> = index.chunks().into_iter().map(|chunk| chunk.id).collect();
+    assert_eq!(ids, ["vesu-lp#lending"]);
+}
+
+#[tokio::test]
+async fn test_retrieved_chunks_are_cited() {
+    let dir = temp_knowledge_dir();
+    std::fs::write(dir.join("vesu-lp.md"), "# Vesu\n\n## Lending\nPermissionless lending pools.\n").unwrap();
+    let index = CitedIndex::new(
+        KnowledgeIndex::load(&dir, HashEmbeddingModel::default()).await.unwrap(),
+        Source::knowledge,
+    );
+
+    let (results, metadata) = turn::scope(TurnMetadata::new("session"), index.top_n::<DefiKnowledge>("lending", 1)).await;
+    // the model sees the source id in place of the chunk id
+    assert_eq!(results.unwrap()[0].1, "kb:vesu-lp#lending");
+    assert_eq!(
+        metadata.sources,
+        [Source::Knowledge {
+            id: "kb:vesu-lp#lending".to_string(),
+            protocol: "Vesu".to_string(),
+            section: "Lending".to_string(),
+        }]
+    );
+}
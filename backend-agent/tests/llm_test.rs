@@ -0,0 +1,43 @@
+use backend_agent::llm::{LlmConfigError, ModelProfile, Provider};
+
+#[test]
+fn test_provider_parsing() {
+    assert_eq!("openai".parse::<Provider>().unwrap(), Provider::OpenAi);
+    assert_eq!("Anthropic".parse::<Provider>().unwrap(), Provider::Anthropic);
+    assert_eq!("ollama".parse::<Provider>().unwrap(), Provider::OpenAiCompatible);
+    assert!(matches!(
+        "mistery-ai".parse::<Provider>(),
+        Err(LlmConfigError::UnknownProvider(_))
+    ));
+}
+
+#[test]
+fn test_model_profile_from_env() {
+    std::env::set_var("TESTNAV_LLM_PROVIDER", "openai-compatible");
+    std::env::set_var("TESTNAV_LLM_MODEL", "llama3.1");
+    std::env::set_var("TESTNAV_LLM_BASE_URL", "http://localhost:11434/v1");
+    std::env::set_var("TESTNAV_LLM_MAX_TOKENS", "256");
+
+    let profile = ModelProfile::from_env(
+        "TESTNAV",
+        ModelProfile::new(Provider::OpenAi, "gpt-4o-mini").temperature(0.3),
+    )
+    .unwrap();
+
+    assert_eq!(profile.provider, Provider::OpenAiCompatible);
+    assert_eq!(profile.model, "llama3.1");
+    assert_eq!(profile.base_url.as_deref(), Some("http://localhost:11434/v1"));
+    assert_eq!(profile.max_tokens, Some(256));
+    // unset variables keep the defaults
+    assert_eq!(profile.temperature, Some(0.3));
+    assert_eq!(profile.id(), "openai-compatible/llama3.1");
+    // local servers don't need a key
+    assert!(profile.completion_model().is_ok());
+}
+
+#[test]
+fn test_model_profile_invalid_number() {
+    std::env::set_var("TESTBAD_LLM_TEMPERATURE", "hot");
+    let profile = ModelProfile::from_env("TESTBAD", ModelProfile::new(Provider::OpenAi, "gpt-4o-mini"));
+    assert!(matches!(profile, Err(LlmConfigError::InvalidValue(_, _))));
+}
@@ -0,0 +1,55 @@
+use backend_agent::agents::navigator::{classify_intent, infer_expertise, infer_risk_profile, Intent};
+use backend_agent::types::{Expertise, RiskProfile};
+
+#[test]
+fn test_classify_greetings() {
+    assert_eq!(classify_intent("hello"), Intent::Greeting);
+    assert_eq!(classify_intent("gm brother!"), Intent::Greeting);
+    assert_eq!(classify_intent("  Hey  "), Intent::Greeting);
+}
+
+#[test]
+fn test_classify_portfolio_requests() {
+    assert_eq!(
+        classify_intent("can you check 0x049d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7"),
+        Intent::PortfolioRequest
+    );
+    assert_eq!(classify_intent("analyze my portfolio"), Intent::PortfolioRequest);
+}
+
+#[test]
+fn test_classify_yield_and_strategy() {
+    assert_eq!(classify_intent("what's the APY on STRK?"), Intent::YieldQuestion);
+    assert_eq!(
+        classify_intent("where should I put my STRK for the best yield?"),
+        Intent::StrategyRequest
+    );
+}
+
+#[test]
+fn test_classify_education() {
+    assert_eq!(classify_intent("what is impermanent loss?"), Intent::ProtocolEducation);
+    assert_eq!(classify_intent("tell me about Ekubo"), Intent::ProtocolEducation);
+}
+
+#[test]
+fn test_classify_off_topic_and_fallback() {
+    assert_eq!(classify_intent("write me a poem about cats"), Intent::OffTopic);
+    // DeFi vocabulary wins over off-topic cues
+    assert_eq!(classify_intent("tell me a joke about starknet gas"), Intent::Unclassified);
+    assert_eq!(classify_intent("hmm not sure"), Intent::Unclassified);
+}
+
+#[test]
+fn test_risk_profile_inference() {
+    assert_eq!(infer_risk_profile("what's the safest way to earn on USDC?"), Some(RiskProfile::Conservative));
+    assert_eq!(infer_risk_profile("give me a degen play"), Some(RiskProfile::Aggressive));
+    assert_eq!(infer_risk_profile("best yield for STRK?"), None);
+}
+
+#[test]
+fn test_expertise_inference() {
+    assert_eq!(infer_expertise("I'm new to DeFi, what is staking?"), Some(Expertise::Beginner));
+    assert_eq!(infer_expertise("which tick range for concentrated liquidity on Ekubo?"), Some(Expertise::Pro));
+    assert_eq!(infer_expertise("best yield for STRK?"), None);
+}
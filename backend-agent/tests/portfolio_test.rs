@@ -0,0 +1,155 @@
+mod common;
+
+use backend_agent::address::ContractAddress;
+use backend_agent::agent_tools::portfolio::{fetch_balances, PortfolioFetch};
+use backend_agent::agents::turn::{self, TurnMetadata};
+use backend_agent::amount::TokenAmount;
+use backend_agent::positions::PositionAdapters;
+use backend_agent::prices::PriceFeed;
+use backend_agent::starknet_id::{StarknetId, NAMING_CONTRACT};
+use backend_agent::token_registry::TokenRegistry;
+use backend_agent::types::{AggregatedPortfolio, Portfolio, Token, TokenCategory};
+use common::FakeRpc;
+use rig::tool::Tool;
+use std::collections::HashMap;
+use starknet::macros::felt;
+use starknet::providers::jsonrpc::HttpTransport;
+
+const STRK: starknet::core::types::Felt = felt!("0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d");
+const USDC: starknet::core::types::Felt = felt!("0x053c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8");
+const ETH: starknet::core::types::Felt = felt!("0x049d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7");
+
+#[tokio::test]
+async fn test_balances_are_batched_and_failures_are_per_token() {
+    let rpc = FakeRpc::default()
+        .with_balance(STRK, 2_500_000_000_000_000_000)
+        .with_balance(USDC, 12_000_000)
+        .with_failure(ETH);
+    let transport = HttpTransport::new(rpc.serve().await);
+    let tokens = TokenRegistry::load("tokens.json").unwrap().verified();
+
+    let fetch = fetch_balances(&transport, address(felt!("0x123")), &tokens).await;
+
+    assert_eq!(*rpc.round_trips.lock(), 1);
+    assert_eq!(rpc.calls.lock().len(), tokens.len());
+    let balance = |symbol: &str| {
+        fetch
+            .balances
+            .iter()
+            .find(|(token, _)| token.name == symbol)
+            .map(|(_, amount)| amount.to_string())
+    };
+    assert_eq!(balance("STRK").as_deref(), Some("2.5"));
+    assert_eq!(balance("USDC").as_deref(), Some("12"));
+    assert_eq!(fetch.balances.len(), 2);
+    assert_eq!(fetch.errors.len(), 1);
+    assert_eq!(fetch.errors[0].token, "ETH");
+    assert!(fetch.errors[0].error.contains("Contract error"));
+}
+
+#[tokio::test]
+async fn test_unreachable_rpc_fails_every_token() {
+    let transport = HttpTransport::new(url::Url::parse("http://127.0.0.1:9/").unwrap());
+    let tokens = TokenRegistry::load("tokens.json").unwrap().verified();
+
+    let fetch = fetch_balances(&transport, address(felt!("0x123")), &tokens).await;
+
+    assert!(fetch.balances.is_empty());
+    assert_eq!(fetch.errors.len(), tokens.len());
+}
+
+fn address(felt: starknet::core::types::Felt) -> ContractAddress {
+    ContractAddress::new(felt).unwrap()
+}
+
+fn token(symbol: &str, felt: starknet::core::types::Felt) -> Token {
+    Token {
+        name: symbol.to_string(),
+        address: address(felt),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_portfolio_is_valued_and_split_by_category() {
+    let wsteth = felt!("0x042b8f0484674ca266ac5d08e4ac6a3fe65bd3129795def2dca5c34ecc5f96d2");
+    let balances = HashMap::from([
+        (token("STRK", STRK), TokenAmount::parse("2.5", 18).unwrap()),
+        (token("ETH", ETH), TokenAmount::parse("0.001", 18).unwrap()),
+        (token("wstETH", wsteth), TokenAmount::parse("0.0005", 18).unwrap()),
+        (token("USDC", USDC), TokenAmount::parse("12", 6).unwrap()),
+    ]);
+    let prices = HashMap::from([(address(STRK), 0.5), (address(ETH), 2000.0), (address(wsteth), 2500.0)]);
+
+    let registry = TokenRegistry::load("tokens.json").unwrap();
+
+    let portfolio = Portfolio::from_balances(address(felt!("0x123")), &balances, &prices, &registry);
+
+    assert_eq!(portfolio.total_value, 4.5);
+    let symbols: Vec<_> = portfolio.assets.iter().map(|asset| asset.symbol.as_str()).collect();
+    assert_eq!(symbols, ["ETH", "STRK", "wstETH", "USDC"]);
+    assert_eq!(portfolio.assets[1].balance, Some(1.25));
+    assert_eq!(portfolio.categories[&TokenCategory::Volatile].value, 3.25);
+    assert_eq!(portfolio.categories[&TokenCategory::LiquidStaking].value, 1.25);
+    assert!(!portfolio.categories.contains_key(&TokenCategory::Stablecoin));
+    // USDC has no price: listed, but out of the total
+    assert_eq!(portfolio.unpriced, ["USDC"]);
+    assert_eq!(portfolio.assets[3].category, TokenCategory::Stablecoin);
+    assert_eq!(portfolio.assets[3].allocation, None);
+    let allocated: f64 = portfolio.assets.iter().filter_map(|asset| asset.allocation).sum();
+    assert!((allocated - 100.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_portfolios_are_combined_per_token() {
+    let registry = TokenRegistry::load("tokens.json").unwrap();
+    let prices = HashMap::from([(address(STRK), 0.5), (address(ETH), 2000.0)]);
+    let hot = HashMap::from([(token("STRK", STRK), TokenAmount::parse("10", 18).unwrap())]);
+    let smart = HashMap::from([
+        (token("STRK", STRK), TokenAmount::parse("2", 18).unwrap()),
+        (token("ETH", ETH), TokenAmount::parse("0.001", 18).unwrap()),
+        (token("USDC", USDC), TokenAmount::parse("3", 6).unwrap()),
+    ]);
+    let portfolios = [
+        Portfolio::from_balances(address(felt!("0x1")), &hot, &prices, &registry),
+        Portfolio::from_balances(address(felt!("0x2")), &smart, &prices, &registry),
+    ];
+
+    let aggregated = AggregatedPortfolio::new(&portfolios).unwrap();
+
+    let combined = &aggregated.combined;
+    assert_eq!(combined.wallet_address, address(felt!("0x1")));
+    assert_eq!(combined.total_value, 8.0);
+    let symbols: Vec<_> = combined.assets.iter().map(|asset| asset.symbol.as_str()).collect();
+    assert_eq!(symbols, ["STRK", "ETH", "USDC"]);
+    assert_eq!(combined.assets[0].amount, TokenAmount::parse("12", 18).unwrap());
+    assert_eq!(combined.assets[0].allocation, Some(75.0));
+    assert_eq!(combined.categories[&TokenCategory::Volatile].value, 8.0);
+    assert_eq!(combined.unpriced, ["USDC"]);
+    let shares: Vec<_> = aggregated.wallets.iter().map(|wallet| (wallet.value, wallet.allocation)).collect();
+    assert_eq!(shares, [(5.0, 62.5), (3.0, 37.5)]);
+    assert!(AggregatedPortfolio::new(&[]).is_none());
+}
+
+#[tokio::test]
+async fn test_no_tokens_to_query_fails_without_panicking() {
+    let rpc_url = FakeRpc::default().serve().await;
+    let app = common::test_app(vec![], rpc_url.clone()).await;
+    let tool = PortfolioFetch {
+        appstate: app.backend.app_state.clone(),
+        rpc_url: rpc_url.clone(),
+        prices: PriceFeed::default(),
+        tokens: TokenRegistry::default(),
+        positions: PositionAdapters::default(),
+        starknet_id: StarknetId::new(rpc_url, NAMING_CONTRACT),
+    };
+    let metadata = TurnMetadata {
+        wallet: Some(address(felt!("0x123"))),
+        ..TurnMetadata::new("session")
+    };
+
+    let args = serde_json::from_value(serde_json::json!({})).unwrap();
+    let (result, _) = turn::scope(metadata, tool.call(args)).await;
+
+    assert!(result.unwrap_err().to_string().contains("no tokens queried"));
+}
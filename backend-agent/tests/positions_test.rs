@@ -0,0 +1,172 @@
+mod common;
+
+use axum::{routing::get, Json, Router};
+use backend_agent::address::ContractAddress;
+use backend_agent::amount::TokenAmount;
+use backend_agent::positions::{AdapterSpec, LendingSide, PositionAdapters, PositionKind};
+use backend_agent::token_registry::TokenRegistry;
+use backend_agent::positions::{Position, PositionAmount};
+use backend_agent::types::{Portfolio, TokenCategory};
+use common::FakeRpc;
+use serde_json::json;
+use starknet::core::types::Felt;
+use starknet::macros::{felt, selector};
+use starknet::providers::jsonrpc::{HttpTransport, JsonRpcClient};
+use std::collections::HashMap;
+
+const STRK: Felt = felt!("0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d");
+const USDC: Felt = felt!("0x053c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8");
+const ETH: Felt = felt!("0x049d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7");
+const WALLET: Felt = felt!("0x123");
+const ONE: u128 = 1_000_000_000_000_000_000;
+
+fn address(felt: Felt) -> ContractAddress {
+    ContractAddress::new(felt).unwrap()
+}
+
+fn u256(value: u128) -> Vec<Felt> {
+    vec![Felt::from(value), Felt::ZERO]
+}
+
+#[tokio::test]
+async fn test_lending_vault_and_lp_positions_are_valued_in_underlying() {
+    let (n_usdc, d_eth, vault, pair) = (felt!("0xa1"), felt!("0xa2"), felt!("0xa3"), felt!("0xa4"));
+    let rpc = FakeRpc::default()
+        .with_balance(n_usdc, 250_000_000)
+        .with_balance(d_eth, ONE / 10)
+        .with_balance(vault, 10 * ONE)
+        .with_result(vault, selector!("asset"), vec![STRK])
+        .with_result(vault, selector!("convert_to_assets"), u256(11 * ONE))
+        .with_balance(pair, 10)
+        .with_result(pair, selector!("totalSupply"), u256(100))
+        .with_result(pair, selector!("token0"), vec![ETH])
+        .with_result(pair, selector!("token1"), vec![USDC])
+        .with_result(pair, selector!("get_reserves"), vec![Felt::from(20 * ONE), Felt::ZERO, Felt::from(60_000_000_000u128), Felt::ZERO, Felt::ONE]);
+    let provider = JsonRpcClient::new(HttpTransport::new(rpc.serve().await));
+    let tokens = TokenRegistry::load("tokens.json").unwrap();
+    let adapters = PositionAdapters::new(vec![
+        AdapterSpec::Lending { protocol: "Nostra".to_string(), address: address(n_usdc), underlying: address(USDC), side: LendingSide::Supply },
+        AdapterSpec::Lending { protocol: "Nostra".to_string(), address: address(d_eth), underlying: address(ETH), side: LendingSide::Borrow },
+        AdapterSpec::Vault { protocol: "vSTRK".to_string(), address: address(vault) },
+        AdapterSpec::LpPair { protocol: "JediSwap".to_string(), address: address(pair) },
+        // no balance, no position
+        AdapterSpec::Lending { protocol: "Nostra".to_string(), address: address(felt!("0xa5")), underlying: address(STRK), side: LendingSide::Supply },
+    ]);
+
+    let fetch = adapters.fetch(&provider, &tokens, address(WALLET)).await;
+
+    assert!(fetch.errors.is_empty(), "{:?}", fetch.errors);
+    let summary: Vec<_> = fetch
+        .positions
+        .iter()
+        .map(|position| {
+            let parts: Vec<_> = position.underlying.iter().map(|part| format!("{} {}", part.amount, part.symbol)).collect();
+            (position.label(), parts.join(" + "))
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("Nostra supply".to_string(), "250 USDC".to_string()),
+            ("Nostra borrow".to_string(), "0.1 ETH".to_string()),
+            ("vSTRK staking".to_string(), "11 STRK".to_string()),
+            ("JediSwap liquidity".to_string(), "2 ETH + 6000 USDC".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_ekubo_positions_come_with_unclaimed_fees() {
+    let api = Router::new().route(
+        "/positions/{owner}",
+        get(|| async {
+            Json(json!({"data": [{
+                "id": "42",
+                "pool_key": {"token0": STRK.to_hex_string(), "token1": USDC.to_hex_string(), "fee": "0x20c49ba5e353f80000000000000000", "tick_spacing": 1000, "extension": "0x0"},
+                "bounds": {"lower": -1000, "upper": 2000}
+            }]}))
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let api_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, api).await.unwrap() });
+    let positions_contract = felt!("0xe1");
+    let info = [0u128, 0, 5, 1, 777, 3 * ONE, 1_500_000, ONE / 2, 0].map(Felt::from).to_vec();
+    let rpc = FakeRpc::default().with_result(positions_contract, selector!("get_token_info"), info);
+    let provider = JsonRpcClient::new(HttpTransport::new(rpc.serve().await));
+    let adapters = PositionAdapters::new(vec![AdapterSpec::Ekubo { positions: address(positions_contract), api_url }]);
+
+    let fetch = adapters.fetch(&provider, &TokenRegistry::load("tokens.json").unwrap(), address(WALLET)).await;
+
+    assert!(fetch.errors.is_empty(), "{:?}", fetch.errors);
+    let position = &fetch.positions[0];
+    assert_eq!(position.label(), "Ekubo liquidity #42");
+    assert_eq!(position.underlying[0].amount.to_string(), "3");
+    assert_eq!(position.underlying[1].amount.to_string(), "1.5");
+    assert_eq!(position.rewards.len(), 1);
+    assert_eq!(format!("{} {}", position.rewards[0].amount, position.rewards[0].symbol), "0.5 STRK");
+    // id, pool key and both bounds as i129
+    let call = rpc.calls.lock()[0]["params"].clone();
+    let request = if call.is_array() { &call[0] } else { &call["request"] };
+    assert_eq!(request["calldata"].as_array().unwrap().len(), 10);
+}
+
+#[tokio::test]
+async fn test_failing_adapter_only_fails_its_protocol() {
+    let rpc = FakeRpc::default().with_failure(felt!("0xa1"));
+    let provider = JsonRpcClient::new(HttpTransport::new(rpc.serve().await));
+    let adapters = PositionAdapters::new(vec![
+        AdapterSpec::Vault { protocol: "vSTRK".to_string(), address: address(felt!("0xa1")) },
+        AdapterSpec::Lending { protocol: "Nostra".to_string(), address: address(felt!("0xa2")), underlying: address(USDC), side: LendingSide::Supply },
+    ]);
+
+    let fetch = adapters.fetch(&provider, &TokenRegistry::load("tokens.json").unwrap(), address(WALLET)).await;
+
+    assert!(fetch.positions.is_empty());
+    assert_eq!(fetch.errors.len(), 1);
+    assert_eq!(fetch.errors[0].token, "vSTRK");
+}
+
+#[test]
+fn test_positions_replace_receipt_balances_and_debts_count_negative() {
+    let tokens = TokenRegistry::load("tokens.json").unwrap();
+    let vstrk = tokens.by_symbol("vSTRK").unwrap();
+    let balances = HashMap::from([
+        (tokens.by_symbol("STRK").unwrap().token(), TokenAmount::parse("10", 18).unwrap()),
+        (vstrk.token(), TokenAmount::parse("10", 18).unwrap()),
+    ]);
+    let prices = HashMap::from([(address(STRK), 0.5), (address(ETH), 2000.0), (address(USDC), 1.0)]);
+    let part = |symbol: &str, amount: &str| {
+        let token = tokens.by_symbol(symbol).unwrap();
+        PositionAmount::new(&token, TokenAmount::parse(amount, token.decimals).unwrap())
+    };
+    let position = |protocol: &str, kind, contract: ContractAddress, underlying| Position {
+        protocol: protocol.to_string(),
+        kind,
+        contract,
+        id: None,
+        underlying,
+        rewards: vec![],
+    };
+    let positions = vec![
+        position("vSTRK", PositionKind::Staking, vstrk.address, vec![part("STRK", "11")]),
+        position("Nostra", PositionKind::Supply, address(felt!("0xa1")), vec![part("USDC", "100")]),
+        // rewards accrued on a debt are still owned
+        Position {
+            rewards: vec![part("STRK", "2")],
+            ..position("Nostra", PositionKind::Borrow, address(felt!("0xa2")), vec![part("ETH", "0.01")])
+        },
+    ];
+
+    let portfolio = Portfolio::from_balances(address(WALLET), &balances, &prices, &tokens).with_positions(positions, &prices, &tokens);
+
+    // the vSTRK balance is the staking position, not a second holding
+    let symbols: Vec<_> = portfolio.assets.iter().map(|asset| asset.symbol.as_str()).collect();
+    assert_eq!(symbols, ["STRK"]);
+    assert_eq!(portfolio.total_value, 5.0 + 5.5 + 100.0 - 20.0 + 1.0);
+    let values: Vec<_> = portfolio.positions.iter().map(|holding| holding.value.unwrap()).collect();
+    assert_eq!(values, [100.0, 5.5, -19.0]);
+    assert_eq!(portfolio.categories[&TokenCategory::Stablecoin].value, 100.0);
+    assert_eq!(portfolio.categories[&TokenCategory::Volatile].value, 5.0 + 5.5 - 20.0 + 1.0);
+    assert!(portfolio.unpriced.is_empty());
+}
@@ -0,0 +1,96 @@
+use backend_agent::prompts::{PromptLibrary, PromptTemplate, PromptVars, TemplateError};
+use backend_agent::types::RiskProfile;
+use chrono::{TimeZone, Utc};
+use std::path::PathBuf;
+
+fn temp_prompts_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("prompts-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(dir.join("expert")).unwrap();
+    dir
+}
+
+#[test]
+fn test_template_renders_typed_variables() {
+    let template = PromptTemplate::parse(
+        "expert",
+        "v1",
+        "Date {{date}}, tokens {{ supported_tokens }}, {{risk_profile}} user, yields as of {{yields_as_of}}",
+    )
+    .unwrap();
+    let vars = PromptVars {
+        date: Utc.with_ymd_and_hms(2025, 1, 15, 10, 0, 0).unwrap(),
+        supported_tokens: vec!["STRK".to_string(), "ETH".to_string()],
+        risk_profile: RiskProfile::Conservative,
+        yields_as_of: Some(Utc.with_ymd_and_hms(2025, 1, 15, 9, 30, 0).unwrap()),
+        ..Default::default()
+    };
+
+    assert_eq!(template.id(), "expert/v1");
+    assert_eq!(
+        template.render(&vars),
+        "Date 2025-01-15, tokens STRK, ETH, conservative user, yields as of 2025-01-15 09:30 UTC"
+    );
+}
+
+#[test]
+fn test_unknown_variable_is_rejected() {
+    let error = PromptTemplate::parse("expert", "v1", "Hello {{username}}").unwrap_err();
+    assert!(matches!(error, TemplateError::UnknownVariable(name, _) if name == "username"));
+    assert!(PromptTemplate::parse("expert", "v1", "Hello {{date").is_err());
+}
+
+#[test]
+fn test_library_selects_latest_pinned_and_reloads() {
+    let dir = temp_prompts_dir();
+    std::fs::write(dir.join("expert/v1.md"), "first {{date}}").unwrap();
+    let library = PromptLibrary::load(&dir).unwrap();
+    assert_eq!(library.select("expert", "s").unwrap().version, "v1");
+    assert!(library.select("navigator", "s").is_err());
+
+    // new versions are picked up without restarting, broken ones are skipped
+    std::fs::write(dir.join("expert/v2.md"), "second").unwrap();
+    std::fs::write(dir.join("expert/v3.md"), "broken {{nope}}").unwrap();
+    library.reload().unwrap();
+    assert_eq!(library.select("expert", "s").unwrap().version, "v2");
+
+    // versions are ordered by number
+    std::fs::write(dir.join("expert/v10.md"), "tenth").unwrap();
+    library.reload().unwrap();
+    assert_eq!(library.select("expert", "s").unwrap().version, "v10");
+
+    library.pin("expert", Some("v1"));
+    assert_eq!(library.select("expert", "s").unwrap().version, "v1");
+    library.pin("expert", None);
+    assert_eq!(library.versions()["expert"], ["v1", "v2", "v10"]);
+}
+
+#[tokio::test]
+async fn test_watch_reloads_in_the_background() {
+    let dir = temp_prompts_dir();
+    std::fs::write(dir.join("expert/v1.md"), "first").unwrap();
+    let library = PromptLibrary::load(&dir).unwrap();
+    let watch = library.watch(std::time::Duration::from_millis(20));
+
+    std::fs::write(dir.join("expert/v2.md"), "second").unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    watch.abort();
+    assert_eq!(library.select("expert", "s").unwrap().version, "v2");
+}
+
+#[test]
+fn test_experiment_assigns_sessions_to_stable_variants() {
+    let dir = temp_prompts_dir();
+    std::fs::write(dir.join("expert/v1.md"), "first").unwrap();
+    std::fs::write(dir.join("expert/v2.md"), "second").unwrap();
+    std::fs::write(dir.join("experiments.json"), r#"{"expert": {"v1": 50, "v2": 50}}"#).unwrap();
+    let library = PromptLibrary::load(&dir).unwrap();
+
+    let versions: Vec<String> = (0..50)
+        .map(|i| library.select("expert", &format!("session-{i}")).unwrap().version)
+        .collect();
+    assert!(versions.iter().any(|v| v == "v1"));
+    assert!(versions.iter().any(|v| v == "v2"));
+    for (i, version) in versions.iter().enumerate() {
+        assert_eq!(&library.select("expert", &format!("session-{i}")).unwrap().version, version);
+    }
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use backend_agent::agents::navigator::Intent;
+use backend_agent::agents::registry::{AgentRegistry, AgentSpec};
+use common::test_app_with_registry;
+
+#[test]
+fn test_registry_routes_intents_and_describes_specialists() {
+    let registry = AgentRegistry::load("agents.json").unwrap();
+
+    assert_eq!(registry.specialist_for(Intent::StrategyRequest).unwrap().name, "defiproman");
+    assert!(registry.specialist_for(Intent::Greeting).is_err());
+    let manifest = registry.manifest();
+    assert!(manifest.contains("## defiproman"));
+    assert!(manifest.contains("mainnet_fetch_portfolio_balance"));
+    // the navigator doesn't describe itself
+    assert!(!manifest.contains("## navigator"));
+
+    // reviews are opt-in
+    assert_eq!(registry.reviewer().unwrap().name, "risk_officer");
+    let registry = registry.without_reviewer();
+    assert!(registry.reviewer().is_none());
+    assert!(registry.specialists().all(|spec| spec.name != "risk_officer"));
+}
+
+#[tokio::test]
+async fn test_specialist_added_by_config() {
+    let mut registry = AgentRegistry::load("agents.json").unwrap();
+    registry.agents.insert(
+        1,
+        AgentSpec {
+            name: "teacher".to_string(),
+            description: "Explains how Starknet protocols work.".to_string(),
+            capabilities: vec!["protocols education".to_string()],
+            tools: vec![],
+            model: "teacher".to_string(),
+            preamble: "defiproman".to_string(),
+            intents: vec![Intent::ProtocolEducation],
+            retrieval: false,
+            reviewer: false,
+        },
+    );
+    let app = test_app_with_registry(vec![], "http://127.0.0.1:9/".parse().unwrap(), registry).await;
+    let navigator = app.backend.app_state.lock().await.agent_state.as_ref().unwrap().navigator.clone();
+    let teacher = &app.models["teacher"];
+
+    teacher.push_text("Impermanent loss is the gap between holding and providing liquidity.");
+    let outcome = navigator.process_prompt("explain impermanent loss", "session".to_string()).await.unwrap();
+
+    assert_eq!(outcome.metadata.agent.as_deref(), Some("teacher"));
+    assert_eq!(teacher.requests().len(), 1);
+    assert!(teacher.requests()[0].tools.is_empty());
+    assert!(app.defiproman_model.requests().is_empty());
+
+    // the navigator is told about every specialist
+    app.navigator_model.push_text("brother defiproman what's up?");
+    app.defiproman_model.push_text("All good.");
+    navigator.process_prompt("hmm not sure", "session".to_string()).await.unwrap();
+    let documents = &app.navigator_model.requests()[0].documents;
+    assert!(documents.iter().any(|document| document.text.contains("## teacher")));
+}
@@ -0,0 +1,149 @@
+use backend_agent::agents::turn::{self, TurnMetadata};
+use backend_agent::llm::mock::ScriptedModel;
+use backend_agent::llm::resilient::{Admission, CircuitBreaker, ResilientModel, RetryPolicy};
+use backend_agent::llm::ModelProfile;
+use rig::{
+    agent::AgentBuilder,
+    completion::{CompletionError, Prompt},
+};
+use std::time::Duration;
+
+fn fast_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_retries: 2,
+        base_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+        breaker_threshold: 3,
+        breaker_cooldown: Duration::from_secs(60),
+    }
+}
+
+#[tokio::test]
+async fn test_retries_transient_errors() {
+    let primary = ScriptedModel::default();
+    primary.push_error("Rate limit reached").push_text("answer");
+    let model = ResilientModel::new("defiproman", "openai/gpt-4o-mini", primary.clone(), fast_policy());
+    let agent = AgentBuilder::new(model).build();
+
+    let (response, metadata) = turn::scope(TurnMetadata::new("session"), agent.prompt("hi")).await;
+
+    assert_eq!(response.unwrap(), "answer");
+    assert_eq!(primary.requests().len(), 2);
+    assert_eq!(metadata.model_calls.len(), 1);
+    assert_eq!(metadata.model_calls[0].model, "openai/gpt-4o-mini");
+    assert_eq!(metadata.model_calls[0].attempts, 2);
+    assert!(!metadata.model_calls[0].fallback);
+}
+
+#[tokio::test]
+async fn test_fails_over_to_fallback() {
+    let primary = ScriptedModel::default();
+    // not retryable, goes straight to the fallback
+    primary.push_error("invalid api key");
+    let fallback = ScriptedModel::default();
+    fallback.push_text("from fallback");
+    let model = ResilientModel::new("navigator", "openai/gpt-4o-mini", primary.clone(), fast_policy())
+        .fallback("openai-compatible/llama3.1", fallback.clone());
+    let agent = AgentBuilder::new(model).build();
+
+    let (response, metadata) = turn::scope(TurnMetadata::new("session"), agent.prompt("hi")).await;
+
+    assert_eq!(response.unwrap(), "from fallback");
+    assert_eq!(primary.requests().len(), 1);
+    assert_eq!(metadata.model_calls[0].agent, "navigator");
+    assert_eq!(metadata.model_calls[0].model, "openai-compatible/llama3.1");
+    assert!(metadata.model_calls[0].fallback);
+}
+
+#[tokio::test]
+async fn test_circuit_breaker_skips_failing_model() {
+    let primary = ScriptedModel::default();
+    primary.push_error("503 unavailable").push_error("503 unavailable").push_error("503 unavailable");
+    let fallback = ScriptedModel::default();
+    fallback.push_text("one").push_text("two");
+    let model = ResilientModel::new("defiproman", "primary", primary.clone(), fast_policy())
+        .fallback("fallback", fallback.clone());
+    let agent = AgentBuilder::new(model.clone()).build();
+
+    assert_eq!(agent.prompt("hi").await.unwrap(), "one");
+    assert!(model.breaker("primary").unwrap().is_open());
+
+    // open circuit: the primary isn't called at all
+    assert_eq!(agent.prompt("hi again").await.unwrap(), "two");
+    assert_eq!(primary.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn test_rejected_requests_dont_open_the_circuit() {
+    let primary = ScriptedModel::default();
+    primary.push_error("invalid api key").push_error("invalid api key").push_error("invalid api key");
+    let model = ResilientModel::new("defiproman", "primary", primary.clone(), fast_policy());
+    let agent = AgentBuilder::new(model.clone()).build();
+
+    for _ in 0..3 {
+        assert!(agent.prompt("hi").await.is_err());
+    }
+    assert!(!model.breaker("primary").unwrap().is_open());
+    assert_eq!(primary.requests().len(), 3);
+}
+
+#[test]
+fn test_half_open_circuit_lets_a_single_trial_through() {
+    let policy = RetryPolicy {
+        breaker_threshold: 1,
+        breaker_cooldown: Duration::ZERO,
+        ..fast_policy()
+    };
+    let unavailable = CompletionError::ProviderError("503 unavailable".to_string());
+    let breaker = CircuitBreaker::default();
+    breaker.on_failure(&policy, Admission::Closed, &unavailable);
+    assert!(breaker.is_open());
+
+    // concurrent requests wait for the trial to finish
+    assert_eq!(breaker.admit(&policy), Some(Admission::Trial));
+    assert_eq!(breaker.admit(&policy), None);
+    breaker.on_failure(&policy, Admission::Trial, &unavailable);
+    assert!(breaker.is_open());
+
+    assert_eq!(breaker.admit(&policy), Some(Admission::Trial));
+    breaker.on_success();
+    assert!(!breaker.is_open());
+    assert_eq!(breaker.admit(&policy), Some(Admission::Closed));
+    assert_eq!(breaker.admit(&policy), Some(Admission::Closed));
+}
+
+#[tokio::test]
+async fn test_all_models_failing_returns_last_error() {
+    let primary = ScriptedModel::default();
+    primary.push_error("bad request");
+    let model = ResilientModel::new("defiproman", "primary", primary, fast_policy());
+    let agent = AgentBuilder::new(model).build();
+
+    let error = agent.prompt("hi").await.unwrap_err();
+    assert!(error.to_string().contains("bad request"));
+}
+
+#[test]
+fn test_backoff_is_bounded() {
+    let policy = RetryPolicy {
+        base_delay: Duration::from_millis(100),
+        max_delay: Duration::from_millis(300),
+        ..RetryPolicy::default()
+    };
+    for attempt in 0..10 {
+        assert!(policy.backoff(attempt) <= Duration::from_millis(300));
+    }
+}
+
+#[test]
+fn test_parse_fallback_entries() {
+    let local = ModelProfile::parse_fallback("ollama:llama3.1@http://localhost:11434/v1").unwrap();
+    assert_eq!(local.id(), "openai-compatible/llama3.1");
+    assert_eq!(local.base_url.as_deref(), Some("http://localhost:11434/v1"));
+
+    let claude = ModelProfile::parse_fallback("anthropic:claude-3-5-haiku-latest").unwrap();
+    assert_eq!(claude.id(), "anthropic/claude-3-5-haiku-latest");
+    assert!(claude.base_url.is_none());
+
+    assert!(ModelProfile::parse_fallback("gpt-4o").is_err());
+}
@@ -0,0 +1,34 @@
+use backend_agent::agents::risk_officer::{RiskLog, RiskReview, Verdict};
+use backend_agent::types::RiskProfile;
+use chrono::Utc;
+
+fn review(draft: &str) -> RiskReview {
+    RiskReview {
+        session_id: "session".to_string(),
+        request_id: "request".to_string(),
+        risk_profile: RiskProfile::default(),
+        draft: draft.to_string(),
+        verdict: Some(Verdict::Approve),
+        warnings: vec![],
+        reason: None,
+        error: None,
+        timestamp: Utc::now(),
+    }
+}
+
+#[test]
+fn test_risk_log_is_persisted_as_jsonl() {
+    let path = std::env::temp_dir().join(format!("risk-{}.jsonl", uuid::Uuid::new_v4()));
+    let log = RiskLog::open(&path).unwrap();
+    log.record(review("Stake STRK"));
+    log.record(review("Lend USDC"));
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+    let reopened = RiskLog::open(&path).unwrap();
+    let drafts: Vec<String> = reopened.reviews().into_iter().map(|review| review.draft).collect();
+    assert_eq!(drafts, ["Stake STRK", "Lend USDC"]);
+
+    std::fs::write(&path, "{\"draft\":\"no verdict\"}\n").unwrap();
+    assert!(RiskLog::open(&path).is_err());
+    std::fs::remove_file(path).unwrap();
+}
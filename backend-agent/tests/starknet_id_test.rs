@@ -0,0 +1,57 @@
+mod common;
+
+use backend_agent::starknet_id::{
+    decode_label, domain_in_prompt, encode_domain, encode_label, is_domain, StarknetId, NAMING_CONTRACT,
+};
+use common::FakeRpc;
+use starknet::core::types::Felt;
+use starknet::macros::selector;
+use std::time::Duration;
+
+#[test]
+fn test_labels_encode_like_the_naming_contract() {
+    assert_eq!(encode_label("ben"), Some(Felt::from(18925u64)));
+    // a trailing `a` is escaped, it would otherwise vanish
+    assert_eq!(encode_label("a"), Some(Felt::from(37u64)));
+    assert_ne!(encode_label("anna"), encode_label("ann"));
+    for label in ["ben", "anna", "a", "vitalik-2024", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"] {
+        assert_eq!(decode_label(encode_label(label).unwrap()).as_deref(), Some(label));
+    }
+    assert_eq!(encode_label("bén"), None);
+    assert_eq!(encode_label(""), None);
+}
+
+#[test]
+fn test_domains_are_found_and_split_into_labels() {
+    assert!(is_domain("alice.stark") && is_domain("Alice.STARK"));
+    assert!(!is_domain(".stark") && !is_domain("0x123") && !is_domain("alice.eth"));
+    assert_eq!(
+        encode_domain("wallet.ben.stark").unwrap(),
+        [encode_label("wallet").unwrap(), Felt::from(18925u64)]
+    );
+    assert!(encode_domain("bad..stark").is_err());
+    assert_eq!(domain_in_prompt("check my wallet Ben.stark, thanks").as_deref(), Some("ben.stark"));
+    assert_eq!(domain_in_prompt("what is stark staking?"), None);
+}
+
+#[tokio::test]
+async fn test_lookups_expire_and_are_bounded() {
+    let rpc = FakeRpc::default().with_result(NAMING_CONTRACT, selector!("domain_to_address"), vec![Felt::from(0x123u64)]);
+    let rpc_url = rpc.serve().await;
+    let calls = || rpc.calls.lock().len();
+
+    // one lookup kept: a second domain evicts the first
+    let starknet_id = StarknetId::new(rpc_url.clone(), NAMING_CONTRACT).with_cache(Duration::from_secs(60), 1);
+    starknet_id.resolve("alice.stark").await.unwrap();
+    starknet_id.resolve("Alice.stark").await.unwrap();
+    assert_eq!(calls(), 1);
+    starknet_id.resolve("bob.stark").await.unwrap();
+    starknet_id.resolve("alice.stark").await.unwrap();
+    assert_eq!(calls(), 3);
+
+    // expired lookups are made again
+    let starknet_id = StarknetId::new(rpc_url, NAMING_CONTRACT).with_cache(Duration::ZERO, 10);
+    starknet_id.resolve("alice.stark").await.unwrap();
+    starknet_id.resolve("alice.stark").await.unwrap();
+    assert_eq!(calls(), 5);
+}
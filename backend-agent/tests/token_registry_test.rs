@@ -0,0 +1,89 @@
+mod common;
+
+use backend_agent::address::ContractAddress;
+use backend_agent::token_registry::{TokenInfo, TokenRegistry, TokenRegistryError};
+use backend_agent::types::TokenCategory;
+use common::FakeRpc;
+use starknet::core::codec::Encode;
+use starknet::core::types::{ByteArray, Felt};
+use starknet::core::utils::cairo_short_string_to_felt;
+use starknet::macros::{felt, selector};
+use starknet::providers::jsonrpc::{HttpTransport, JsonRpcClient};
+
+#[test]
+fn test_registry_file_lists_verified_tokens() {
+    let registry = TokenRegistry::load("tokens.json").unwrap();
+
+    assert_eq!(registry.verified().len(), 22);
+    let usdc = registry.by_symbol("USDC").unwrap();
+    assert_eq!(usdc.decimals, 6);
+    assert_eq!(usdc.coingecko_id.as_deref(), Some("usd-coin"));
+    assert_eq!(usdc.category(), TokenCategory::Stablecoin);
+    assert_eq!(registry.by_symbol("wstETH").unwrap().category(), TokenCategory::LiquidStaking);
+    assert_eq!(registry.by_symbol("WBTC").unwrap().decimals, 8);
+    assert_eq!(registry.category("0x42".parse().unwrap()), TokenCategory::Volatile);
+}
+
+#[test]
+fn test_registry_rejects_duplicate_tokens() {
+    let token = TokenInfo {
+        address: "0x1".parse().unwrap(),
+        symbol: "USDC".to_string(),
+        name: None,
+        decimals: 6,
+        coingecko_id: None,
+        tags: vec![],
+        verified: true,
+    };
+    let fake = TokenInfo {
+        address: "0x2".parse().unwrap(),
+        ..token.clone()
+    };
+
+    assert!(matches!(
+        TokenRegistry::new(vec![token.clone(), fake.clone()]),
+        Err(TokenRegistryError::Duplicate(symbol)) if symbol == "USDC"
+    ));
+    // unverified lookalikes are allowed, they never shadow the verified one
+    let registry = TokenRegistry::new(vec![token.clone(), TokenInfo { verified: false, ..fake }]).unwrap();
+    assert_eq!(registry.by_symbol("USDC").unwrap().address, "0x1".parse().unwrap());
+}
+
+#[tokio::test]
+async fn test_unknown_tokens_are_resolved_on_chain_once() {
+    let token = felt!("0x0123abc");
+    let mut name = vec![];
+    ByteArray::from("Brother Yield Token").encode(&mut name).unwrap();
+    let rpc = FakeRpc::default()
+        .with_result(token, selector!("decimals"), vec![Felt::from(8u8)])
+        .with_result(token, selector!("symbol"), vec![cairo_short_string_to_felt("BYT").unwrap()])
+        .with_result(token, selector!("name"), name);
+    let provider = JsonRpcClient::new(HttpTransport::new(rpc.serve().await));
+    let registry = TokenRegistry::load("tokens.json").unwrap();
+
+    let address = ContractAddress::new(token).unwrap();
+    let resolved = registry.resolve(&provider, address).await.unwrap();
+
+    assert_eq!(resolved.symbol, "BYT");
+    assert_eq!(resolved.name.as_deref(), Some("Brother Yield Token"));
+    assert_eq!(resolved.decimals, 8);
+    assert!(!resolved.verified);
+    assert_eq!(rpc.calls.lock().len(), 3);
+    // cached, and shared with every clone of the registry
+    assert_eq!(registry.clone().resolve(&provider, address).await.unwrap(), resolved);
+    assert_eq!(rpc.calls.lock().len(), 3);
+    assert!(registry.by_symbol("BYT").is_none());
+    assert_eq!(registry.get(address), Some(resolved));
+}
+
+#[tokio::test]
+async fn test_tokens_without_metadata_are_not_resolved() {
+    let rpc = FakeRpc::default().with_failure(felt!("0x0123abc"));
+    let provider = JsonRpcClient::new(HttpTransport::new(rpc.serve().await));
+    let registry = TokenRegistry::load("tokens.json").unwrap();
+
+    let error = registry.resolve(&provider, "0x0123abc".parse().unwrap()).await.unwrap_err();
+
+    assert!(matches!(error, TokenRegistryError::Call("decimals", _, _)));
+    assert!(registry.get("0x123ABC".parse().unwrap()).is_none());
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use backend_agent::agents::turn::{self, TurnMetadata};
+use backend_agent::llm::mock::ScriptedModel;
+use backend_agent::llm::resilient::{ResilientModel, RetryPolicy};
+use backend_agent::usage::{CallKind, ModelPrice, PriceTable, Quota, TokenUsage, UsageLedger};
+use common::test_app;
+use http_body_util::BodyExt;
+use rig::{agent::AgentBuilder, completion::Prompt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tower::ServiceExt;
+
+fn prices() -> PriceTable {
+    PriceTable(HashMap::from([(
+        "test/model".to_string(),
+        ModelPrice { prompt: 1.0, completion: 2.0 },
+    )]))
+}
+
+#[tokio::test]
+async fn test_completion_usage_is_recorded_per_session_and_agent() {
+    let ledger = UsageLedger::new(prices(), Quota::default());
+    let scripted = ScriptedModel::default();
+    scripted.push_text("12345678");
+    let model = ResilientModel::new("defiproman", "test/model", scripted, RetryPolicy::default()).metered(ledger.clone());
+    let agent = AgentBuilder::new(model).build();
+
+    let (response, metadata) = turn::scope(TurnMetadata::new("session-1"), agent.prompt("abcdefgh")).await;
+    assert_eq!(response.unwrap(), "12345678");
+
+    // scripted models report nothing, so 8 characters are estimated as 2 tokens each way
+    assert_eq!(metadata.usage.prompt_tokens, 2);
+    assert_eq!(metadata.usage.completion_tokens, 2);
+    let report = ledger.report();
+    assert_eq!(report.total.requests, 1);
+    assert!((report.by_session["session-1"].cost_usd - 6.0 / 1_000_000.0).abs() < 1e-12);
+    assert_eq!(report.by_agent["defiproman"].requests, 1);
+    assert_eq!(report.by_model["test/model"].requests, 1);
+}
+
+#[tokio::test]
+async fn test_wallet_usage_and_quotas() {
+    let ledger = UsageLedger::new(
+        prices(),
+        Quota {
+            session_usd: Some(1.0),
+            wallet_daily_usd: Some(0.5),
+        },
+    );
+    let usage = TokenUsage {
+        prompt_tokens: 400_000,
+        completion_tokens: 0,
+    };
+
+    let wallet = "0xabc".parse().unwrap();
+    let mut metadata = TurnMetadata::new("session-1");
+    metadata.wallet = Some(wallet);
+    metadata.linked_wallets = vec![wallet];
+    turn::scope(metadata, async { ledger.record("defiproman", "test/model", CallKind::Completion, usage) }).await;
+    assert!(ledger.check_quota("session-1", &[wallet]).is_ok());
+
+    // without a wallet in the turn, spend goes to the linked wallet, which is now over its daily budget
+    let mut metadata = TurnMetadata::new("session-1");
+    metadata.linked_wallets = vec![wallet];
+    turn::scope(metadata, async { ledger.record("navigator", "test/model", CallKind::Completion, usage) }).await;
+    assert_eq!(ledger.report().by_wallet[&wallet].requests, 2);
+    assert!(ledger.check_quota("session-1", &[wallet]).is_err());
+    assert!(ledger.check_quota("session-2", &[wallet]).is_err());
+    assert!(ledger.check_quota("session-2", &[]).is_ok());
+
+    // unknown models are free
+    assert_eq!(prices().cost("other/model", usage), 0.0);
+}
+
+#[tokio::test]
+async fn test_typed_wallet_does_not_burn_its_owners_quota() {
+    let ledger = UsageLedger::new(
+        prices(),
+        Quota {
+            session_usd: None,
+            wallet_daily_usd: Some(0.5),
+        },
+    );
+    let usage = TokenUsage {
+        prompt_tokens: 1_000_000,
+        completion_tokens: 0,
+    };
+
+    // a session that never linked the wallet only types its address
+    let victim = "0xabc".parse().unwrap();
+    let mut metadata = TurnMetadata::new("attacker");
+    metadata.wallet = Some(victim);
+    turn::scope(metadata, async { ledger.record("defiproman", "test/model", CallKind::Completion, usage) }).await;
+
+    let report = ledger.report();
+    assert!(report.by_wallet.is_empty());
+    assert_eq!(report.by_session["attacker"].requests, 1);
+    assert!(ledger.check_quota("owner", &[victim]).is_ok());
+
+    // a linked wallet is charged even when the turn works with another, typed, address
+    let own = "0xdef".parse().unwrap();
+    let mut metadata = TurnMetadata::new("attacker");
+    metadata.wallet = Some(victim);
+    metadata.linked_wallets = vec![own];
+    turn::scope(metadata, async { ledger.record("defiproman", "test/model", CallKind::Completion, usage) }).await;
+    assert!(ledger.check_quota("attacker", &[own]).is_err());
+    assert!(ledger.check_quota("owner", &[victim]).is_ok());
+}
+
+#[tokio::test]
+async fn test_quota_and_admin_endpoint() {
+    let mut app = test_app(vec![], "http://127.0.0.1:9/".parse().unwrap()).await;
+    app.backend.usage = UsageLedger::new(
+        prices(),
+        Quota {
+            session_usd: Some(0.1),
+            wallet_daily_usd: None,
+        },
+    );
+    app.backend.admin_token = Some("secret".to_string());
+    let router = app.backend.router();
+
+    turn::scope(TurnMetadata::new("spender"), async {
+        app.backend.usage.record(
+            "defiproman",
+            "test/model",
+            CallKind::Completion,
+            TokenUsage {
+                prompt_tokens: 200_000,
+                completion_tokens: 0,
+            },
+        )
+    })
+    .await;
+
+    let request = Request::post("/prompt")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({"prompt": "gm", "session_id": "spender"}).to_string()))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(app.defiproman_model.requests().is_empty());
+
+    let unauthorized = router
+        .clone()
+        .oneshot(Request::get("/admin/usage").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(unauthorized.status(), StatusCode::UNAUTHORIZED);
+
+    let response = router
+        .oneshot(
+            Request::get("/admin/usage")
+                .header("authorization", "Bearer secret")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+    assert_eq!(body["by_session"]["spender"]["prompt_tokens"], 200_000);
+}